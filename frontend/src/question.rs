@@ -6,6 +6,8 @@ pub struct QuestionStruct {
     pub id: i32,
     pub title: String,
     pub content: String,
+    /// Sanitized HTML rendered from the content by the backend
+    pub content_html: String,
     pub tags: Option<Vec<String>>,
 }
 
@@ -16,8 +18,8 @@ impl QuestionStruct {
 
         // If a key is passed, get the corresponding question; otherwise, get a random question
         let request = match &key {
-            None => format!("{}/question", host,),
-            Some(ref key) => format!("{}/question/{}", host, key,),
+            None => format!("{}/api/v1/question", host,),
+            Some(ref key) => format!("{}/api/v1/question/{}", host, key,),
         };
         // Match the response and process the question if ok
         let response = http::Request::get(&request).send().await;
@@ -47,7 +49,9 @@ pub fn question(question: &QuestionProps) -> Html {
     html! { <>
         <div class="question">
             <span class="title">{question.title.clone()}</span><br/>
-            <span class="content">{question.content.clone()}</span><br/>
+            <div class="content">
+                {Html::from_html_unchecked(AttrValue::from(question.content_html.clone()))}
+            </div>
         </div>
         <span class="annotation">
             {format!("[id: {}", &question.id)}
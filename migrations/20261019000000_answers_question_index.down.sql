@@ -0,0 +1,2 @@
+-- Add down migration script here
+DROP INDEX IF EXISTS answers_corresponding_question_idx;
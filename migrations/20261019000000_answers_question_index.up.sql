@@ -0,0 +1,2 @@
+-- Add up migration script here
+CREATE INDEX IF NOT EXISTS answers_corresponding_question_idx ON answers (corresponding_question);
@@ -0,0 +1,8 @@
+-- Add up migration script here
+CREATE TABLE IF NOT EXISTS users (
+  id serial PRIMARY KEY,
+  username VARCHAR (255) NOT NULL UNIQUE,
+  password_hash TEXT NOT NULL,
+  is_admin BOOLEAN NOT NULL DEFAULT FALSE,
+  created_on TIMESTAMP NOT NULL DEFAULT NOW()
+);
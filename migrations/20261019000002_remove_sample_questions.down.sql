@@ -0,0 +1,9 @@
+-- Add down migration script here
+INSERT INTO questions (title, content, tags)
+VALUES ('Sample Question 1', 'Sample Content 1', ARRAY['Sample', 'Content']);
+
+INSERT INTO questions (title, content, tags)
+VALUES ('Sample Question 2', 'Sample Content 2', ARRAY['Example', 'Content']);
+
+INSERT INTO questions (title, content, tags)
+VALUES ('Sample Question 3', 'Sample Content 3', ARRAY['FAQ']);
@@ -0,0 +1,10 @@
+-- Add up migration script here
+-- The sample questions used to be inserted by the first migration, they are now loaded on request by
+-- the `demo` seed set instead. Only remove them if they are unchanged and have not been answered.
+DELETE FROM questions
+WHERE (title, content) IN (
+  ('Sample Question 1', 'Sample Content 1'),
+  ('Sample Question 2', 'Sample Content 2'),
+  ('Sample Question 3', 'Sample Content 3')
+)
+AND NOT EXISTS (SELECT 1 FROM answers WHERE answers.corresponding_question = questions.id);
@@ -0,0 +1,16 @@
+-- Add up migration script here
+-- Changes are written here in the same transaction as the write that made them, the relay
+-- publishes the unpublished rows and marks them, so a change is never lost after a commit
+CREATE TABLE IF NOT EXISTS outbox (
+  id bigserial PRIMARY KEY,
+  event_type TEXT NOT NULL,
+  question_id INTEGER NOT NULL,
+  tags TEXT [],
+  payload JSONB NOT NULL,
+  created_on TIMESTAMP NOT NULL DEFAULT NOW(),
+  published_on TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS outbox_unpublished_idx ON outbox (id) WHERE published_on IS NULL;
+CREATE INDEX IF NOT EXISTS outbox_published_idx ON outbox (published_on)
+  WHERE published_on IS NOT NULL;
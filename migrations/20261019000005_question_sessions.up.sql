@@ -0,0 +1,11 @@
+-- Add up migration script here
+-- Questions already served to each session of the random question route, so that a session does
+-- not see a question twice until it has seen every one
+CREATE TABLE IF NOT EXISTS question_sessions (
+  session TEXT NOT NULL,
+  question_id INTEGER NOT NULL REFERENCES questions(id) ON DELETE CASCADE,
+  served_on TIMESTAMP NOT NULL DEFAULT NOW(),
+  PRIMARY KEY (session, question_id)
+);
+
+CREATE INDEX IF NOT EXISTS question_sessions_served_idx ON question_sessions (served_on);
@@ -0,0 +1,14 @@
+-- Add up migration script here
+-- Spaced repetition state of each question a user has studied, questions without a row are new
+CREATE TABLE IF NOT EXISTS study_reviews (
+  user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+  question_id INTEGER NOT NULL REFERENCES questions(id) ON DELETE CASCADE,
+  ease DOUBLE PRECISION NOT NULL,
+  interval_days INTEGER NOT NULL,
+  repetitions INTEGER NOT NULL,
+  due_on TIMESTAMP NOT NULL,
+  last_reviewed_on TIMESTAMP NOT NULL DEFAULT NOW(),
+  PRIMARY KEY (user_id, question_id)
+);
+
+CREATE INDEX IF NOT EXISTS study_reviews_due_idx ON study_reviews (user_id, due_on);
@@ -0,0 +1,3 @@
+-- Add down migration script here
+DROP TABLE IF EXISTS quiz_questions;
+DROP TABLE IF EXISTS quizzes;
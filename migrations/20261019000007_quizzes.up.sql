@@ -0,0 +1,25 @@
+-- Add up migration script here
+-- Timed quizzes, each is reached with its random token
+CREATE TABLE IF NOT EXISTS quizzes (
+  token TEXT PRIMARY KEY,
+  tag TEXT,
+  threshold DOUBLE PRECISION NOT NULL,
+  created_on TIMESTAMP NOT NULL DEFAULT NOW(),
+  expires_on TIMESTAMP NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS quizzes_expires_idx ON quizzes (expires_on);
+
+-- Questions drawn for each quiz in the order they are asked, with the submitted answer and its
+-- score once there is one
+CREATE TABLE IF NOT EXISTS quiz_questions (
+  token TEXT NOT NULL REFERENCES quizzes(token) ON DELETE CASCADE,
+  position INTEGER NOT NULL,
+  question_id INTEGER NOT NULL REFERENCES questions(id) ON DELETE CASCADE,
+  answer TEXT,
+  similarity DOUBLE PRECISION,
+  correct BOOLEAN,
+  answered_on TIMESTAMP,
+  PRIMARY KEY (token, position),
+  UNIQUE (token, question_id)
+);
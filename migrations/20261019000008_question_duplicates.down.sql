@@ -0,0 +1,4 @@
+-- Add down migration script here
+DROP TABLE IF EXISTS question_duplicates;
+DROP INDEX IF EXISTS questions_title_trgm_idx;
+DROP EXTENSION IF EXISTS pg_trgm;
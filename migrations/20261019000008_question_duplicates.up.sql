@@ -0,0 +1,15 @@
+-- Add up migration script here
+-- Trigram index so that questions with a similar title can be found without reading every one
+CREATE EXTENSION IF NOT EXISTS pg_trgm;
+
+CREATE INDEX IF NOT EXISTS questions_title_trgm_idx ON questions USING GIN (title gin_trgm_ops);
+
+-- Questions marked as duplicates, each points straight at the question it duplicates, which is
+-- never a duplicate itself
+CREATE TABLE IF NOT EXISTS question_duplicates (
+  question_id INTEGER PRIMARY KEY REFERENCES questions(id) ON DELETE CASCADE,
+  duplicate_of INTEGER NOT NULL REFERENCES questions(id) ON DELETE CASCADE,
+  marked_on TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS question_duplicates_of_idx ON question_duplicates (duplicate_of);
@@ -0,0 +1,17 @@
+-- Add up migration script here
+-- Files uploaded to a question or an answer, the contents are kept in the file store under
+-- `storage_key` and are deleted by the server along with the question
+CREATE TABLE IF NOT EXISTS attachments (
+  id SERIAL PRIMARY KEY,
+  question_id INTEGER REFERENCES questions(id) ON DELETE CASCADE,
+  answer_id INTEGER REFERENCES answers(id) ON DELETE CASCADE,
+  filename TEXT NOT NULL,
+  content_type TEXT NOT NULL,
+  size BIGINT NOT NULL,
+  storage_key TEXT NOT NULL UNIQUE,
+  created_on TIMESTAMP NOT NULL DEFAULT NOW(),
+  CHECK ((question_id IS NULL) <> (answer_id IS NULL))
+);
+
+CREATE INDEX IF NOT EXISTS attachments_question_idx ON attachments (question_id);
+CREATE INDEX IF NOT EXISTS attachments_answer_idx ON attachments (answer_id);
@@ -0,0 +1,3 @@
+-- Add up migration script here
+-- Trigram index so that questions reposted under another title can be found by their content
+CREATE INDEX IF NOT EXISTS questions_content_trgm_idx ON questions USING GIN (content gin_trgm_ops);
@@ -0,0 +1,6 @@
+-- Add up migration script here
+-- When each question was last written, so that incremental exports pick up edited questions
+ALTER TABLE questions ADD COLUMN IF NOT EXISTS updated_on TIMESTAMP;
+UPDATE questions SET updated_on = created_on WHERE updated_on IS NULL;
+ALTER TABLE questions ALTER COLUMN updated_on SET DEFAULT NOW();
+ALTER TABLE questions ALTER COLUMN updated_on SET NOT NULL;
@@ -1,16 +1,50 @@
 use crate::*;
 
 /// Answer struct used to store questions in the database
-#[derive(Debug, Serialize, Clone, Deserialize)]
+/// The HTTP routes send it to clients as a `RenderedAnswer`
+#[derive(Debug, Serialize, Clone, Deserialize, SimpleObject)]
+#[graphql(complex)]
 pub struct Answer {
     pub id: i32,
     pub content: String,
     pub corresponding_question: i32,
 }
 
+/// Answer struct as it is sent to clients, `content` is CommonMark and `content_html` is the
+/// sanitized HTML it renders to
+#[derive(Debug, Serialize, Clone, ToSchema)]
+#[schema(as = Answer)]
+pub struct RenderedAnswer {
+    pub id: i32,
+    pub content: String,
+    pub content_html: String,
+    pub corresponding_question: i32,
+}
+
+impl From<Answer> for RenderedAnswer {
+    fn from(answer: Answer) -> Self {
+        RenderedAnswer {
+            id: answer.id,
+            content_html: render_markdown(&answer.content),
+            content: answer.content,
+            corresponding_question: answer.corresponding_question,
+        }
+    }
+}
+
 /// New answer struct used to create and update questions in the database
-#[derive(Debug, Serialize, Clone, Deserialize)]
+#[derive(Debug, Serialize, Clone, Deserialize, ToSchema, InputObject)]
 pub struct NewAnswer {
     pub content: String,
     pub corresponding_question: i32,
 }
+
+impl NewAnswer {
+    /// Check that the content is within the length limit
+    pub fn validate(&self) -> Result<(), Err> {
+        if self.content.chars().count() > MAX_CONTENT_LENGTH {
+            return Err(Err::ContentTooLong);
+        }
+        Ok(())
+    }
+}
@@ -1,5 +1,26 @@
 use crate::*;
 
+/// Maximum number of characters in a question title, matching the size of the `title` column
+pub const MAX_TITLE_LENGTH: usize = 255;
+
+/// Maximum number of characters in the content of a question or answer
+pub const MAX_CONTENT_LENGTH: usize = 10_000;
+
+/// Number of answers embedded in a question with `include=answers` if no `answer_limit` is given
+const DEFAULT_ANSWER_LIMIT: i64 = 20;
+
+/// Largest `answer_limit` that can be asked for
+const MAX_ANSWER_LIMIT: i64 = 100;
+
+/// Longest session the random question route accepts
+const MAX_SESSION_LENGTH: usize = 64;
+
+/// Number of candidate duplicates returned when a new question is rejected
+const MAX_DUPLICATE_CANDIDATES: i64 = 5;
+
+/// Default similarity from 0 to 1 at which a new question is rejected as a duplicate
+const DEFAULT_DUPLICATE_THRESHOLD: f64 = 0.6;
+
 // Questions Table Routes
 
 /// Pagination struct that is being extracted from the query params
@@ -16,6 +37,42 @@ pub enum Err {
     MissingParameters,
     ParseInt(std::num::ParseIntError),
     QuestionNotFound,
+    DatabaseUnavailable,
+    MigrationsPending,
+    RateLimited,
+    TitleTooLong,
+    ContentTooLong,
+    ParseBool(std::str::ParseBoolError),
+    UnsupportedFormat(String),
+    MissingColumn(&'static str),
+    InvalidFile(String),
+    InvalidDate(String),
+    UnknownSeedSet(String),
+    SeedingDisabled,
+    InvalidUrl(String),
+    PrivateWebhookUrl(String),
+    UnknownEventType(String),
+    WebhookNotFound,
+    DeliveryNotFound,
+    UnknownInclude(String),
+    InvalidAnswerLimit,
+    InvalidSession,
+    Unauthorized,
+    AdminRequired,
+    NothingToStudy,
+    InvalidQuality,
+    InvalidQuiz(String),
+    QuizNotFound,
+    QuizExpired,
+    AlreadyAnswered,
+    DuplicateQuestion(i32),
+    SelfDuplicate,
+    InvalidRelatedLimit,
+    AnswerNotFound,
+    MissingFile,
+    FileTooLarge(usize),
+    UnsupportedFileType,
+    AttachmentNotFound,
 }
 
 /// Implements error messages for the custom Error struct
@@ -27,10 +84,78 @@ impl std::fmt::Display for Err {
             }
             Err::MissingParameters => write!(f, "Missing parameter"),
             Err::QuestionNotFound => write!(f, "Question not found"),
+            Err::DatabaseUnavailable => write!(f, "Database unavailable"),
+            Err::MigrationsPending => write!(f, "Migrations pending"),
+            Err::RateLimited => write!(f, "Too many requests"),
+            Err::TitleTooLong => {
+                write!(f, "Title is longer than {} characters", MAX_TITLE_LENGTH)
+            }
+            Err::ContentTooLong => {
+                write!(
+                    f,
+                    "Content is longer than {} characters",
+                    MAX_CONTENT_LENGTH
+                )
+            }
+            Err::ParseBool(ref err) => {
+                write!(f, "Cannot parse parameter: {}", err)
+            }
+            Err::UnsupportedFormat(ref format) => write!(f, "Unsupported format: {}", format),
+            Err::MissingColumn(column) => write!(f, "Missing column: {}", column),
+            Err::InvalidFile(ref err) => write!(f, "Invalid file: {}", err),
+            Err::InvalidDate(ref date) => write!(f, "Invalid date: {}", date),
+            Err::UnknownSeedSet(ref set) => write!(f, "Unknown seed set: {}", set),
+            Err::SeedingDisabled => write!(f, "Seeding is disabled"),
+            Err::InvalidUrl(ref url) => write!(f, "Invalid url: {}", url),
+            Err::PrivateWebhookUrl(ref url) => write!(
+                f,
+                "Webhook url points at a private address, add its host to WEBHOOK_ALLOWED_HOSTS to allow it: {}",
+                url
+            ),
+            Err::UnknownEventType(ref kind) => write!(f, "Unknown event type: {}", kind),
+            Err::WebhookNotFound => write!(f, "Webhook not found"),
+            Err::DeliveryNotFound => write!(f, "Dead letter not found"),
+            Err::UnknownInclude(ref include) => write!(f, "Unknown include: {}", include),
+            Err::InvalidAnswerLimit => {
+                write!(f, "answer_limit must be between 1 and {}", MAX_ANSWER_LIMIT)
+            }
+            Err::InvalidSession => write!(
+                f,
+                "session must be 1 to {} letters, digits, '-' or '_'",
+                MAX_SESSION_LENGTH
+            ),
+            Err::Unauthorized => write!(f, "Missing or wrong credentials"),
+            Err::AdminRequired => write!(f, "Only admins can do this"),
+            Err::NothingToStudy => write!(f, "Nothing to study"),
+            Err::InvalidQuality => write!(f, "quality must be between 0 and 5"),
+            Err::InvalidQuiz(ref reason) => write!(f, "Invalid quiz: {}", reason),
+            Err::QuizNotFound => write!(f, "Quiz not found or the question is not in it"),
+            Err::QuizExpired => write!(f, "The time limit of the quiz has passed"),
+            Err::AlreadyAnswered => write!(f, "The question has already been answered"),
+            Err::DuplicateQuestion(id) => write!(
+                f,
+                "Question looks like a duplicate of question {}, pass force=true to add it anyway",
+                id
+            ),
+            Err::SelfDuplicate => write!(f, "A question cannot be a duplicate of itself"),
+            Err::InvalidRelatedLimit => {
+                write!(f, "limit must be between 1 and {}", MAX_RELATED_LIMIT)
+            }
+            Err::AnswerNotFound => write!(f, "Answer not found"),
+            Err::MissingFile => write!(f, "Missing file field"),
+            Err::FileTooLarge(size) => write!(f, "File is larger than {} bytes", size),
+            Err::UnsupportedFileType => write!(
+                f,
+                "Unsupported file type, only images, PDFs and text files are allowed"
+            ),
+            Err::AttachmentNotFound => write!(f, "Attachment not found"),
         }
     }
 }
 
+/// Allows the custom Error struct to be returned as a boxed error, as the admin tool does
+impl std::error::Error for Err {}
+
 /// Extract query parameters from the `questions` route
 fn extract_pagination(params: HashMap<String, String>) -> Result<Pagination, Err> {
     // Checks to see if the parameters passed contains the required fields
@@ -59,6 +184,20 @@ fn extract_pagination(params: HashMap<String, String>) -> Result<Pagination, Err
 /// # Example query
 /// GET requests to this route can have a pagination attached so we just return the questions we need
 /// `/questions?limit=3&offset=1`
+#[utoipa::path(
+    get,
+    path = "/questions",
+    tag = "questions",
+    params(
+        ("limit" = Option<i32>, Query, description = "Maximum number of questions to return"),
+        ("offset" = Option<i32>, Query, description = "Number of questions to skip"),
+    ),
+    responses(
+        (status = 200, description = "Questions found", body = [Question]),
+        (status = 400, description = "Database error", body = String),
+        (status = 416, description = "Pagination parameters are missing or invalid", body = String),
+    )
+)]
 pub async fn get_questions(
     State(store): State<Arc<RwLock<Store>>>,
     Query(params): Query<HashMap<String, String>>,
@@ -93,13 +232,13 @@ pub async fn get_questions(
         }
     }
     // Get the questions by passing the pagination object
-    let res: Vec<Question> = match store
+    let res: Vec<RenderedQuestion> = match store
         .read()
         .await
         .get_questions(pagination.limit, pagination.offset)
         .await
     {
-        Ok(res) => res,
+        Ok(res) => res.into_iter().map(RenderedQuestion::from).collect(),
         Err(e) => return (StatusCode::BAD_REQUEST, e.to_string()).into_response(),
     };
     let res = &res;
@@ -108,38 +247,207 @@ pub async fn get_questions(
 
 // CREATE OPERATION
 
+/// Similarity from 0 to 1 at which a new question is rejected as a duplicate, read from
+/// `DUPLICATE_THRESHOLD`
+fn duplicate_threshold() -> f64 {
+    std::env::var("DUPLICATE_THRESHOLD")
+        .ok()
+        .and_then(|threshold| threshold.parse::<f64>().ok())
+        .unwrap_or(DEFAULT_DUPLICATE_THRESHOLD)
+}
+
+/// Find the questions most similar to a new question, most similar first
+pub async fn find_duplicates(
+    store: &Store,
+    new_question: &NewQuestion,
+) -> Result<Vec<DuplicateCandidate>, sqlx::Error> {
+    store
+        .find_duplicate_questions(
+            &new_question.title,
+            &new_question.content,
+            MAX_DUPLICATE_CANDIDATES,
+        )
+        .await
+}
+
+/// Find the questions a new question looks like a duplicate of
+/// Returns the most similar questions if the closest one reaches the threshold, otherwise none
+pub async fn find_blocking_duplicates(
+    store: &Store,
+    new_question: &NewQuestion,
+) -> Result<Vec<DuplicateCandidate>, sqlx::Error> {
+    let candidates = find_duplicates(store, new_question).await?;
+    Ok(blocking_duplicates(candidates, duplicate_threshold()))
+}
+
+/// Keep the candidates, most similar first, if the closest one reaches the threshold
+fn blocking_duplicates(
+    candidates: Vec<DuplicateCandidate>,
+    threshold: f64,
+) -> Vec<DuplicateCandidate> {
+    match candidates.first() {
+        Some(closest) if closest.similarity >= threshold => candidates,
+        _ => Vec::new(),
+    }
+}
+
+/// Keep the candidates that are below the threshold, these are shown with a question that was
+/// added without blocking it
+fn near_duplicates(candidates: Vec<DuplicateCandidate>, threshold: f64) -> Vec<DuplicateCandidate> {
+    candidates
+        .into_iter()
+        .filter(|candidate| candidate.similarity < threshold)
+        .collect()
+}
+
 /// Create a new question in the `questions` based on a json body specifying the new data in the question
 /// # Example query
 /// POST requests to this route have an json body attached so we just create the question we need
-/// `/question`
+/// A question that looks like a duplicate of an existing one is rejected with the most similar
+/// questions, unless `force=true` is passed
+/// The id of the new question is returned with the similar questions that did not block it
+/// `/question?force=false`
 /// `{
 ///     "title": "New Question",
 ///     "content": "This is the contents of the new question",
 ///     "tags": ["sample", "tags", "example"]
 /// }`
+#[utoipa::path(
+    post,
+    path = "/question",
+    tag = "questions",
+    params(("force" = Option<bool>, Query, description = "Add the question even if it looks like a duplicate")),
+    request_body = NewQuestion,
+    responses(
+        (status = 201, description = "Question added", body = AddedQuestion),
+        (status = 400, description = "Question is invalid or could not be added", body = String),
+        (status = 409, description = "Question looks like a duplicate", body = DuplicateConflict),
+    )
+)]
 pub async fn add_question(
     State(store): State<Arc<RwLock<Store>>>,
+    Query(params): Query<HashMap<String, String>>,
     Json(new_question): Json<NewQuestion>,
 ) -> Response {
+    // Reject questions that are too long before touching the database
+    if let Err(e) = new_question.validate() {
+        return (StatusCode::BAD_REQUEST, e.to_string()).into_response();
+    }
+    let force = match params.get("force") {
+        Some(force) => match force.parse::<bool>() {
+            Ok(force) => force,
+            Err(e) => {
+                return (StatusCode::BAD_REQUEST, Err::ParseBool(e).to_string()).into_response()
+            }
+        },
+        None => false,
+    };
+
+    // Look for similar questions on every create, the near matches are returned with the new
+    // question
+    let candidates = match find_duplicates(&*store.read().await, &new_question).await {
+        Ok(candidates) => candidates,
+        Err(e) => return (StatusCode::BAD_REQUEST, e.to_string()).into_response(),
+    };
+    let threshold = duplicate_threshold();
+
+    // Reject questions that look like one that is already there
+    if !force {
+        let candidates = blocking_duplicates(candidates.clone(), threshold);
+        if let Some(closest) = candidates.first() {
+            let conflict = DuplicateConflict {
+                message: Err::DuplicateQuestion(closest.question.id).to_string(),
+                candidates,
+            };
+            return (StatusCode::CONFLICT, Json(conflict)).into_response();
+        }
+    }
+
     match store.write().await.add_question(new_question).await {
-        Ok(_) => (StatusCode::CREATED, "Question added".to_string()).into_response(),
+        Ok(question) => {
+            let added = AddedQuestion {
+                id: question.id,
+                candidates: near_duplicates(candidates, threshold),
+            };
+            (StatusCode::CREATED, Json(added)).into_response()
+        }
         Err(e) => (StatusCode::BAD_REQUEST, e.to_string()).into_response(),
     }
 }
 
 // READ OPERATION
 
+/// Extract the number of answers to embed from the query params of the `question` route
+/// Returns `None` unless `include=answers` is passed
+fn extract_answer_limit(params: &HashMap<String, String>) -> Result<Option<i64>, Err> {
+    // `include` is a comma separated list so that more relations can be added later
+    let mut answers = false;
+    for include in params.get("include").into_iter().flat_map(|i| i.split(',')) {
+        match include.trim() {
+            "answers" => answers = true,
+            "" => {}
+            other => return Err(Err::UnknownInclude(other.to_owned())),
+        }
+    }
+    if !answers {
+        return Ok(None);
+    }
+
+    let limit = match params.get("answer_limit") {
+        Some(limit) => limit.parse::<i64>().map_err(Err::ParseInt)?,
+        None => DEFAULT_ANSWER_LIMIT,
+    };
+    if !(1..=MAX_ANSWER_LIMIT).contains(&limit) {
+        return Err(Err::InvalidAnswerLimit);
+    }
+    Ok(Some(limit))
+}
+
 /// Fetch a specific question from the `questions` route based on the id passed in the route
 /// # Example query
 /// GET requests to this route have an id attached so we just return the question we need
-/// `/question/3`
+/// With `include=answers` the question is returned with its oldest answers embedded, up to
+/// `answer_limit` of them, and the total number of answers
+/// `/question/3?include=answers&answer_limit=10`
+#[utoipa::path(
+    get,
+    path = "/question/{id}",
+    tag = "questions",
+    params(
+        ("id" = i32, Path, description = "Question id"),
+        ("include" = Option<String>, Query, description = "`answers` to embed the answers"),
+        ("answer_limit" = Option<i64>, Query, description = "Maximum number of answers to embed, defaults to 20"),
+    ),
+    responses(
+        (status = 200, description = "Question found, `answers` and `answer_count` are only included with `include=answers`", body = QuestionWithAnswers),
+        (status = 400, description = "Parameters are invalid or database error", body = String),
+        (status = 404, description = "Question not found", body = String),
+    )
+)]
 pub async fn get_question(
     State(store): State<Arc<RwLock<Store>>>,
     Path(id): Path<i32>,
+    Query(params): Query<HashMap<String, String>>,
 ) -> Response {
-    // Get the question by passing the id
-    match store.read().await.get_question(&id).await {
-        Ok(q) => (StatusCode::OK, Json(q)).into_response(),
+    let answer_limit = match extract_answer_limit(&params) {
+        Ok(limit) => limit,
+        Err(e) => return (StatusCode::BAD_REQUEST, e.to_string()).into_response(),
+    };
+
+    // Get the question by passing the id, with its answers if they were asked for
+    let store = store.read().await;
+    let res = match answer_limit {
+        Some(limit) => store
+            .get_question_with_answers(&id, limit)
+            .await
+            .map(|q| Json(q).into_response()),
+        None => store
+            .get_question(&id)
+            .await
+            .map(|q| Json(RenderedQuestion::from(q)).into_response()),
+    };
+    match res {
+        Ok(res) => res,
         Err(sqlx::Error::RowNotFound) => {
             (StatusCode::NOT_FOUND, Err::QuestionNotFound.to_string()).into_response()
         }
@@ -147,14 +455,87 @@ pub async fn get_question(
     }
 }
 
+/// Extract the filter and the session from the query params of the random `question` route
+fn extract_random_filter(
+    params: &HashMap<String, String>,
+) -> Result<(RandomFilter, Option<String>), Err> {
+    let unanswered = match params.get("unanswered") {
+        Some(unanswered) => unanswered.parse::<bool>().map_err(Err::ParseBool)?,
+        None => false,
+    };
+    // `unanswered=false` leaves the answers unfiltered rather than asking for answered questions
+    let answered = unanswered.then_some(false);
+    let exclude = match params.get("exclude") {
+        Some(exclude) => exclude
+            .split(',')
+            .map(str::trim)
+            .filter(|id| !id.is_empty())
+            .map(|id| id.parse::<i32>().map_err(Err::ParseInt))
+            .collect::<Result<Vec<_>, _>>()?,
+        None => Vec::new(),
+    };
+
+    // Sessions are chosen by the client, so keep them short and printable
+    let session = match params.get("session") {
+        Some(session)
+            if (1..=MAX_SESSION_LENGTH).contains(&session.len())
+                && session
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') =>
+        {
+            Some(session.clone())
+        }
+        Some(_) => return Err(Err::InvalidSession),
+        None => None,
+    };
+
+    let filter = RandomFilter {
+        tag: params.get("tag").filter(|tag| !tag.is_empty()).cloned(),
+        answered,
+        exclude,
+    };
+    Ok((filter, session))
+}
+
 /// Fetch a random question from the `questions` route
 /// # Example query
-/// GET requests to this route have an id attached so we just return the question we need
-/// `/question`
-pub async fn get_random_question(State(store): State<Arc<RwLock<Store>>>) -> Response {
-    // Get the question by passing the id
-    match store.read().await.get_random_question().await {
-        Ok(q) => (StatusCode::OK, Json(q)).into_response(),
+/// GET requests to this route return a random question that passes the filters
+/// With a `session` picked by the client, no question is returned twice in that session until
+/// every question that passes the filters has been, and the same session gets the same order
+/// `/question?tag=rust&unanswered=true&exclude=1,2&session=3f9a2c`
+#[utoipa::path(
+    get,
+    path = "/question",
+    tag = "questions",
+    params(
+        ("tag" = Option<String>, Query, description = "Only questions with this tag"),
+        ("unanswered" = Option<bool>, Query, description = "Only questions without answers"),
+        ("exclude" = Option<String>, Query, description = "Comma separated ids of questions to leave out"),
+        ("session" = Option<String>, Query, description = "Up to 64 letters, digits, `-` and `_` to not repeat questions"),
+    ),
+    responses(
+        (status = 200, description = "Random question", body = Question),
+        (status = 400, description = "Parameters are invalid or database error", body = String),
+        (status = 404, description = "There are no questions that pass the filters", body = String),
+    )
+)]
+pub async fn get_random_question(
+    State(store): State<Arc<RwLock<Store>>>,
+    Query(params): Query<HashMap<String, String>>,
+) -> Response {
+    let (filter, session) = match extract_random_filter(&params) {
+        Ok(extracted) => extracted,
+        Err(e) => return (StatusCode::BAD_REQUEST, e.to_string()).into_response(),
+    };
+
+    // Get a question that passes the filter
+    match store
+        .read()
+        .await
+        .get_random_question(&filter, session.as_deref())
+        .await
+    {
+        Ok(q) => (StatusCode::OK, Json(RenderedQuestion::from(q))).into_response(),
         Err(sqlx::Error::RowNotFound) => {
             (StatusCode::NOT_FOUND, Err::QuestionNotFound.to_string()).into_response()
         }
@@ -164,6 +545,49 @@ pub async fn get_random_question(State(store): State<Arc<RwLock<Store>>>) -> Res
 
 // UPDATE OPERATION
 
+/// Mark a question as a duplicate of another from the `question/mark-duplicate` route
+/// # Example query
+/// POST requests to this route mark the first question as a duplicate of the second, marked
+/// questions are no longer offered as candidate duplicates of new questions
+/// If the second question is itself a duplicate, the first is marked as a duplicate of the
+/// question the second duplicates
+/// `/question/2/mark-duplicate/1`
+#[utoipa::path(
+    post,
+    path = "/question/{id}/mark-duplicate/{other}",
+    tag = "questions",
+    params(
+        ("id" = i32, Path, description = "Id of the duplicate question"),
+        ("other" = i32, Path, description = "Id of the question it duplicates"),
+    ),
+    responses(
+        (status = 200, description = "Question marked as a duplicate", body = Duplicate),
+        (status = 400, description = "Both ids are the same or database error", body = String),
+        (status = 404, description = "Question not found", body = String),
+    )
+)]
+pub async fn mark_duplicate_question(
+    State(store): State<Arc<RwLock<Store>>>,
+    Path((id, other)): Path<(i32, i32)>,
+) -> Response {
+    if id == other {
+        return (StatusCode::BAD_REQUEST, Err::SelfDuplicate.to_string()).into_response();
+    }
+
+    match store
+        .write()
+        .await
+        .mark_duplicate_question(&id, &other)
+        .await
+    {
+        Ok(duplicate) => (StatusCode::OK, Json(duplicate)).into_response(),
+        Err(sqlx::Error::RowNotFound) => {
+            (StatusCode::NOT_FOUND, Err::QuestionNotFound.to_string()).into_response()
+        }
+        Err(e) => (StatusCode::BAD_REQUEST, e.to_string()).into_response(),
+    }
+}
+
 /// Update a specific question from the `questions` route based on the id passed in the route
 /// and a json body specifying the new data in the question
 /// # Example query
@@ -175,11 +599,27 @@ pub async fn get_random_question(State(store): State<Arc<RwLock<Store>>>) -> Res
 ///     "content": "This is the new contents of the question",
 ///     "tags": ["sample", "tags", "example"]
 /// }`
+#[utoipa::path(
+    put,
+    path = "/question/{id}",
+    tag = "questions",
+    params(("id" = i32, Path, description = "Question id")),
+    request_body = NewQuestion,
+    responses(
+        (status = 200, description = "Question updated", body = String),
+        (status = 400, description = "Question is invalid or could not be updated", body = String),
+    )
+)]
 pub async fn update_question(
     State(store): State<Arc<RwLock<Store>>>,
     Path(id): Path<i32>,
     Json(new_question): Json<NewQuestion>,
 ) -> Response {
+    // Reject questions that are too long before touching the database
+    if let Err(e) = new_question.validate() {
+        return (StatusCode::BAD_REQUEST, e.to_string()).into_response();
+    }
+
     // Update the question by passing the id
     match store.write().await.update_question(&id, new_question).await {
         Ok(_) => (StatusCode::OK, "Question updated".to_string()).into_response(),
@@ -193,6 +633,16 @@ pub async fn update_question(
 /// # Example query
 /// DELETE requests to this route have an id attached so we just delete the question we need
 /// `/question/3`
+#[utoipa::path(
+    delete,
+    path = "/question/{id}",
+    tag = "questions",
+    params(("id" = i32, Path, description = "Question id")),
+    responses(
+        (status = 200, description = "Question deleted", body = String),
+        (status = 400, description = "Database error", body = String),
+    )
+)]
 pub async fn delete_question(
     State(store): State<Arc<RwLock<Store>>>,
     Path(id): Path<i32>,
@@ -210,6 +660,20 @@ pub async fn delete_question(
 /// # Example query
 /// GET requests to this route can have a pagination attached so we just return the answers we need
 /// `/answers?limit=3&offset=1`
+#[utoipa::path(
+    get,
+    path = "/answers",
+    tag = "answers",
+    params(
+        ("limit" = Option<i32>, Query, description = "Maximum number of answers to return"),
+        ("offset" = Option<i32>, Query, description = "Number of answers to skip"),
+    ),
+    responses(
+        (status = 200, description = "Answers found", body = [Answer]),
+        (status = 400, description = "Database error", body = String),
+        (status = 416, description = "Pagination parameters are missing or invalid", body = String),
+    )
+)]
 pub async fn get_answers(
     State(store): State<Arc<RwLock<Store>>>,
     Query(params): Query<HashMap<String, String>>,
@@ -244,13 +708,13 @@ pub async fn get_answers(
         }
     }
     // Get the answers by passing the pagination object
-    let res: Vec<Answer> = match store
+    let res: Vec<RenderedAnswer> = match store
         .read()
         .await
         .get_answers(pagination.limit, pagination.offset)
         .await
     {
-        Ok(res) => res,
+        Ok(res) => res.into_iter().map(RenderedAnswer::from).collect(),
         Err(e) => return (StatusCode::BAD_REQUEST, e.to_string()).into_response(),
     };
     let res = &res;
@@ -265,12 +729,62 @@ pub async fn get_answers(
 ///     "content": "This is the answer to question 1",
 ///     "corresponding_question": 1
 /// }`
+#[utoipa::path(
+    post,
+    path = "/answer",
+    tag = "answers",
+    request_body = NewAnswer,
+    responses(
+        (status = 201, description = "Answer added", body = String),
+        (status = 400, description = "Answer is invalid or could not be added", body = String),
+    )
+)]
 pub async fn add_answer(
     State(store): State<Arc<RwLock<Store>>>,
     Json(new_answer): Json<NewAnswer>,
 ) -> Response {
+    // Reject answers that are too long before touching the database
+    if let Err(e) = new_answer.validate() {
+        return (StatusCode::BAD_REQUEST, e.to_string()).into_response();
+    }
+
     match store.write().await.add_answer(new_answer).await {
         Ok(_) => (StatusCode::CREATED, "Answer added".to_string()).into_response(),
         Err(e) => (StatusCode::BAD_REQUEST, e.to_string()).into_response(),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn candidate(id: i32, similarity: f64) -> DuplicateCandidate {
+        DuplicateCandidate {
+            question: Question {
+                id,
+                title: String::new(),
+                content: String::new(),
+                tags: None,
+            }
+            .into(),
+            similarity,
+        }
+    }
+
+    #[test]
+    fn only_the_closest_candidate_has_to_reach_the_threshold() {
+        let blocking = blocking_duplicates(vec![candidate(1, 0.8), candidate(2, 0.3)], 0.8);
+        let ids: Vec<i32> = blocking.iter().map(|c| c.question.id).collect();
+        assert_eq!(ids, [1, 2]);
+
+        assert!(blocking_duplicates(vec![candidate(1, 0.79), candidate(2, 0.3)], 0.8).is_empty());
+        assert!(blocking_duplicates(Vec::new(), 0.0).is_empty());
+    }
+
+    #[test]
+    fn near_duplicates_are_the_candidates_below_the_threshold() {
+        let near = near_duplicates(vec![candidate(1, 0.8), candidate(2, 0.79)], 0.8);
+        let ids: Vec<i32> = near.iter().map(|c| c.question.id).collect();
+        assert_eq!(ids, [2]);
+    }
+}
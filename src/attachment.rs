@@ -0,0 +1,466 @@
+use crate::*;
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use axum::extract::Multipart;
+use std::path::PathBuf;
+
+/// Default maximum size of an attachment in bytes
+const DEFAULT_MAX_ATTACHMENT_SIZE: usize = 10 * 1024 * 1024;
+
+/// Room left in the request body for the multipart boundaries and headers around the file
+const MULTIPART_OVERHEAD: usize = 64 * 1024;
+
+/// Default directory attachments are stored in
+const DEFAULT_ATTACHMENT_DIR: &str = "attachments";
+
+/// Longest file name kept for an attachment
+const MAX_FILENAME_LENGTH: usize = 255;
+
+/// Maximum size of an attachment in bytes, read from `MAX_ATTACHMENT_SIZE`
+pub fn max_attachment_size() -> usize {
+    std::env::var("MAX_ATTACHMENT_SIZE")
+        .ok()
+        .and_then(|size| size.parse::<usize>().ok())
+        .unwrap_or(DEFAULT_MAX_ATTACHMENT_SIZE)
+}
+
+/// Maximum size of an upload request, the attachment plus the multipart framing
+pub fn max_upload_size() -> usize {
+    max_attachment_size() + MULTIPART_OVERHEAD
+}
+
+/// File store trait for where the contents of attachments are kept
+/// Keys are generated by the server, so a store can use them as file or object names as they are
+#[axum::async_trait]
+pub trait FileStore: Send + Sync {
+    /// Save the contents under a key
+    async fn put(&self, key: &str, contents: &[u8]) -> std::io::Result<()>;
+
+    /// Read the contents saved under a key
+    async fn get(&self, key: &str) -> std::io::Result<Vec<u8>>;
+
+    /// Delete the contents saved under a key, a key that is not there is not an error
+    async fn delete(&self, key: &str) -> std::io::Result<()>;
+}
+
+/// File store that keeps every attachment as a file in one directory
+pub struct LocalFileStore {
+    root: PathBuf,
+}
+
+impl LocalFileStore {
+    /// Constructor that reads the directory from `ATTACHMENT_DIR`, it is created on the first
+    /// upload
+    pub fn new() -> Self {
+        let root = std::env::var("ATTACHMENT_DIR").unwrap_or(DEFAULT_ATTACHMENT_DIR.to_owned());
+        LocalFileStore { root: root.into() }
+    }
+}
+
+impl Default for LocalFileStore {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[axum::async_trait]
+impl FileStore for LocalFileStore {
+    async fn put(&self, key: &str, contents: &[u8]) -> std::io::Result<()> {
+        tokio::fs::create_dir_all(&self.root).await?;
+        tokio::fs::write(self.root.join(key), contents).await
+    }
+
+    async fn get(&self, key: &str) -> std::io::Result<Vec<u8>> {
+        tokio::fs::read(self.root.join(key)).await
+    }
+
+    async fn delete(&self, key: &str) -> std::io::Result<()> {
+        match tokio::fs::remove_file(self.root.join(key)).await {
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            res => res,
+        }
+    }
+}
+
+/// Attachment struct with the metadata of an uploaded file, exactly one of `question_id` and
+/// `answer_id` is set
+#[derive(Debug, Serialize, Clone, ToSchema)]
+pub struct Attachment {
+    pub id: i32,
+    pub question_id: Option<i32>,
+    pub answer_id: Option<i32>,
+    pub filename: String,
+    pub content_type: String,
+    pub size: i64,
+    pub created_on: NaiveDateTime,
+    #[serde(skip)]
+    pub storage_key: String,
+}
+
+/// What an attachment belongs to
+#[derive(Debug, Clone, Copy)]
+pub enum AttachmentParent {
+    Question(i32),
+    Answer(i32),
+}
+
+impl AttachmentParent {
+    /// Id of the question, if the attachment belongs to one
+    pub fn question_id(&self) -> Option<i32> {
+        match self {
+            AttachmentParent::Question(id) => Some(*id),
+            AttachmentParent::Answer(_) => None,
+        }
+    }
+
+    /// Id of the answer, if the attachment belongs to one
+    pub fn answer_id(&self) -> Option<i32> {
+        match self {
+            AttachmentParent::Question(_) => None,
+            AttachmentParent::Answer(id) => Some(*id),
+        }
+    }
+
+    /// Error for a question or answer that does not exist
+    fn not_found(&self) -> Err {
+        match self {
+            AttachmentParent::Question(_) => Err::QuestionNotFound,
+            AttachmentParent::Answer(_) => Err::AnswerNotFound,
+        }
+    }
+}
+
+/// Generate a random key to save the contents of an attachment under, the uploaded file name is
+/// never used so that it cannot point outside of the file store
+fn generate_storage_key() -> String {
+    let mut bytes = [0u8; 16];
+    OsRng.fill_bytes(&mut bytes);
+    hex::encode(bytes)
+}
+
+/// Work out the type of a file from its first bytes, only the allowed types are recognised
+/// The type the client sends is not trusted, so a script cannot be uploaded as an image
+pub fn detect_content_type(contents: &[u8]) -> Option<&'static str> {
+    let content_type = match contents {
+        [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n', ..] => "image/png",
+        [0xff, 0xd8, 0xff, ..] => "image/jpeg",
+        [b'G', b'I', b'F', b'8', b'7' | b'9', b'a', ..] => "image/gif",
+        [b'R', b'I', b'F', b'F', _, _, _, _, b'W', b'E', b'B', b'P', ..] => "image/webp",
+        [b'%', b'P', b'D', b'F', b'-', ..] => "application/pdf",
+        [] => return None,
+        // Anything else is only allowed as plain text, such as a log
+        _ if !contents.contains(&0) && std::str::from_utf8(contents).is_ok() => {
+            "text/plain; charset=utf-8"
+        }
+        _ => return None,
+    };
+    Some(content_type)
+}
+
+/// Keep the last part of an uploaded file name without control characters or quotes, so that it
+/// is safe to send back in a `Content-Disposition` header
+pub fn clean_filename(filename: Option<&str>) -> String {
+    let filename: String = filename
+        .unwrap_or_default()
+        .rsplit(['/', '\\'])
+        .next()
+        .unwrap_or_default()
+        .chars()
+        .filter(|c| !c.is_control() && *c != '"')
+        .take(MAX_FILENAME_LENGTH)
+        .collect();
+    match filename.trim() {
+        "" | "." | ".." => "attachment".to_owned(),
+        filename => filename.to_owned(),
+    }
+}
+
+/// Read the `file` field of a multipart upload, checking its size and type
+async fn read_upload(
+    mut multipart: Multipart,
+) -> Result<(String, &'static str, Vec<u8>), (StatusCode, String)> {
+    let max_size = max_attachment_size();
+    loop {
+        let mut field = match multipart.next_field().await {
+            Ok(Some(field)) => field,
+            Ok(None) => return Err((StatusCode::BAD_REQUEST, Err::MissingFile.to_string())),
+            Err(e) => return Err((e.status(), e.body_text())),
+        };
+        if field.name() != Some("file") {
+            continue;
+        }
+
+        // Read the file a chunk at a time so that a large one is stopped early
+        let filename = clean_filename(field.file_name());
+        let mut contents = Vec::new();
+        while let Some(chunk) = field
+            .chunk()
+            .await
+            .map_err(|e| (e.status(), e.body_text()))?
+        {
+            if contents.len() + chunk.len() > max_size {
+                return Err((
+                    StatusCode::PAYLOAD_TOO_LARGE,
+                    Err::FileTooLarge(max_size).to_string(),
+                ));
+            }
+            contents.extend_from_slice(&chunk);
+        }
+
+        return match detect_content_type(&contents) {
+            Some(content_type) => Ok((filename, content_type, contents)),
+            None => Err((
+                StatusCode::UNSUPPORTED_MEDIA_TYPE,
+                Err::UnsupportedFileType.to_string(),
+            )),
+        };
+    }
+}
+
+/// Save an upload and record it against a question or answer
+async fn upload_attachment(
+    store: Arc<RwLock<Store>>,
+    parent: AttachmentParent,
+    multipart: Multipart,
+) -> Response {
+    let (filename, content_type, contents) = match read_upload(multipart).await {
+        Ok(upload) => upload,
+        Err(e) => return e.into_response(),
+    };
+
+    // Save the file first, then record it, removing the file again if it cannot be recorded
+    let key = generate_storage_key();
+    let files = store.read().await.files.clone();
+    if let Err(e) = files.put(&key, &contents).await {
+        tracing::error!("Could not save attachment: {:?}", e);
+        return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response();
+    }
+    let res = store
+        .write()
+        .await
+        .add_attachment(parent, &filename, content_type, contents.len() as i64, &key)
+        .await;
+    if res.is_err() {
+        if let Err(e) = files.delete(&key).await {
+            tracing::error!("Could not remove attachment {}: {:?}", key, e);
+        }
+    }
+
+    match res {
+        Ok(attachment) => (StatusCode::CREATED, Json(attachment)).into_response(),
+        Err(sqlx::Error::RowNotFound) => {
+            (StatusCode::NOT_FOUND, parent.not_found().to_string()).into_response()
+        }
+        Err(e) => (StatusCode::BAD_REQUEST, e.to_string()).into_response(),
+    }
+}
+
+/// List the attachments of a question or answer
+async fn list_attachments(store: Arc<RwLock<Store>>, parent: AttachmentParent) -> Response {
+    match store.read().await.get_attachments(parent).await {
+        Ok(attachments) => (StatusCode::OK, Json(attachments)).into_response(),
+        Err(sqlx::Error::RowNotFound) => {
+            (StatusCode::NOT_FOUND, parent.not_found().to_string()).into_response()
+        }
+        Err(e) => (StatusCode::BAD_REQUEST, e.to_string()).into_response(),
+    }
+}
+
+/// Attach a file to a question from the `question/attachments` route
+/// # Example query
+/// POST requests to this route have a multipart body with the file in a `file` field
+/// Images (PNG, JPEG, GIF and WebP), PDFs and UTF-8 text such as logs are accepted, the type is
+/// worked out from the contents of the file
+/// `curl -F file=@screenshot.png /question/1/attachments`
+#[utoipa::path(
+    post,
+    path = "/question/{id}/attachments",
+    tag = "attachments",
+    params(("id" = i32, Path, description = "Question id")),
+    request_body(content = String, description = "Multipart form with a `file` field", content_type = "multipart/form-data"),
+    responses(
+        (status = 201, description = "Attachment added", body = Attachment),
+        (status = 400, description = "Upload is invalid or database error", body = String),
+        (status = 404, description = "Question not found", body = String),
+        (status = 413, description = "File is too large", body = String),
+        (status = 415, description = "File type is not allowed", body = String),
+    )
+)]
+pub async fn add_question_attachment(
+    State(store): State<Arc<RwLock<Store>>>,
+    Path(id): Path<i32>,
+    multipart: Multipart,
+) -> Response {
+    upload_attachment(store, AttachmentParent::Question(id), multipart).await
+}
+
+/// Attach a file to an answer from the `answer/attachments` route
+/// # Example query
+/// POST requests to this route have a multipart body with the file in a `file` field, the same
+/// files are accepted as for questions
+/// `curl -F file=@server.log /answer/1/attachments`
+#[utoipa::path(
+    post,
+    path = "/answer/{id}/attachments",
+    tag = "attachments",
+    params(("id" = i32, Path, description = "Answer id")),
+    request_body(content = String, description = "Multipart form with a `file` field", content_type = "multipart/form-data"),
+    responses(
+        (status = 201, description = "Attachment added", body = Attachment),
+        (status = 400, description = "Upload is invalid or database error", body = String),
+        (status = 404, description = "Answer not found", body = String),
+        (status = 413, description = "File is too large", body = String),
+        (status = 415, description = "File type is not allowed", body = String),
+    )
+)]
+pub async fn add_answer_attachment(
+    State(store): State<Arc<RwLock<Store>>>,
+    Path(id): Path<i32>,
+    multipart: Multipart,
+) -> Response {
+    upload_attachment(store, AttachmentParent::Answer(id), multipart).await
+}
+
+/// Fetch the attachments of a question from the `question/attachments` route
+/// # Example query
+/// GET requests to this route return the metadata of every file attached to the question, oldest
+/// first, the files themselves are served from the `attachment` route
+/// `/question/1/attachments`
+#[utoipa::path(
+    get,
+    path = "/question/{id}/attachments",
+    tag = "attachments",
+    params(("id" = i32, Path, description = "Question id")),
+    responses(
+        (status = 200, description = "Attachments of the question", body = [Attachment]),
+        (status = 400, description = "Database error", body = String),
+        (status = 404, description = "Question not found", body = String),
+    )
+)]
+pub async fn get_question_attachments(
+    State(store): State<Arc<RwLock<Store>>>,
+    Path(id): Path<i32>,
+) -> Response {
+    list_attachments(store, AttachmentParent::Question(id)).await
+}
+
+/// Fetch the attachments of an answer from the `answer/attachments` route
+/// # Example query
+/// GET requests to this route return the metadata of every file attached to the answer, oldest
+/// first
+/// `/answer/1/attachments`
+#[utoipa::path(
+    get,
+    path = "/answer/{id}/attachments",
+    tag = "attachments",
+    params(("id" = i32, Path, description = "Answer id")),
+    responses(
+        (status = 200, description = "Attachments of the answer", body = [Attachment]),
+        (status = 400, description = "Database error", body = String),
+        (status = 404, description = "Answer not found", body = String),
+    )
+)]
+pub async fn get_answer_attachments(
+    State(store): State<Arc<RwLock<Store>>>,
+    Path(id): Path<i32>,
+) -> Response {
+    list_attachments(store, AttachmentParent::Answer(id)).await
+}
+
+/// Download an attachment from the `attachment` route
+/// # Example query
+/// GET requests to this route return the file with its content type, images are shown in the
+/// browser and every other file is downloaded
+/// `/attachment/1`
+#[utoipa::path(
+    get,
+    path = "/attachment/{id}",
+    tag = "attachments",
+    params(("id" = i32, Path, description = "Attachment id")),
+    responses(
+        (status = 200, description = "Contents of the file", body = Vec<u8>, content_type = "application/octet-stream"),
+        (status = 400, description = "Database error", body = String),
+        (status = 404, description = "Attachment not found", body = String),
+    )
+)]
+pub async fn get_attachment(
+    State(store): State<Arc<RwLock<Store>>>,
+    Path(id): Path<i32>,
+) -> Response {
+    let (attachment, files) = {
+        let store = store.read().await;
+        match store.get_attachment(&id).await {
+            Ok(attachment) => (attachment, store.files.clone()),
+            Err(sqlx::Error::RowNotFound) => {
+                return (StatusCode::NOT_FOUND, Err::AttachmentNotFound.to_string()).into_response()
+            }
+            Err(e) => return (StatusCode::BAD_REQUEST, e.to_string()).into_response(),
+        }
+    };
+
+    let contents = match files.get(&attachment.storage_key).await {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            tracing::error!("Attachment {} is missing its file", attachment.id);
+            return (StatusCode::NOT_FOUND, Err::AttachmentNotFound.to_string()).into_response();
+        }
+        Err(e) => {
+            tracing::error!("Could not read attachment {}: {:?}", attachment.id, e);
+            return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response();
+        }
+    };
+
+    // Only images are shown inline, and browsers are told not to guess another type
+    let disposition = if attachment.content_type.starts_with("image/") {
+        "inline"
+    } else {
+        "attachment"
+    };
+    (
+        StatusCode::OK,
+        [
+            (header::CONTENT_TYPE, attachment.content_type),
+            (
+                header::CONTENT_DISPOSITION,
+                format!("{}; filename=\"{}\"", disposition, attachment.filename),
+            ),
+            (header::X_CONTENT_TYPE_OPTIONS, "nosniff".to_owned()),
+        ],
+        contents,
+    )
+        .into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn content_type_comes_from_the_contents() {
+        assert_eq!(
+            detect_content_type(b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR"),
+            Some("image/png")
+        );
+        assert_eq!(detect_content_type(b"GIF89a\x01\0"), Some("image/gif"));
+        assert_eq!(
+            detect_content_type(b"RIFF\x24\0\0\0WEBPVP8 "),
+            Some("image/webp")
+        );
+        assert_eq!(
+            detect_content_type(b"2026-10-19 ERROR failed\n"),
+            Some("text/plain; charset=utf-8")
+        );
+        // Binaries are rejected, even if the client says they are images
+        assert_eq!(detect_content_type(b"MZ\x90\0\x03\0\0\0"), None);
+        assert_eq!(detect_content_type(b"\xff\xfe not utf-8"), None);
+        assert_eq!(detect_content_type(b""), None);
+    }
+
+    #[test]
+    fn filenames_are_safe_for_headers() {
+        assert_eq!(clean_filename(Some("../../etc/passwd")), "passwd");
+        assert_eq!(clean_filename(Some("C:\\logs\\app.log")), "app.log");
+        assert_eq!(clean_filename(Some("a\"b\r\n.png")), "ab.png");
+        assert_eq!(clean_filename(Some("..")), "attachment");
+        assert_eq!(clean_filename(None), "attachment");
+    }
+}
@@ -0,0 +1,265 @@
+use clap::{Parser, Subcommand};
+use futures_util::StreamExt;
+use rustproject::*;
+use std::error::Error;
+use std::io::{BufRead, IsTerminal, Write};
+use std::path::PathBuf;
+
+/// Command-line tool for managing the questions database
+///
+/// It reads the same `PG_*` environment variables as the server to connect to the database
+#[derive(Parser)]
+#[command(name = "admin", version)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Run or revert the database migrations
+    Migrate {
+        #[command(subcommand)]
+        action: MigrateAction,
+    },
+    /// Load a set of sample data into the database
+    Seed {
+        /// Seed set to load, `empty`, `demo` or `load-test`
+        #[arg(default_value = "demo")]
+        set: String,
+        /// Delete every question and answer first
+        #[arg(long)]
+        reset: bool,
+    },
+    /// Import questions and their answers from a JSON Lines or CSV file
+    Import {
+        /// File to import
+        file: PathBuf,
+        /// Format of the file, `jsonl` or `csv`, guessed from the extension if not given
+        #[arg(long)]
+        format: Option<String>,
+        /// Check every row without saving anything
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Export every question and its answers to a file, or to standard output with `-`
+    Export {
+        /// File to write, or `-` for standard output
+        file: PathBuf,
+        /// Format of the file, `jsonl`, `json` or `csv`, guessed from the extension if not given
+        #[arg(long)]
+        format: Option<String>,
+        /// Only export questions created, edited or answered after this date
+        #[arg(long)]
+        since: Option<String>,
+    },
+    /// List the questions in the database
+    List {
+        /// Maximum number of questions to list
+        #[arg(long)]
+        limit: Option<i32>,
+        /// Number of questions to skip
+        #[arg(long, default_value_t = 0)]
+        offset: i32,
+    },
+    /// Delete a question and its answers
+    Delete {
+        /// Id of the question to delete
+        id: i32,
+    },
+    /// Create an admin user, the password is read from standard input
+    CreateAdmin {
+        /// Name of the new user
+        username: String,
+    },
+    /// Create a user that can log in to the study routes, the password is read from standard
+    /// input
+    CreateUser {
+        /// Name of the new user
+        username: String,
+    },
+    /// Print the number of questions and answers and the migration version
+    Stats,
+}
+
+#[derive(Subcommand)]
+enum MigrateAction {
+    /// Apply every pending migration
+    Run,
+    /// Revert the most recently applied migration
+    Revert,
+}
+
+#[tokio::main]
+async fn main() {
+    let cli = Cli::parse();
+
+    // Connect without running the migrations so that they can be managed from here
+    let store = Store::connect().await.unwrap_or_else(|e| {
+        eprintln!("Error: could not connect to the database: {}", e);
+        std::process::exit(1);
+    });
+
+    if let Err(e) = run(cli.command, store).await {
+        eprintln!("Error: {}", e);
+        std::process::exit(1);
+    }
+}
+
+/// Run a single command against the data store
+async fn run(command: Command, mut store: Store) -> Result<(), Box<dyn Error>> {
+    match command {
+        Command::Migrate {
+            action: MigrateAction::Run,
+        } => {
+            MIGRATOR.run(&store.connection).await?;
+            println!("Migrated to version {}", migration_version(&store).await?);
+        }
+        Command::Migrate {
+            action: MigrateAction::Revert,
+        } => {
+            // Undo everything newer than the migration before the current one
+            let current = migration_version(&store).await?;
+            let target = MIGRATOR
+                .iter()
+                .map(|m| m.version)
+                .filter(|v| *v < current)
+                .max()
+                .unwrap_or(0);
+            MIGRATOR.undo(&store.connection, target).await?;
+            println!("Reverted to version {}", target);
+        }
+        Command::Seed { set, reset } => {
+            let report = seed(&mut store, SeedSet::parse(Some(&set))?, reset).await?;
+            println!(
+                "Added {} questions and {} answers",
+                report.questions, report.answers
+            );
+        }
+        Command::Import {
+            file,
+            format,
+            dry_run,
+        } => {
+            let format = format.unwrap_or_else(|| match extension(&file).as_deref() {
+                Some("csv") => "csv".to_owned(),
+                _ => "jsonl".to_owned(),
+            });
+            let body = std::fs::read_to_string(&file)?;
+            let parsed = parse_import(&body, &format.to_lowercase())?;
+            let report = run_import(&mut store, parsed, dry_run).await?;
+
+            // Print the rows that failed, then a summary
+            for row in report.rows.iter().filter(|r| r.error.is_some()) {
+                eprintln!(
+                    "Row {}: {}",
+                    row.row,
+                    row.error.as_deref().unwrap_or_default()
+                );
+            }
+            if dry_run {
+                println!(
+                    "{} rows valid, {} rows failed (dry run, nothing was saved)",
+                    report.rows.len() - report.failed,
+                    report.failed
+                );
+            } else {
+                println!(
+                    "{} rows imported, {} rows failed",
+                    report.imported, report.failed
+                );
+            }
+        }
+        Command::Export {
+            file,
+            format,
+            since,
+        } => {
+            let format = ExportFormat::parse(format.or_else(|| extension(&file)).as_deref())?;
+            let since = since.as_deref().map(parse_since).transpose()?;
+
+            // Write the chunks as they are read from the database cursor
+            let mut out: Box<dyn Write> = if file.as_os_str() == "-" {
+                Box::new(std::io::stdout().lock())
+            } else {
+                Box::new(std::io::BufWriter::new(std::fs::File::create(&file)?))
+            };
+            let mut chunks = Box::pin(export_body(format, store.export_questions(since)));
+            while let Some(chunk) = chunks.next().await {
+                out.write_all(chunk.map_err(|e| e.to_string())?.as_bytes())?;
+            }
+            out.flush()?;
+        }
+        Command::List { limit, offset } => {
+            for question in store.get_questions(limit, offset).await? {
+                println!(
+                    "{}\t{}\t{}",
+                    question.id,
+                    question.title,
+                    question.tags.unwrap_or_default().join(", ")
+                );
+            }
+        }
+        Command::Delete { id } => {
+            if !store.delete_question(&id).await? {
+                return Err(Err::QuestionNotFound.into());
+            }
+            println!("Deleted question {}", id);
+        }
+        Command::CreateAdmin { username } => {
+            let password = read_password()?;
+            let id = store
+                .add_user(NewUser::new(&username, &password, true)?)
+                .await?;
+            println!("Created admin user {} with id {}", username, id);
+        }
+        Command::CreateUser { username } => {
+            let password = read_password()?;
+            let id = store
+                .add_user(NewUser::new(&username, &password, false)?)
+                .await?;
+            println!("Created user {} with id {}", username, id);
+        }
+        Command::Stats => {
+            let (questions, answers) = store.get_totals().await?;
+            println!("Questions: {}", questions);
+            println!("Answers: {}", answers);
+            println!("Migration version: {}", migration_version(&store).await?);
+        }
+    }
+    Ok(())
+}
+
+/// Get the version of the latest applied migration, or 0 if none have been applied
+async fn migration_version(store: &Store) -> Result<i64, sqlx::Error> {
+    match store.get_migration_version().await {
+        Ok(version) => Ok(version.unwrap_or(0)),
+        // The migrations table does not exist until the first migration has run
+        Err(sqlx::Error::Database(e)) if e.code().as_deref() == Some("42P01") => Ok(0),
+        Err(e) => Err(e),
+    }
+}
+
+/// Read a password from the first line of standard input
+/// The terminal does not echo it when standard input is a terminal
+fn read_password() -> Result<String, Box<dyn Error>> {
+    let password = if std::io::stdin().is_terminal() {
+        rpassword::prompt_password("Password: ")?
+    } else {
+        let mut password = String::new();
+        std::io::stdin().lock().read_line(&mut password)?;
+        password
+    };
+    let password = password.trim_end_matches(['\r', '\n']);
+    if password.is_empty() {
+        return Err("the password cannot be empty".into());
+    }
+    Ok(password.to_owned())
+}
+
+/// Get the lowercase extension of a file, used to guess its format
+fn extension(file: &std::path::Path) -> Option<String> {
+    file.extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_lowercase())
+}
@@ -0,0 +1,51 @@
+use crate::*;
+use axum::http::HeaderValue;
+
+/// Date the unversioned routes were deprecated, as a structured field date (seconds since epoch)
+const DEPRECATED_ON: &str = "@1792368000";
+
+/// Default date after which the unversioned routes may be removed, as an HTTP date
+const DEFAULT_SUNSET: &str = "Mon, 19 Apr 2027 00:00:00 GMT";
+
+/// Deprecation struct describing where clients should move to and when the old routes go away
+#[derive(Clone)]
+pub struct Deprecation {
+    pub successor: &'static str,
+    pub sunset: HeaderValue,
+}
+
+impl Deprecation {
+    /// Constructor that reads the sunset date from `API_SUNSET`, falling back to the default
+    pub fn new(successor: &'static str) -> Self {
+        let sunset = std::env::var("API_SUNSET")
+            .ok()
+            .and_then(|date| HeaderValue::from_str(&date).ok())
+            .unwrap_or_else(|| HeaderValue::from_static(DEFAULT_SUNSET));
+
+        Deprecation { successor, sunset }
+    }
+}
+
+/// Middleware to mark responses from deprecated routes
+/// Adds the `Deprecation` and `Sunset` headers and a `Link` to the same route under the successor
+/// version so clients know where to move to
+pub async fn deprecated(
+    State(deprecation): State<Deprecation>,
+    request: Request<axum::body::Body>,
+    next: Next,
+) -> Response {
+    let successor = format!(
+        "<{}{}>; rel=\"successor-version\"",
+        deprecation.successor,
+        request.uri().path()
+    );
+
+    let mut response = next.run(request).await;
+    let headers = response.headers_mut();
+    headers.insert("deprecation", HeaderValue::from_static(DEPRECATED_ON));
+    headers.insert("sunset", deprecation.sunset);
+    if let Ok(link) = HeaderValue::from_str(&successor) {
+        headers.insert(header::LINK, link);
+    }
+    response
+}
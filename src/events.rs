@@ -0,0 +1,332 @@
+use crate::*;
+use axum::response::sse::{Event, KeepAlive, Sse};
+use std::collections::VecDeque;
+use std::convert::Infallible;
+use std::sync::Mutex;
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio_util::sync::CancellationToken;
+
+/// Default number of recent events kept in memory so that clients can resume with `Last-Event-ID`
+const DEFAULT_EVENT_HISTORY_SIZE: usize = 1_000;
+
+/// Number of events that can be waiting for a slow client before it is disconnected
+const EVENT_CHANNEL_CAPACITY: usize = 1_024;
+
+/// Header browsers send when an `EventSource` reconnects
+const LAST_EVENT_ID_HEADER: &str = "last-event-id";
+
+/// Kinds of change that are written to the outbox by the data store writes
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, ToSchema)]
+pub enum ChangeKind {
+    #[serde(rename = "question.created")]
+    QuestionCreated,
+    #[serde(rename = "question.updated")]
+    QuestionUpdated,
+    #[serde(rename = "question.deleted")]
+    QuestionDeleted,
+    #[serde(rename = "answer.created")]
+    AnswerCreated,
+}
+
+impl ChangeKind {
+    /// Parse the name of a kind
+    pub fn parse(kind: &str) -> Option<Self> {
+        match kind {
+            "question.created" => Some(ChangeKind::QuestionCreated),
+            "question.updated" => Some(ChangeKind::QuestionUpdated),
+            "question.deleted" => Some(ChangeKind::QuestionDeleted),
+            "answer.created" => Some(ChangeKind::AnswerCreated),
+            _ => None,
+        }
+    }
+
+    /// Name of the kind, used as the SSE event type
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ChangeKind::QuestionCreated => "question.created",
+            ChangeKind::QuestionUpdated => "question.updated",
+            ChangeKind::QuestionDeleted => "question.deleted",
+            ChangeKind::AnswerCreated => "answer.created",
+        }
+    }
+}
+
+/// Change event struct sent to subscribers of the `events` route
+/// `id` is the id of the outbox row, `data` is the question or answer that changed, or just the
+/// id of a deleted question
+#[derive(Debug, Serialize, Clone, ToSchema)]
+pub struct ChangeEvent {
+    pub id: u64,
+    #[serde(rename = "type")]
+    pub kind: ChangeKind,
+    pub question_id: i32,
+    pub tags: Option<Vec<String>>,
+    #[schema(value_type = Object)]
+    pub data: serde_json::Value,
+}
+
+impl ChangeEvent {
+    /// Copy of the event with the question or answer in `data` rendered the way the HTTP routes
+    /// send it, with `content_html`
+    /// Data that is not a question or answer, such as the id of a deleted question, is kept as is
+    pub fn rendered(&self) -> ChangeEvent {
+        let data = match self.kind {
+            ChangeKind::QuestionCreated | ChangeKind::QuestionUpdated => {
+                serde_json::from_value::<Question>(self.data.clone())
+                    .and_then(|question| serde_json::to_value(RenderedQuestion::from(question)))
+            }
+            ChangeKind::AnswerCreated => serde_json::from_value::<Answer>(self.data.clone())
+                .and_then(|answer| serde_json::to_value(RenderedAnswer::from(answer))),
+            ChangeKind::QuestionDeleted => Ok(self.data.clone()),
+        };
+        ChangeEvent {
+            data: data.unwrap_or_else(|_| self.data.clone()),
+            ..self.clone()
+        }
+    }
+}
+
+/// Recent events in the order they were published
+struct History {
+    events: VecDeque<Arc<ChangeEvent>>,
+    capacity: usize,
+}
+
+/// Events struct that publishes the changes relayed from the outbox to every subscriber
+/// Clones share the same channel and history
+#[derive(Clone)]
+pub struct Events {
+    sender: broadcast::Sender<Arc<ChangeEvent>>,
+    history: Arc<Mutex<History>>,
+    closed: CancellationToken,
+}
+
+impl Default for Events {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Events {
+    /// Constructor that reads the number of events to keep from `EVENT_HISTORY_SIZE`
+    pub fn new() -> Self {
+        let capacity = std::env::var("EVENT_HISTORY_SIZE")
+            .ok()
+            .and_then(|size| size.parse::<usize>().ok())
+            .unwrap_or(DEFAULT_EVENT_HISTORY_SIZE);
+
+        Events {
+            sender: broadcast::channel(EVENT_CHANNEL_CAPACITY).0,
+            history: Arc::new(Mutex::new(History {
+                events: VecDeque::with_capacity(capacity),
+                capacity,
+            })),
+            closed: CancellationToken::new(),
+        }
+    }
+
+    /// Publish a change, this is called by the outbox relay once the change has been committed
+    pub fn publish(&self, event: ChangeEvent) {
+        let mut history = self.history.lock().unwrap();
+        let event = Arc::new(event);
+
+        // Keep the event for clients that resume later, then send it to the connected ones
+        // Sending while holding the lock keeps the history and the channel in the same order
+        if history.events.len() == history.capacity {
+            history.events.pop_front();
+        }
+        if history.capacity > 0 {
+            history.events.push_back(event.clone());
+        }
+        // Sending only fails if nobody is subscribed
+        let _ = self.sender.send(event);
+    }
+
+    /// Subscribe to the events that pass a filter, starting with the kept events published after
+    /// `after` if the subscriber is resuming
+    pub fn subscribe(&self, filter: EventFilter, after: Option<u64>) -> Subscription {
+        // Subscribe while holding the history lock so that no event falls between the two
+        let history = self.history.lock().unwrap();
+        let backlog = match after {
+            // Outbox ids are taken before their writes commit, so a lower id can be published
+            // after a higher one, resume from where the event was in the history if it is kept
+            Some(after) => match history.events.iter().position(|e| e.id == after) {
+                Some(position) => history.events.range(position + 1..).cloned().collect(),
+                None => history
+                    .events
+                    .iter()
+                    .filter(|e| e.id > after)
+                    .cloned()
+                    .collect(),
+            },
+            None => VecDeque::new(),
+        };
+        Subscription {
+            backlog,
+            receiver: self.sender.subscribe(),
+            filter,
+            closed: self.closed.clone(),
+        }
+    }
+
+    /// End every event stream once the server starts shutting down, otherwise the open streams
+    /// would keep the server waiting until the shutdown timeout
+    pub async fn close_on_shutdown(self, shutdown: Shutdown) {
+        shutdown.token.cancelled().await;
+        self.closed.cancel();
+    }
+}
+
+/// Filter struct that is being extracted from the query params of the `events` route
+#[derive(Debug, Deserialize, Default)]
+pub struct EventFilter {
+    question_id: Option<i32>,
+    tag: Option<String>,
+}
+
+impl EventFilter {
+    /// Filter that only lets through the events for one question
+    pub fn question(id: i32) -> Self {
+        EventFilter {
+            question_id: Some(id),
+            tag: None,
+        }
+    }
+
+    /// Check whether an event should be sent to the client
+    fn matches(&self, event: &ChangeEvent) -> bool {
+        if self.question_id.is_some_and(|id| id != event.question_id) {
+            return false;
+        }
+        match &self.tag {
+            Some(tag) => event
+                .tags
+                .as_ref()
+                .is_some_and(|tags| tags.iter().any(|t| t.eq_ignore_ascii_case(tag))),
+            None => true,
+        }
+    }
+}
+
+/// Subscription struct for a single subscriber, ends when the server shuts down or when the
+/// subscriber falls too far behind
+pub struct Subscription {
+    backlog: VecDeque<Arc<ChangeEvent>>,
+    receiver: broadcast::Receiver<Arc<ChangeEvent>>,
+    filter: EventFilter,
+    closed: CancellationToken,
+}
+
+impl Subscription {
+    /// Wait for the next event that passes the filter, or `None` once the stream should end
+    pub async fn next(&mut self) -> Option<Arc<ChangeEvent>> {
+        loop {
+            let event = match self.backlog.pop_front() {
+                Some(event) => event,
+                None => tokio::select! {
+                    _ = self.closed.cancelled() => return None,
+                    received = self.receiver.recv() => match received {
+                        Ok(event) => event,
+                        // The client fell too far behind, closing the stream makes it reconnect
+                        // with `Last-Event-ID` and catch up from the history
+                        Err(RecvError::Lagged(missed)) => {
+                            tracing::warn!("Closing event stream that missed {} events", missed);
+                            return None;
+                        }
+                        Err(RecvError::Closed) => return None,
+                    },
+                },
+            };
+            if self.filter.matches(&event) {
+                return Some(event);
+            }
+        }
+    }
+}
+
+/// Stream changes to questions and answers from the `events` route
+/// # Example query
+/// GET requests to this route open a Server-Sent Events stream that sends `question.created`,
+/// `question.updated`, `question.deleted` and `answer.created` events as writes commit
+/// Events can be filtered by question id and by tag, and a client that reconnects with the
+/// `Last-Event-ID` header is sent the events it missed first
+/// `/events?question_id=1&tag=rust`
+#[utoipa::path(
+    get,
+    path = "/events",
+    tag = "events",
+    params(
+        ("question_id" = Option<i32>, Query, description = "Only send events for this question"),
+        ("tag" = Option<String>, Query, description = "Only send events for questions with this tag"),
+        ("Last-Event-ID" = Option<u64>, Header, description = "Resume after this event"),
+    ),
+    responses(
+        (status = 200, description = "Event stream", body = ChangeEvent, content_type = "text/event-stream"),
+        (status = 400, description = "Parameters are invalid", body = String),
+    )
+)]
+pub async fn get_events(
+    State(store): State<Arc<RwLock<Store>>>,
+    Query(filter): Query<EventFilter>,
+    headers: axum::http::HeaderMap,
+) -> Response {
+    // Parse the id of the last event the client saw, if it is resuming
+    let after = match headers
+        .get(LAST_EVENT_ID_HEADER)
+        .map(|id| id.to_str().unwrap_or_default().trim().parse::<u64>())
+    {
+        Some(Ok(id)) => Some(id),
+        Some(Err(e)) => {
+            return (StatusCode::BAD_REQUEST, Err::ParseInt(e).to_string()).into_response()
+        }
+        None => None,
+    };
+
+    let subscription = store.read().await.events.subscribe(filter, after);
+
+    // Turn each change into an SSE event with its id, type and JSON data
+    let stream = futures_util::stream::unfold(subscription, |mut subscription| async move {
+        let event = subscription.next().await?;
+        let sse = Event::default()
+            .id(event.id.to_string())
+            .event(event.kind.as_str())
+            .json_data(&*event)
+            .unwrap_or_default();
+        Some((Ok::<_, Infallible>(sse), subscription))
+    });
+    Sse::new(stream)
+        .keep_alive(KeepAlive::default())
+        .into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(kind: ChangeKind, data: serde_json::Value) -> ChangeEvent {
+        ChangeEvent {
+            id: 1,
+            kind,
+            question_id: 3,
+            tags: None,
+            data,
+        }
+    }
+
+    #[test]
+    fn questions_and_answers_are_rendered() {
+        let question = serde_json::json!({ "id": 3, "title": "T", "content": "*C*", "tags": null });
+        let rendered = event(ChangeKind::QuestionUpdated, question).rendered();
+        assert_eq!(rendered.data["content"], "*C*");
+        assert_eq!(rendered.data["content_html"], "<p><em>C</em></p>\n");
+
+        let answer =
+            serde_json::json!({ "id": 5, "content": "**A**", "corresponding_question": 3 });
+        let rendered = event(ChangeKind::AnswerCreated, answer).rendered();
+        assert_eq!(rendered.data["content_html"], "<p><strong>A</strong></p>\n");
+
+        let deleted = serde_json::json!({ "id": 3 });
+        let rendered = event(ChangeKind::QuestionDeleted, deleted.clone()).rendered();
+        assert_eq!(rendered.data, deleted);
+    }
+}
@@ -0,0 +1,207 @@
+use crate::*;
+use chrono::{DateTime, NaiveDate, NaiveDateTime};
+use futures_util::{Stream, StreamExt};
+
+/// Format of the timestamps written to CSV, the same one used by JSON
+const TIMESTAMP_FORMAT: &str = "%Y-%m-%dT%H:%M:%S%.f";
+
+/// Export answer struct for an answer nested in an exported question
+#[derive(Debug, Serialize, Clone, Deserialize, ToSchema)]
+pub struct ExportAnswer {
+    pub id: i32,
+    pub content: String,
+    pub created_on: NaiveDateTime,
+}
+
+/// Export question struct for a question with every one of its answers
+#[derive(Debug, Serialize, Clone, Deserialize, ToSchema)]
+pub struct ExportQuestion {
+    pub id: i32,
+    pub title: String,
+    pub content: String,
+    pub tags: Option<Vec<String>>,
+    pub created_on: NaiveDateTime,
+    pub answers: Vec<ExportAnswer>,
+}
+
+/// Formats that the `export` route can write
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ExportFormat {
+    Jsonl,
+    Json,
+    Csv,
+}
+
+impl ExportFormat {
+    /// Parse a format name, defaulting to JSON Lines if there is none
+    pub fn parse(format: Option<&str>) -> Result<Self, Err> {
+        match format.map(|f| f.to_lowercase()).as_deref() {
+            None | Some("jsonl") | Some("ndjson") => Ok(ExportFormat::Jsonl),
+            Some("json") => Ok(ExportFormat::Json),
+            Some("csv") => Ok(ExportFormat::Csv),
+            Some(format) => Err(Err::UnsupportedFormat(format.to_owned())),
+        }
+    }
+
+    /// Content type and file extension used for the format
+    fn content_type(&self) -> (&'static str, &'static str) {
+        match self {
+            ExportFormat::Jsonl => ("application/x-ndjson", "jsonl"),
+            ExportFormat::Json => ("application/json", "json"),
+            ExportFormat::Csv => ("text/csv", "csv"),
+        }
+    }
+}
+
+/// Parse a `since` date as an RFC 3339 timestamp, a timestamp without an offset or a date
+pub fn parse_since(since: &str) -> Result<NaiveDateTime, Err> {
+    DateTime::parse_from_rfc3339(since)
+        .map(|date| date.naive_utc())
+        .or_else(|_| NaiveDateTime::parse_from_str(since, TIMESTAMP_FORMAT))
+        .or_else(|_| {
+            NaiveDate::parse_from_str(since, "%Y-%m-%d")
+                .map(|date| date.and_time(Default::default()))
+        })
+        .map_err(|_| Err::InvalidDate(since.to_owned()))
+}
+
+/// Write a batch of questions in the requested format
+/// CSV has one row per answer, repeating the question columns, and questions without answers get
+/// a single row with empty answer columns, the `import` route joins the rows back up by `id`
+fn write_batch(
+    format: ExportFormat,
+    batch: &[ExportQuestion],
+    first: bool,
+) -> Result<String, Box<dyn Error + Send + Sync>> {
+    let mut out = String::new();
+    match format {
+        ExportFormat::Jsonl => {
+            for question in batch {
+                out.push_str(&serde_json::to_string(question)?);
+                out.push('\n');
+            }
+        }
+        ExportFormat::Json => {
+            for (i, question) in batch.iter().enumerate() {
+                if !(first && i == 0) {
+                    out.push(',');
+                }
+                out.push_str(&serde_json::to_string(question)?);
+            }
+        }
+        ExportFormat::Csv => {
+            let mut writer = csv::WriterBuilder::new()
+                .has_headers(false)
+                .from_writer(Vec::new());
+            for question in batch {
+                let tags = question.tags.clone().unwrap_or_default().join(";");
+                let question_columns = [
+                    question.id.to_string(),
+                    question.title.clone(),
+                    question.content.clone(),
+                    tags,
+                    question.created_on.format(TIMESTAMP_FORMAT).to_string(),
+                ];
+                if question.answers.is_empty() {
+                    writer.write_record(question_columns.iter().chain(&[
+                        String::new(),
+                        String::new(),
+                        String::new(),
+                    ]))?;
+                }
+                for answer in &question.answers {
+                    writer.write_record(question_columns.iter().chain(&[
+                        answer.id.to_string(),
+                        answer.content.clone(),
+                        answer.created_on.format(TIMESTAMP_FORMAT).to_string(),
+                    ]))?;
+                }
+            }
+            out.push_str(&String::from_utf8(writer.into_inner()?)?);
+        }
+    }
+    Ok(out)
+}
+
+/// Turn the batches from the data store into chunks of the export file
+pub fn export_body(
+    format: ExportFormat,
+    batches: impl Stream<Item = Result<Vec<ExportQuestion>, sqlx::Error>> + Send + 'static,
+) -> impl Stream<Item = Result<String, Box<dyn Error + Send + Sync>>> + Send + 'static {
+    // Write the opening and closing parts that wrap the rows
+    let (start, end) = match format {
+        ExportFormat::Jsonl => ("", ""),
+        ExportFormat::Json => ("[", "]\n"),
+        ExportFormat::Csv => (
+            "id,title,content,tags,created_on,answer_id,answer_content,answer_created_on\n",
+            "",
+        ),
+    };
+
+    let rows = batches.enumerate().map(move |(i, batch)| match batch {
+        Ok(batch) => write_batch(format, &batch, i == 0),
+        Err(e) => {
+            // The status has already been sent, so all that can be done is to cut the body short
+            tracing::event!(tracing::Level::ERROR, "{:?}", e);
+            Err(e.into())
+        }
+    });
+
+    futures_util::stream::once(async move { Ok(start.to_owned()) })
+        .chain(rows)
+        .chain(futures_util::stream::once(
+            async move { Ok(end.to_owned()) },
+        ))
+}
+
+/// Export every question with its answers from the `export` route
+/// # Example query
+/// GET requests to this route stream the whole dataset as it is read from a database cursor, so
+/// it is never held in memory all at once
+/// The format is one of `jsonl` (the default), `json` or `csv` and `since` only exports questions
+/// created, edited or answered after the given date
+/// `/export?format=jsonl&since=2024-05-06T00:00:00Z`
+#[utoipa::path(
+    get,
+    path = "/export",
+    tag = "export",
+    params(
+        ("format" = Option<String>, Query, description = "`jsonl`, `json` or `csv`, defaults to `jsonl`"),
+        ("since" = Option<String>, Query, description = "Only export questions created, edited or answered after this date"),
+    ),
+    responses(
+        (status = 200, description = "Every question with its answers", body = [ExportQuestion], content_type = "application/x-ndjson"),
+        (status = 400, description = "Parameters are invalid", body = String),
+    )
+)]
+pub async fn export(
+    State(store): State<Arc<RwLock<Store>>>,
+    Query(params): Query<HashMap<String, String>>,
+) -> Response {
+    let format = match ExportFormat::parse(params.get("format").map(String::as_str)) {
+        Ok(format) => format,
+        Err(e) => return (StatusCode::BAD_REQUEST, e.to_string()).into_response(),
+    };
+    let since = match params.get("since").map(|since| parse_since(since)) {
+        Some(Ok(since)) => Some(since),
+        Some(Err(e)) => return (StatusCode::BAD_REQUEST, e.to_string()).into_response(),
+        None => None,
+    };
+
+    // Clone the data store so the lock is not held while the body is streamed
+    let store = store.read().await.clone();
+    let (content_type, extension) = format.content_type();
+
+    (
+        StatusCode::OK,
+        [
+            (header::CONTENT_TYPE, content_type.to_owned()),
+            (
+                header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"export.{}\"", extension),
+            ),
+        ],
+        axum::body::Body::from_stream(export_body(format, store.export_questions(since))),
+    )
+        .into_response()
+}
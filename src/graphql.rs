@@ -0,0 +1,374 @@
+use crate::*;
+use async_graphql::dataloader::{DataLoader, Loader};
+use async_graphql::http::{parse_query_string, GraphiQLSource};
+use async_graphql::parser::types::OperationType;
+use async_graphql::{Context, EmptySubscription, ErrorExtensions, Object, Schema};
+use axum::extract::OriginalUri;
+use axum::response::Html;
+use std::sync::OnceLock;
+
+/// Number of questions returned by the `questions` query if no limit is given
+const DEFAULT_GRAPHQL_LIMIT: i64 = 20;
+
+/// Largest limit the `questions` and `answers` queries accept
+const MAX_GRAPHQL_LIMIT: i64 = 100;
+
+/// Deepest nesting a query can have, answers and questions can be nested in each other forever
+const MAX_GRAPHQL_DEPTH: usize = 8;
+
+/// Largest number of fields a query can select, counting the fields of every nested object
+const MAX_GRAPHQL_COMPLEXITY: usize = 2_000;
+
+/// Schema served from the `graphql` route
+pub type QuestionSchema = Schema<QueryRoot, MutationRoot, EmptySubscription>;
+
+/// Build the schema once, it holds no state so every request shares it
+pub fn graphql_schema() -> &'static QuestionSchema {
+    static SCHEMA: OnceLock<QuestionSchema> = OnceLock::new();
+    SCHEMA.get_or_init(|| {
+        Schema::build(QueryRoot, MutationRoot, EmptySubscription)
+            .limit_depth(MAX_GRAPHQL_DEPTH)
+            .limit_complexity(MAX_GRAPHQL_COMPLEXITY)
+            .finish()
+    })
+}
+
+/// Filter struct for the `questions` query, every field that is set has to match
+#[derive(Debug, Default, InputObject)]
+pub struct QuestionFilter {
+    /// Only questions with this tag, ignoring case
+    pub tag: Option<String>,
+    /// Only questions whose title or content contains this text, ignoring case
+    pub search: Option<String>,
+    /// Only questions that have at least one answer, or only the ones that have none
+    pub answered: Option<bool>,
+}
+
+/// Orders the `questions` query can sort by
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Enum)]
+pub enum QuestionSort {
+    #[default]
+    Newest,
+    Oldest,
+    Title,
+}
+
+impl QuestionSort {
+    /// `ORDER BY` clause for the order
+    pub fn order_by(&self) -> &'static str {
+        match self {
+            QuestionSort::Newest => "id DESC",
+            QuestionSort::Oldest => "id ASC",
+            QuestionSort::Title => "title ASC, id ASC",
+        }
+    }
+}
+
+/// Tag count struct returned by the `tags` query
+#[derive(Debug, Clone, SimpleObject)]
+pub struct TagCount {
+    pub tag: String,
+    pub questions: i64,
+}
+
+/// Loader that batches the answers of every question in a query into one database query
+pub struct AnswersLoader(Store);
+
+impl Loader<i32> for AnswersLoader {
+    type Value = Vec<Answer>;
+    type Error = Arc<sqlx::Error>;
+
+    async fn load(&self, keys: &[i32]) -> Result<HashMap<i32, Vec<Answer>>, Self::Error> {
+        let mut answers: HashMap<i32, Vec<Answer>> = HashMap::new();
+        for answer in self.0.get_answers_for_questions(keys).await? {
+            answers
+                .entry(answer.corresponding_question)
+                .or_default()
+                .push(answer);
+        }
+        Ok(answers)
+    }
+}
+
+/// Loader that batches the question of every answer in a query into one database query
+pub struct QuestionLoader(Store);
+
+impl Loader<i32> for QuestionLoader {
+    type Value = Question;
+    type Error = Arc<sqlx::Error>;
+
+    async fn load(&self, keys: &[i32]) -> Result<HashMap<i32, Question>, Self::Error> {
+        Ok(self
+            .0
+            .get_questions_by_ids(keys)
+            .await?
+            .into_iter()
+            .map(|question| (question.id, question))
+            .collect())
+    }
+}
+
+#[ComplexObject]
+impl Question {
+    /// Content rendered from CommonMark to sanitized HTML
+    async fn content_html(&self) -> String {
+        render_markdown(&self.content)
+    }
+
+    /// Answers to the question, oldest first
+    async fn answers(&self, ctx: &Context<'_>) -> async_graphql::Result<Vec<Answer>> {
+        let loader = ctx.data_unchecked::<DataLoader<AnswersLoader>>();
+        Ok(loader.load_one(self.id).await?.unwrap_or_default())
+    }
+}
+
+#[ComplexObject]
+impl Answer {
+    /// Content rendered from CommonMark to sanitized HTML
+    async fn content_html(&self) -> String {
+        render_markdown(&self.content)
+    }
+
+    /// Question the answer belongs to
+    async fn question(&self, ctx: &Context<'_>) -> async_graphql::Result<Option<Question>> {
+        let loader = ctx.data_unchecked::<DataLoader<QuestionLoader>>();
+        Ok(loader.load_one(self.corresponding_question).await?)
+    }
+}
+
+/// Check the limit and offset of a query
+fn page(limit: Option<i64>, offset: Option<i64>) -> async_graphql::Result<(i64, i64)> {
+    let limit = limit.unwrap_or(DEFAULT_GRAPHQL_LIMIT);
+    if !(1..=MAX_GRAPHQL_LIMIT).contains(&limit) {
+        return Err(format!("limit must be between 1 and {}", MAX_GRAPHQL_LIMIT).into());
+    }
+    let offset = offset.unwrap_or(0);
+    if offset < 0 {
+        return Err("offset cannot be negative".into());
+    }
+    Ok((limit, offset))
+}
+
+/// Queries of the `graphql` route
+pub struct QueryRoot;
+
+#[Object]
+impl QueryRoot {
+    /// Questions that pass the filter, newest first unless another order is given
+    async fn questions(
+        &self,
+        ctx: &Context<'_>,
+        filter: Option<QuestionFilter>,
+        sort: Option<QuestionSort>,
+        limit: Option<i64>,
+        offset: Option<i64>,
+    ) -> async_graphql::Result<Vec<Question>> {
+        let (limit, offset) = page(limit, offset)?;
+        let store = ctx.data_unchecked::<Arc<RwLock<Store>>>();
+        Ok(store
+            .read()
+            .await
+            .search_questions(
+                &filter.unwrap_or_default(),
+                sort.unwrap_or_default(),
+                limit,
+                offset,
+            )
+            .await?)
+    }
+
+    /// Question with the given id, or null if there is none
+    async fn question(
+        &self,
+        ctx: &Context<'_>,
+        id: i32,
+    ) -> async_graphql::Result<Option<Question>> {
+        let loader = ctx.data_unchecked::<DataLoader<QuestionLoader>>();
+        Ok(loader.load_one(id).await?)
+    }
+
+    /// Answers to every question, oldest first
+    async fn answers(
+        &self,
+        ctx: &Context<'_>,
+        limit: Option<i64>,
+        offset: Option<i64>,
+    ) -> async_graphql::Result<Vec<Answer>> {
+        let (limit, offset) = page(limit, offset)?;
+        let store = ctx.data_unchecked::<Arc<RwLock<Store>>>();
+        Ok(store
+            .read()
+            .await
+            .get_answers(Some(limit as i32), offset as i32)
+            .await?)
+    }
+
+    /// Every tag and the number of questions using it, most used first
+    async fn tags(&self, ctx: &Context<'_>) -> async_graphql::Result<Vec<TagCount>> {
+        let store = ctx.data_unchecked::<Arc<RwLock<Store>>>();
+        Ok(store.read().await.get_tags().await?)
+    }
+}
+
+/// Mutations of the `graphql` route, they go through the same data store methods as the REST
+/// routes so they publish the same events
+pub struct MutationRoot;
+
+#[Object]
+impl MutationRoot {
+    /// Add a question, returning it with its id
+    /// A question that looks like a duplicate of an existing one is rejected unless `force` is
+    /// true, the ids of the most similar questions are in the `candidates` extension of the error
+    async fn add_question(
+        &self,
+        ctx: &Context<'_>,
+        question: NewQuestion,
+        force: Option<bool>,
+    ) -> async_graphql::Result<Question> {
+        question.validate()?;
+        let store = ctx.data_unchecked::<Arc<RwLock<Store>>>();
+        if !force.unwrap_or(false) {
+            let candidates = find_blocking_duplicates(&*store.read().await, &question).await?;
+            if let Some(closest) = candidates.first() {
+                let ids: Vec<i32> = candidates.iter().map(|c| c.question.id).collect();
+                return Err(Err::DuplicateQuestion(closest.question.id)
+                    .to_string()
+                    .extend_with(|_, e| e.set("candidates", ids)));
+            }
+        }
+        Ok(store.write().await.add_question(question).await?)
+    }
+
+    /// Update a question, returning the updated question or null if there is none with the id
+    async fn update_question(
+        &self,
+        ctx: &Context<'_>,
+        id: i32,
+        question: NewQuestion,
+    ) -> async_graphql::Result<Option<Question>> {
+        question.validate()?;
+        let store = ctx.data_unchecked::<Arc<RwLock<Store>>>();
+        Ok(store.write().await.update_question(&id, question).await?)
+    }
+
+    /// Delete a question and its answers, returning whether it existed
+    async fn delete_question(&self, ctx: &Context<'_>, id: i32) -> async_graphql::Result<bool> {
+        let store = ctx.data_unchecked::<Arc<RwLock<Store>>>();
+        Ok(store.write().await.delete_question(&id).await?)
+    }
+
+    /// Add an answer to a question, returning it with its id
+    async fn add_answer(
+        &self,
+        ctx: &Context<'_>,
+        answer: NewAnswer,
+    ) -> async_graphql::Result<Answer> {
+        answer.validate()?;
+        let store = ctx.data_unchecked::<Arc<RwLock<Store>>>();
+        Ok(store.write().await.add_answer(answer).await?)
+    }
+}
+
+/// Run a request against the schema with the data store and a fresh set of loaders, so that
+/// nothing loaded is cached between requests
+async fn execute(
+    store: Arc<RwLock<Store>>,
+    request: async_graphql::Request,
+) -> async_graphql::Response {
+    let loaders = store.read().await.clone();
+    let request = request
+        .data(DataLoader::new(
+            AnswersLoader(loaders.clone()),
+            tokio::spawn,
+        ))
+        .data(DataLoader::new(QuestionLoader(loaders), tokio::spawn))
+        .data(store);
+    graphql_schema().execute(request).await
+}
+
+/// Check whether the operation a request runs is a mutation
+fn is_mutation(request: &mut async_graphql::Request) -> bool {
+    let name = request.operation_name.clone();
+    match request.parsed_query() {
+        Ok(document) => document
+            .operations
+            .iter()
+            .filter(|(operation, _)| {
+                name.is_none() || operation.map(|o| o.as_str()) == name.as_deref()
+            })
+            .any(|(_, operation)| operation.node.ty == OperationType::Mutation),
+        // Let the schema report the syntax error
+        Err(_) => false,
+    }
+}
+
+/// Run a GraphQL query from the `graphql` route, or open the GraphiQL explorer
+/// # Example query
+/// GET requests to this route with a `query` param run it, they can only run queries so that
+/// they are rate limited as reads
+/// Without a `query` param the GraphiQL explorer is returned, it loads its scripts from a CDN
+/// `/graphql?query={question(id: 1){title tags answers{content}}}`
+#[utoipa::path(
+    get,
+    path = "/graphql",
+    tag = "graphql",
+    params(
+        ("query" = Option<String>, Query, description = "GraphQL query, the explorer is returned without one"),
+        ("operationName" = Option<String>, Query, description = "Operation to run if the query has several"),
+        ("variables" = Option<String>, Query, description = "JSON object of variables"),
+    ),
+    responses(
+        (status = 200, description = "GraphQL response, or the explorer page", body = Object),
+        (status = 400, description = "Parameters are invalid", body = String),
+        (status = 405, description = "The query is a mutation", body = String),
+    )
+)]
+pub async fn graphql_query(
+    State(store): State<Arc<RwLock<Store>>>,
+    OriginalUri(uri): OriginalUri,
+) -> Response {
+    let Some(query) = uri.query().filter(|q| !q.is_empty()) else {
+        let page = GraphiQLSource::build().endpoint(uri.path()).finish();
+        return Html(page).into_response();
+    };
+
+    let mut request = match parse_query_string(query) {
+        Ok(request) => request,
+        Err(e) => return (StatusCode::BAD_REQUEST, e.to_string()).into_response(),
+    };
+    if is_mutation(&mut request) {
+        return (
+            StatusCode::METHOD_NOT_ALLOWED,
+            "Mutations must be sent with POST".to_string(),
+        )
+            .into_response();
+    }
+
+    Json(execute(store, request).await).into_response()
+}
+
+/// Run a GraphQL query or mutation from the `graphql` route
+/// # Example query
+/// POST requests to this route have a json body with the query and its variables
+/// Nested questions and answers are loaded in batches, one database query per level
+/// `/graphql`
+/// `{
+///     "query": "mutation($q: NewQuestion!) { addQuestion(question: $q) { id } }",
+///     "variables": { "q": { "title": "New Question", "content": "Contents", "tags": ["rust"] } }
+/// }`
+#[utoipa::path(
+    post,
+    path = "/graphql",
+    tag = "graphql",
+    request_body(content = Object, description = "GraphQL request with `query`, `operationName` and `variables`"),
+    responses(
+        (status = 200, description = "GraphQL response with `data` and `errors`", body = Object),
+        (status = 400, description = "Body is not a GraphQL request", body = String),
+    )
+)]
+pub async fn graphql(
+    State(store): State<Arc<RwLock<Store>>>,
+    Json(request): Json<async_graphql::Request>,
+) -> Response {
+    Json(execute(store, request).await).into_response()
+}
@@ -0,0 +1,120 @@
+use crate::*;
+
+// Health Routes
+
+/// Version struct returned by the `version` route
+#[derive(Debug, Serialize, Clone, ToSchema)]
+pub struct Version {
+    pub version: &'static str,
+    pub git_hash: &'static str,
+    pub migration_version: Option<i64>,
+}
+
+/// Report that the server process is alive from the `healthz` route
+/// # Example query
+/// GET requests to this route do not touch the database so they only fail if the server is down
+/// `/healthz`
+#[utoipa::path(
+    get,
+    path = "/healthz",
+    tag = "health",
+    responses((status = 200, description = "Server is alive", body = String))
+)]
+pub async fn get_health() -> Response {
+    (StatusCode::OK, "OK".to_string()).into_response()
+}
+
+/// Report whether the server is ready to handle requests from the `readyz` route
+/// # Example query
+/// GET requests to this route ping the database and check that every migration has been applied
+/// `/readyz`
+#[utoipa::path(
+    get,
+    path = "/readyz",
+    tag = "health",
+    responses(
+        (status = 200, description = "Server is ready", body = String),
+        (status = 503, description = "Database is unavailable or migrations are pending", body = String),
+    )
+)]
+pub async fn get_ready(State(store): State<Arc<RwLock<Store>>>) -> Response {
+    let store = store.read().await;
+
+    // Make sure the database can be reached
+    if store.ping().await.is_err() {
+        return (
+            StatusCode::SERVICE_UNAVAILABLE,
+            Err::DatabaseUnavailable.to_string(),
+        )
+            .into_response();
+    }
+
+    // Compare the latest applied migration with the latest embedded migration
+    let latest = MIGRATOR.iter().map(|m| m.version).max();
+    match store.get_migration_version().await {
+        Ok(applied) if applied >= latest => (StatusCode::OK, "OK".to_string()).into_response(),
+        Ok(_) => (
+            StatusCode::SERVICE_UNAVAILABLE,
+            Err::MigrationsPending.to_string(),
+        )
+            .into_response(),
+        Err(_) => (
+            StatusCode::SERVICE_UNAVAILABLE,
+            Err::DatabaseUnavailable.to_string(),
+        )
+            .into_response(),
+    }
+}
+
+/// Fetch the build and schema versions from the `version` route
+/// # Example query
+/// GET requests to this route return the crate version, the git hash it was built from and the
+/// latest migration applied to the database, which is null if the database cannot be read
+/// `/version`
+#[utoipa::path(
+    get,
+    path = "/version",
+    tag = "health",
+    responses(
+        (status = 200, description = "Build and schema versions", body = Version),
+    )
+)]
+pub async fn get_version(State(store): State<Arc<RwLock<Store>>>) -> Response {
+    // The build versions are still returned when the database is down, they help debug it
+    let migration_version = store
+        .read()
+        .await
+        .get_migration_version()
+        .await
+        .unwrap_or(None);
+
+    (
+        StatusCode::OK,
+        Json(Version {
+            version: env!("CARGO_PKG_VERSION"),
+            git_hash: env!("GIT_HASH"),
+            migration_version,
+        }),
+    )
+        .into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn versions_are_returned_when_the_database_is_down() {
+        let store = Arc::new(RwLock::new(Store::offline()));
+        let response = get_version(State(store)).await;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let version: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(version["version"], env!("CARGO_PKG_VERSION"));
+        assert_eq!(version["git_hash"], env!("GIT_HASH"));
+        assert!(version["migration_version"].is_null());
+    }
+}
@@ -0,0 +1,557 @@
+use crate::*;
+
+/// Default maximum size of an import file in bytes
+const DEFAULT_MAX_IMPORT_SIZE: usize = 10 * 1024 * 1024;
+
+/// Maximum size of an import file in bytes, read from `MAX_IMPORT_SIZE`
+pub fn max_import_size() -> usize {
+    std::env::var("MAX_IMPORT_SIZE")
+        .ok()
+        .and_then(|size| size.parse::<usize>().ok())
+        .unwrap_or(DEFAULT_MAX_IMPORT_SIZE)
+}
+
+/// Import answer struct for an answer nested in an imported question
+#[derive(Debug, Serialize, Clone, Deserialize, ToSchema)]
+pub struct ImportAnswer {
+    pub content: String,
+}
+
+/// Import question struct for one row of an import file
+#[derive(Debug, Serialize, Clone, Deserialize, ToSchema)]
+pub struct ImportQuestion {
+    pub title: String,
+    pub content: String,
+    pub tags: Option<Vec<String>>,
+    #[serde(default)]
+    pub answers: Vec<ImportAnswer>,
+}
+
+impl ImportQuestion {
+    /// Check that the question and its answers are within the length limits
+    fn validate(&self) -> Result<(), Err> {
+        NewQuestion {
+            title: self.title.clone(),
+            content: self.content.clone(),
+            tags: None,
+        }
+        .validate()?;
+
+        self.answers.iter().try_for_each(|answer| {
+            NewAnswer {
+                content: answer.content.clone(),
+                corresponding_question: 0,
+            }
+            .validate()
+        })
+    }
+}
+
+/// Status of one row of an import
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ImportStatus {
+    Imported,
+    Valid,
+    Failed,
+}
+
+/// Import row struct that reports what happened to one row of an import file
+#[derive(Debug, Serialize, Clone, ToSchema)]
+pub struct ImportRow {
+    pub row: usize,
+    pub status: ImportStatus,
+    pub question_id: Option<i32>,
+    pub answers: usize,
+    pub error: Option<String>,
+}
+
+/// Import report struct returned by the `import` route
+#[derive(Debug, Serialize, Clone, ToSchema)]
+pub struct ImportReport {
+    pub dry_run: bool,
+    pub imported: usize,
+    pub failed: usize,
+    pub rows: Vec<ImportRow>,
+}
+
+/// Rows parsed from an import file, each with its line number and either the question or an error
+pub type ParsedRows = Vec<(usize, Result<ImportQuestion, String>)>;
+
+/// Parse a JSON Lines file with one question object per line, skipping blank lines
+fn parse_jsonl(body: &str) -> ParsedRows {
+    body.lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(i, line)| {
+            let question = serde_json::from_str::<ImportQuestion>(line).map_err(|e| e.to_string());
+            (i + 1, question)
+        })
+        .collect()
+}
+
+/// Parse the `tags` column of a CSV file
+/// Tags are separated by semicolons (`a;b`) or written as a Postgres array literal (`{a,b}`)
+fn parse_tags(cell: &str) -> Option<Vec<String>> {
+    let cell = cell.trim();
+    let tags: Vec<String> = match cell.strip_prefix('{').and_then(|c| c.strip_suffix('}')) {
+        Some(list) => list.split(',').map(str::to_owned).collect(),
+        None => cell.split(';').map(str::to_owned).collect(),
+    };
+    let tags: Vec<String> = tags
+        .into_iter()
+        .map(|tag| tag.trim().trim_matches('"').to_owned())
+        .filter(|tag| !tag.is_empty())
+        .collect();
+
+    if tags.is_empty() {
+        None
+    } else {
+        Some(tags)
+    }
+}
+
+/// Parse a CSV file with a header row
+/// The `title` and `content` columns are required, `tags` is optional and every column whose name
+/// starts with `answer` holds one answer, empty cells are skipped
+/// Rows with the same `id` are one question with the answers of every row, which is how the
+/// `export` route writes questions, and the `answer_id` and `answer_created_on` columns it adds
+/// are not answers
+fn parse_csv(body: &str) -> Result<ParsedRows, Err> {
+    let mut reader = csv::ReaderBuilder::new()
+        .flexible(true)
+        .from_reader(body.as_bytes());
+    let headers = reader
+        .headers()
+        .map_err(|e| Err::InvalidFile(e.to_string()))?
+        .clone();
+
+    // Find the columns by name so they can be in any order
+    let column = |name: &str| headers.iter().position(|h| h.trim() == name);
+    let id = column("id");
+    let title = column("title").ok_or(Err::MissingColumn("title"))?;
+    let content = column("content").ok_or(Err::MissingColumn("content"))?;
+    let tags = column("tags");
+    let answers: Vec<usize> = headers
+        .iter()
+        .enumerate()
+        .filter(|(_, h)| {
+            let h = h.trim();
+            h.starts_with("answer") && !h.ends_with("_id") && !h.ends_with("_created_on")
+        })
+        .map(|(i, _)| i)
+        .collect();
+
+    let mut rows: ParsedRows = Vec::new();
+    // Position in `rows` of the question with each id
+    let mut grouped: HashMap<String, usize> = HashMap::new();
+    for (i, record) in reader.records().enumerate() {
+        // Default to the record count plus the header row if the position is unknown
+        let line = match &record {
+            Ok(r) => r.position().map(|p| p.line() as usize),
+            Err(e) => e.position().map(|p| p.line() as usize),
+        }
+        .unwrap_or(i + 2);
+
+        let record = match record {
+            Ok(record) => record,
+            Err(e) => {
+                rows.push((line, Err(e.to_string())));
+                continue;
+            }
+        };
+        let cell = |i: usize| record.get(i).unwrap_or_default().to_owned();
+        let row_answers: Vec<ImportAnswer> = answers
+            .iter()
+            .map(|i| cell(*i))
+            .filter(|answer| !answer.trim().is_empty())
+            .map(|content| ImportAnswer { content })
+            .collect();
+
+        // Add the answers to the question an earlier row with the same id started
+        let key = id
+            .map(|i| cell(i).trim().to_owned())
+            .filter(|key| !key.is_empty());
+        if let Some(&position) = key.as_ref().and_then(|key| grouped.get(key)) {
+            if let (_, Ok(question)) = &mut rows[position] {
+                question.answers.extend(row_answers);
+            }
+            continue;
+        }
+        if let Some(key) = key {
+            grouped.insert(key, rows.len());
+        }
+
+        let question = ImportQuestion {
+            title: cell(title),
+            content: cell(content),
+            tags: tags.and_then(|i| parse_tags(&cell(i))),
+            answers: row_answers,
+        };
+        rows.push((line, Ok(question)));
+    }
+    Ok(rows)
+}
+
+/// Parse an import file in the given format, either `jsonl` (or `ndjson`) or `csv`
+pub fn parse_import(body: &str, format: &str) -> Result<ParsedRows, Err> {
+    match format {
+        "jsonl" | "ndjson" => Ok(parse_jsonl(body)),
+        "csv" => parse_csv(body),
+        _ => Err(Err::UnsupportedFormat(format.to_owned())),
+    }
+}
+
+/// Validated rows to import, with the index of their entry in the report
+type ValidRows = Vec<(usize, ImportQuestion)>;
+
+/// Validate the rows that parsed, returning a report entry for every row and the valid rows,
+/// which are the only ones sent to the database
+fn validate_rows(parsed: ParsedRows) -> (Vec<ImportRow>, ValidRows) {
+    let mut rows = Vec::new();
+    let mut valid = Vec::new();
+    for (line, question) in parsed {
+        let question = question.and_then(|q| q.validate().map(|_| q).map_err(|e| e.to_string()));
+        let (status, answers, error) = match &question {
+            Ok(q) => (ImportStatus::Valid, q.answers.len(), None),
+            Err(e) => (ImportStatus::Failed, 0, Some(e.clone())),
+        };
+        if let Ok(q) = question {
+            valid.push((rows.len(), q));
+        }
+        rows.push(ImportRow {
+            row: line,
+            status,
+            question_id: None,
+            answers,
+            error,
+        });
+    }
+    (rows, valid)
+}
+
+/// Fill in the report with what the database did with each valid row
+/// Rows the database rejects are reported as failed, the others are imported unless this is a
+/// dry run, in which case they stay valid
+fn build_report<E: ToString>(
+    mut rows: Vec<ImportRow>,
+    valid: &ValidRows,
+    results: Vec<Result<i32, E>>,
+    dry_run: bool,
+) -> ImportReport {
+    for ((index, _), result) in valid.iter().zip(results) {
+        let row = &mut rows[*index];
+        match result {
+            Ok(id) if !dry_run => {
+                row.status = ImportStatus::Imported;
+                row.question_id = Some(id);
+            }
+            Ok(_) => {}
+            Err(e) => {
+                row.status = ImportStatus::Failed;
+                row.answers = 0;
+                row.error = Some(e.to_string());
+            }
+        }
+    }
+
+    ImportReport {
+        dry_run,
+        imported: rows
+            .iter()
+            .filter(|r| r.status == ImportStatus::Imported)
+            .count(),
+        failed: rows
+            .iter()
+            .filter(|r| r.status == ImportStatus::Failed)
+            .count(),
+        rows,
+    }
+}
+
+/// Validate the parsed rows and insert the valid ones in a single transaction
+/// Every row gets an entry in the report, whether it failed to parse, failed validation, was
+/// rejected by the database or was imported
+pub async fn run_import(
+    store: &mut Store,
+    parsed: ParsedRows,
+    dry_run: bool,
+) -> Result<ImportReport, sqlx::Error> {
+    let (rows, valid) = validate_rows(parsed);
+    let questions: Vec<ImportQuestion> = valid.iter().map(|(_, q)| q.clone()).collect();
+    let results = store.import_questions(&questions, dry_run).await?;
+    Ok(build_report(rows, &valid, results, dry_run))
+}
+
+/// Import questions and their answers from the `import` route
+/// # Example query
+/// POST requests to this route have a JSON Lines or CSV body attached, every row is inserted in a
+/// single transaction and a report says which rows were imported and which failed
+/// The format is taken from `format=jsonl|csv` or from the `Content-Type` header and
+/// `dry_run=true` checks every row without keeping anything
+/// Only admins can import
+/// `/import?format=jsonl&dry_run=true`
+/// `{"title": "Question", "content": "Contents", "tags": ["example"], "answers": [{"content": "Answer"}]}`
+#[utoipa::path(
+    post,
+    path = "/import",
+    tag = "import",
+    params(
+        ("format" = Option<String>, Query, description = "`jsonl` or `csv`, defaults to the content type"),
+        ("dry_run" = Option<bool>, Query, description = "Validate the rows without keeping them"),
+    ),
+    request_body(content = String, description = "JSON Lines or CSV file", content_type = "application/x-ndjson"),
+    responses(
+        (status = 200, description = "Dry run report", body = ImportReport),
+        (status = 201, description = "Import report", body = ImportReport),
+        (status = 400, description = "File or parameters are invalid", body = String),
+        (status = 401, description = "Missing or wrong credentials", body = String),
+        (status = 403, description = "User is not an admin", body = String),
+    ),
+    security(("basic" = []))
+)]
+pub async fn import(
+    State(store): State<Arc<RwLock<Store>>>,
+    _admin: AdminUser,
+    Query(params): Query<HashMap<String, String>>,
+    headers: axum::http::HeaderMap,
+    body: String,
+) -> Response {
+    // Parse the dry run flag, the import is kept unless told otherwise
+    let dry_run = match params.get("dry_run").map(|d| d.parse::<bool>()) {
+        Some(Ok(dry_run)) => dry_run,
+        Some(Err(e)) => {
+            return (StatusCode::BAD_REQUEST, Err::ParseBool(e).to_string()).into_response()
+        }
+        None => false,
+    };
+
+    // Use the format parameter if given, otherwise guess from the content type
+    let format = match params.get("format") {
+        Some(format) => format.to_lowercase(),
+        None => match headers
+            .get(header::CONTENT_TYPE)
+            .and_then(|t| t.to_str().ok())
+        {
+            Some(t) if t.starts_with("text/csv") => "csv".to_owned(),
+            _ => "jsonl".to_owned(),
+        },
+    };
+    let parsed = match parse_import(&body, &format) {
+        Ok(parsed) => parsed,
+        Err(e) => return (StatusCode::BAD_REQUEST, e.to_string()).into_response(),
+    };
+
+    // Validate and insert the rows, reporting what happened to each one
+    let report = match run_import(&mut *store.write().await, parsed, dry_run).await {
+        Ok(report) => report,
+        Err(e) => return (StatusCode::BAD_REQUEST, e.to_string()).into_response(),
+    };
+    let status = if dry_run {
+        StatusCode::OK
+    } else {
+        StatusCode::CREATED
+    };
+    (status, Json(report)).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn question(row: &(usize, Result<ImportQuestion, String>)) -> &ImportQuestion {
+        row.1.as_ref().unwrap()
+    }
+
+    #[test]
+    fn csv_columns_can_be_in_any_order() {
+        let rows = parse_csv("tags,content,title\nrust,Body,Title\n").unwrap();
+        assert_eq!(rows.len(), 1);
+        assert_eq!(question(&rows[0]).title, "Title");
+        assert_eq!(question(&rows[0]).content, "Body");
+        assert_eq!(question(&rows[0]).tags, Some(vec!["rust".to_owned()]));
+
+        assert!(matches!(
+            parse_csv("title,body\nTitle,Body\n"),
+            Err(Err::MissingColumn("content"))
+        ));
+    }
+
+    #[test]
+    fn tags_can_be_a_list_or_an_array_literal() {
+        let tags = |tags: &[&str]| Some(tags.iter().map(|t| t.to_string()).collect::<Vec<_>>());
+        assert_eq!(parse_tags("a;b"), tags(&["a", "b"]));
+        assert_eq!(parse_tags("{a,b}"), tags(&["a", "b"]));
+        assert_eq!(parse_tags("{\"a b\", c}"), tags(&["a b", "c"]));
+        assert_eq!(parse_tags(" a ; ;b "), tags(&["a", "b"]));
+        assert_eq!(parse_tags(""), None);
+        assert_eq!(parse_tags("{}"), None);
+    }
+
+    #[test]
+    fn csv_answer_columns_skip_empty_cells() {
+        let rows = parse_csv("title,content,answer,answer_2,answers\nT,C,one,,three\n").unwrap();
+        let answers: Vec<&str> = question(&rows[0])
+            .answers
+            .iter()
+            .map(|a| a.content.as_str())
+            .collect();
+        assert_eq!(answers, ["one", "three"]);
+    }
+
+    #[tokio::test]
+    async fn exports_can_be_imported_back() {
+        use futures_util::TryStreamExt;
+
+        let created_on = parse_since("2024-05-06").unwrap();
+        let answer = |id: i32, content: &str| ExportAnswer {
+            id,
+            content: content.to_owned(),
+            created_on,
+        };
+        let exported = vec![
+            ExportQuestion {
+                id: 1,
+                title: "Answered".to_owned(),
+                content: "Two answers, one with a comma".to_owned(),
+                tags: Some(vec!["a".to_owned(), "b".to_owned()]),
+                created_on,
+                answers: vec![answer(7, "First"), answer(9, "Second, with a comma")],
+            },
+            ExportQuestion {
+                id: 2,
+                title: "Unanswered".to_owned(),
+                content: "No answers".to_owned(),
+                tags: None,
+                created_on,
+                answers: Vec::new(),
+            },
+        ];
+
+        for format in [ExportFormat::Csv, ExportFormat::Jsonl] {
+            let batches = futures_util::stream::iter([Ok(exported.clone())]);
+            let file: String = export_body(format, batches).try_collect().await.unwrap();
+            let name = if format == ExportFormat::Csv {
+                "csv"
+            } else {
+                "jsonl"
+            };
+            let rows = parse_import(&file, name).unwrap();
+
+            assert_eq!(rows.len(), exported.len(), "{}", file);
+            for (row, exported) in rows.iter().zip(&exported) {
+                let imported = question(row);
+                assert_eq!(imported.title, exported.title);
+                assert_eq!(imported.content, exported.content);
+                assert_eq!(imported.tags, exported.tags);
+                let answers: Vec<&str> = imported
+                    .answers
+                    .iter()
+                    .map(|a| a.content.as_str())
+                    .collect();
+                let expected: Vec<&str> = exported
+                    .answers
+                    .iter()
+                    .map(|a| a.content.as_str())
+                    .collect();
+                assert_eq!(answers, expected);
+            }
+        }
+    }
+
+    #[test]
+    fn short_csv_rows_are_read_and_lines_are_reported() {
+        // Flexible rows can leave out trailing columns, quoted newlines move the next line down
+        let rows = parse_csv("title,content,tags\nOne,C\n\"Two\nlines\",C,x\nThree,C,y\n").unwrap();
+        let lines: Vec<usize> = rows.iter().map(|(line, _)| *line).collect();
+        assert_eq!(lines, [2, 3, 5]);
+        assert_eq!(question(&rows[0]).tags, None);
+        assert_eq!(question(&rows[1]).title, "Two\nlines");
+    }
+
+    #[test]
+    fn jsonl_skips_blank_lines_and_keeps_line_numbers() {
+        let rows = parse_jsonl(
+            "{\"title\":\"A\",\"content\":\"B\"}\n\n{not json}\n{\"title\":\"C\",\"content\":\"D\",\"answers\":[{\"content\":\"E\"}]}\n",
+        );
+        let lines: Vec<usize> = rows.iter().map(|(line, _)| *line).collect();
+        assert_eq!(lines, [1, 3, 4]);
+        assert!(rows[1].1.is_err());
+        assert_eq!(question(&rows[2]).answers.len(), 1);
+    }
+
+    #[test]
+    fn unknown_formats_are_rejected() {
+        assert!(matches!(
+            parse_import("", "xml"),
+            Err(Err::UnsupportedFormat(_))
+        ));
+    }
+
+    #[test]
+    fn reports_map_each_row_to_a_status() {
+        let parsed: ParsedRows = vec![
+            (1, Err("bad json".to_owned())),
+            (
+                2,
+                Ok(ImportQuestion {
+                    title: "x".repeat(MAX_TITLE_LENGTH + 1),
+                    content: "C".to_owned(),
+                    tags: None,
+                    answers: Vec::new(),
+                }),
+            ),
+            (
+                3,
+                Ok(ImportQuestion {
+                    title: "Kept".to_owned(),
+                    content: "C".to_owned(),
+                    tags: None,
+                    answers: vec![ImportAnswer {
+                        content: "A".to_owned(),
+                    }],
+                }),
+            ),
+            (
+                4,
+                Ok(ImportQuestion {
+                    title: "Rejected".to_owned(),
+                    content: "C".to_owned(),
+                    tags: None,
+                    answers: vec![ImportAnswer {
+                        content: "A".to_owned(),
+                    }],
+                }),
+            ),
+        ];
+        let results = || vec![Ok(10), Err("duplicate key")];
+
+        // Only the valid rows are sent to the database, in order
+        let (rows, valid) = validate_rows(parsed.clone());
+        assert_eq!(valid.iter().map(|(i, _)| *i).collect::<Vec<_>>(), [2, 3]);
+
+        let report = build_report(rows, &valid, results(), false);
+        let statuses: Vec<ImportStatus> = report.rows.iter().map(|r| r.status).collect();
+        assert_eq!(
+            statuses,
+            [
+                ImportStatus::Failed,
+                ImportStatus::Failed,
+                ImportStatus::Imported,
+                ImportStatus::Failed
+            ]
+        );
+        assert_eq!((report.imported, report.failed), (1, 3));
+        assert_eq!(report.rows[2].question_id, Some(10));
+        assert_eq!(report.rows[3].answers, 0);
+        assert_eq!(report.rows[3].error.as_deref(), Some("duplicate key"));
+
+        // A dry run leaves the rows the database accepted as valid
+        let (rows, valid) = validate_rows(parsed);
+        let report = build_report(rows, &valid, results(), true);
+        assert_eq!(report.rows[2].status, ImportStatus::Valid);
+        assert_eq!(report.rows[2].question_id, None);
+        assert_eq!(report.rows[2].answers, 1);
+        assert_eq!((report.imported, report.failed), (0, 3));
+    }
+}
@@ -0,0 +1,214 @@
+mod answer;
+mod api;
+mod attachment;
+mod deprecation;
+mod events;
+mod export;
+mod graphql;
+mod health;
+mod import;
+pub mod logging;
+mod metrics;
+mod openapi;
+mod outbox;
+mod question;
+mod quiz;
+mod rate_limit;
+mod related;
+mod render;
+mod seed;
+mod shutdown;
+mod socket;
+mod store;
+mod study;
+#[cfg(feature = "otel")]
+mod telemetry;
+mod user;
+mod webhook;
+
+pub use answer::*;
+pub use api::*;
+use async_graphql::{ComplexObject, Enum, InputObject, SimpleObject};
+pub use attachment::*;
+use axum::{
+    extract::{ConnectInfo, DefaultBodyLimit, Extension, MatchedPath, Path, Query, State},
+    http::{header, Method, Request, StatusCode},
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    routing::{delete, get, post, put},
+    Json, Router,
+};
+use chrono::NaiveDateTime;
+pub use deprecation::*;
+pub use events::*;
+pub use export::*;
+use futures_util::Stream;
+pub use graphql::*;
+pub use health::*;
+pub use import::*;
+pub use logging::*;
+pub use metrics::*;
+pub use openapi::*;
+pub use outbox::*;
+pub use question::*;
+pub use quiz::*;
+pub use rate_limit::*;
+pub use related::*;
+pub use render::*;
+pub use seed::*;
+use serde::{Deserialize, Serialize};
+pub use shutdown::*;
+pub use socket::*;
+use sqlx::migrate::Migrator;
+use sqlx::postgres::{PgConnection, PgPool, PgRow, Postgres};
+use sqlx::{Connection, Row, Transaction};
+use std::error::Error;
+use std::sync::Arc;
+use std::time::Duration;
+use std::{collections::HashMap, net::SocketAddr};
+pub use store::*;
+pub use study::*;
+use tokio::{self, sync::RwLock};
+use tracing::info_span;
+pub use user::*;
+use utoipa::ToSchema;
+pub use webhook::*;
+
+/// Handler to return an error message if a route cannot be found
+async fn return_error() -> Response {
+    (StatusCode::NOT_FOUND, "Route not found").into_response()
+}
+
+/// Paths of the routes in `v1`, the OpenAPI tests try every method on each of them against the
+/// router to check that whatever is served is documented, so new paths have to be added here too
+#[cfg(test)]
+pub(crate) const V1_PATHS: &[&str] = &[
+    "/questions",
+    "/question",
+    "/question/:id",
+    "/answers",
+    "/answer",
+    "/import",
+    "/export",
+    "/seed",
+    "/events",
+    "/ws/question/:id",
+    "/webhooks",
+    "/webhook/:id",
+    "/webhook/:id/deliveries",
+    "/webhooks/dead-letters",
+    "/webhooks/dead-letters/:id/retry",
+    "/graphql",
+    "/study/next",
+    "/study/:id/answers",
+    "/study/:id/grade",
+    "/question/:id/related",
+    "/question/:id/mark-duplicate/:other",
+    "/render/preview",
+    "/quiz",
+    "/quiz/:token/question/:id",
+    "/quiz/:token/results",
+    "/question/:id/attachments",
+    "/answer/:id/attachments",
+    "/attachment/:id",
+];
+
+/// Paths of the operational routes added in `routes`, checked the same way as `V1_PATHS`
+#[cfg(test)]
+pub(crate) const OPERATIONAL_PATHS: &[&str] = &["/healthz", "/readyz", "/version", "/metrics"];
+
+/// Create a router with the version 1 routes for questions and answers
+/// A new version can be added next to this one, reusing the same handlers and data store methods
+/// for anything that has not changed
+fn v1() -> Router<Arc<RwLock<Store>>> {
+    Router::new()
+        .route("/questions", get(get_questions))
+        .route("/question/:id", get(get_question))
+        .route("/question", get(get_random_question))
+        .route("/question", post(add_question))
+        .route("/question/:id", put(update_question))
+        .route("/question/:id", delete(delete_question))
+        .route("/answers", get(get_answers))
+        .route("/answer", post(add_answer))
+        .route(
+            "/import",
+            post(import).layer(DefaultBodyLimit::max(max_import_size())),
+        )
+        .route("/export", get(export))
+        .route("/seed", post(seed_database))
+        .route("/events", get(get_events))
+        .route("/ws/question/:id", get(question_socket))
+        .route("/webhooks", get(get_webhooks))
+        .route("/webhooks", post(add_webhook))
+        .route("/webhook/:id", get(get_webhook))
+        .route("/webhook/:id", put(update_webhook))
+        .route("/webhook/:id", delete(delete_webhook))
+        .route("/webhook/:id/deliveries", get(get_webhook_deliveries))
+        .route("/webhooks/dead-letters", get(get_dead_letters))
+        .route("/webhooks/dead-letters/:id/retry", post(retry_dead_letter))
+        .route("/graphql", get(graphql_query))
+        .route("/graphql", post(graphql))
+        .route("/study/next", get(get_next_study_card))
+        .route("/study/:id/answers", get(reveal_study_answers))
+        .route("/study/:id/grade", post(grade_study_card))
+        .route("/question/:id/related", get(get_related_questions))
+        .route(
+            "/question/:id/mark-duplicate/:other",
+            post(mark_duplicate_question),
+        )
+        .route("/render/preview", post(preview_markdown))
+        .route("/quiz", post(add_quiz))
+        .route("/quiz/:token/question/:id", post(submit_quiz_answer))
+        .route("/quiz/:token/results", get(get_quiz_results))
+        .route(
+            "/question/:id/attachments",
+            post(add_question_attachment).layer(DefaultBodyLimit::max(max_upload_size())),
+        )
+        .route("/question/:id/attachments", get(get_question_attachments))
+        .route(
+            "/answer/:id/attachments",
+            post(add_answer_attachment).layer(DefaultBodyLimit::max(max_upload_size())),
+        )
+        .route("/answer/:id/attachments", get(get_answer_attachments))
+        .route("/attachment/:id", get(get_attachment))
+}
+
+/// Create a router with the routes that were served at the root before the API was versioned
+/// Only these are kept as aliases of the version 1 routes, newer routes are only under `/api/v1`
+fn unversioned() -> Router<Arc<RwLock<Store>>> {
+    Router::new()
+        .route("/questions", get(get_questions))
+        .route("/question/:id", get(get_question))
+        .route("/question", get(get_random_question))
+        .route("/question", post(add_question))
+        .route("/question/:id", put(update_question))
+        .route("/question/:id", delete(delete_question))
+        .route("/answers", get(get_answers))
+        .route("/answer", post(add_answer))
+}
+
+/// Create a router with every versioned API and the operational routes
+/// The routes that predate versioning are also served at the root for older clients, with
+/// deprecation headers
+/// Only the API routes are rate limited, so health checks and metrics scrapes from an address
+/// that also sends API traffic keep working
+/// Fallback calls the error handler if the route cannot be found
+pub fn routes(limiter: RateLimiter) -> Router<Arc<RwLock<Store>>> {
+    let rate_limit = middleware::from_fn_with_state(limiter, rate_limit);
+    Router::new()
+        .nest("/api/v1", v1().layer(rate_limit.clone()))
+        .merge(
+            unversioned()
+                .layer(middleware::from_fn_with_state(
+                    Deprecation::new("/api/v1"),
+                    deprecated,
+                ))
+                .layer(rate_limit),
+        )
+        .route("/healthz", get(get_health))
+        .route("/readyz", get(get_ready))
+        .route("/version", get(get_version))
+        .route("/metrics", get(get_metrics))
+        .merge(docs())
+        .fallback(return_error)
+}
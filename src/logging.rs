@@ -0,0 +1,104 @@
+use crate::*;
+use tracing_subscriber::{
+    fmt::format::FmtSpan, layer::SubscriberExt, util::SubscriberInitExt, EnvFilter, Layer,
+};
+
+/// Header used to read and return the request id
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// Longest request id accepted from a client
+const MAX_REQUEST_ID_LENGTH: usize = 128;
+
+/// Set up tracing so that logs are printed to the console
+/// `RUST_LOG` sets the filter, which is this crate at `info` by default, the spans of the
+/// background workers that poll every second are at `debug` so they only show up when asked for
+/// `LOG_FORMAT=json` switches to one JSON object per line so that a log pipeline can parse the
+/// output, anything else keeps the human readable format
+pub fn init() {
+    let log_filter = std::env::var("RUST_LOG").unwrap_or_else(|_| "rustproject=info".to_owned());
+    let log_format = std::env::var("LOG_FORMAT").unwrap_or_default();
+
+    let fmt_layer = tracing_subscriber::fmt::layer().with_span_events(FmtSpan::CLOSE);
+
+    // The JSON format includes the fields of every enclosing span, so events logged by the data
+    // store carry the request id recorded on the `http_request` span
+    let fmt_layer = if log_format.eq_ignore_ascii_case("json") {
+        fmt_layer.json().with_span_list(true).boxed()
+    } else {
+        fmt_layer.boxed()
+    };
+
+    let subscriber = tracing_subscriber::registry()
+        .with(EnvFilter::new(log_filter))
+        .with(fmt_layer);
+
+    // Export spans to an OTLP collector when the server is built with the `otel` feature
+    #[cfg(feature = "otel")]
+    let subscriber = subscriber.with(telemetry::layer());
+
+    subscriber.init();
+}
+
+/// Check that a request id sent by a client is short and only has letters, digits and `-`, `_`,
+/// `.` or `:`, so that it cannot flood or break the logs
+pub fn is_valid_request_id(id: &str) -> bool {
+    !id.is_empty()
+        && id.len() <= MAX_REQUEST_ID_LENGTH
+        && id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.' | ':'))
+}
+
+/// Middleware to drop a request id sent by a client that is not valid, so that the request id
+/// layer generates a new one instead
+pub async fn drop_invalid_request_id(
+    mut request: Request<axum::body::Body>,
+) -> Request<axum::body::Body> {
+    let valid = request
+        .headers()
+        .get(REQUEST_ID_HEADER)
+        .map(|id| id.to_str().is_ok_and(is_valid_request_id));
+    if valid == Some(false) {
+        request.headers_mut().remove(REQUEST_ID_HEADER);
+    }
+    request
+}
+
+/// Create the span for each request handled by the trace layer
+/// The request id is set by the request id layer before the trace layer runs, either from the
+/// `X-Request-Id` header sent by the client or a newly generated one
+pub fn make_request_span(request: &Request<axum::body::Body>) -> tracing::Span {
+    let matched_path = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(MatchedPath::as_str);
+
+    let request_id = request
+        .headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|id| id.to_str().ok())
+        .filter(|id| is_valid_request_id(id))
+        .unwrap_or_default();
+
+    info_span!(
+        "http_request",
+        method = ?request.method(),
+        matched_path,
+        request_id,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn request_ids_are_checked() {
+        assert!(is_valid_request_id("6f1c2b9e-3d4a-4c55-9a51-0c2f4e1b7d21"));
+        assert!(is_valid_request_id("client.trace:42_a"));
+        assert!(!is_valid_request_id(""));
+        assert!(!is_valid_request_id("id with spaces"));
+        assert!(!is_valid_request_id("id\"}"));
+        assert!(!is_valid_request_id(&"a".repeat(MAX_REQUEST_ID_LENGTH + 1)));
+    }
+}
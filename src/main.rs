@@ -1,48 +1,22 @@
-mod answer;
-mod api;
-mod question;
-mod store;
-
-use answer::*;
-use api::*;
-use axum::{
-    extract::{MatchedPath, Path, Query, State},
-    http::{Method, Request, StatusCode},
-    response::{IntoResponse, Response},
-    routing::{delete, get, post, put},
-    Json, Router,
-};
-use question::*;
-use serde::{Deserialize, Serialize};
-use sqlx::postgres::{PgPool, PgRow};
-use sqlx::Row;
-use std::error::Error;
+use axum::{extract::DefaultBodyLimit, http::Method, middleware};
+use rustproject::*;
+use std::future::IntoFuture;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
 use std::sync::Arc;
-use std::{
-    collections::HashMap,
-    net::{IpAddr, Ipv4Addr, SocketAddr},
+use tokio::sync::RwLock;
+use tower_http::{
+    cors,
+    request_id::{MakeRequestUuid, PropagateRequestIdLayer, SetRequestIdLayer},
+    trace::TraceLayer,
 };
-use store::*;
-use tokio::{self, sync::RwLock};
-use tower_http::{cors, trace::TraceLayer};
-use tracing::info_span;
-use tracing_subscriber::fmt::format::FmtSpan;
-
-/// Handler to return an error message if a route cannot be found
-async fn return_error() -> Response {
-    (StatusCode::NOT_FOUND, "Route not found").into_response()
-}
+
+/// Default maximum size of a request body in bytes
+const DEFAULT_MAX_BODY_SIZE: usize = 64 * 1024;
 
 #[tokio::main]
 async fn main() {
     // Set up tracing in order to get tracing information printed to the console
-    let log_filter =
-        std::env::var("RUST_LOG").unwrap_or_else(|_| "practical_rust_book=info".to_owned());
-
-    tracing_subscriber::fmt()
-        .with_env_filter(log_filter)
-        .with_span_events(FmtSpan::CLOSE)
-        .init();
+    logging::init();
 
     // Set up a CORS layer
     let cors = cors::CorsLayer::new()
@@ -57,45 +31,83 @@ async fn main() {
     });
     tracing::info!("Connected to database");
 
+    // Keep a handle to the metrics so the middleware can record requests without locking the store
+    let metrics = store.metrics.clone();
+    let events = store.events.clone();
+    let relay = OutboxRelay::new(store.clone());
+    let webhooks = WebhookWorker::new(store.clone());
+
+    // Set up the rate limits and the maximum request body size
+    let limiter = RateLimiter::new();
+    let max_body_size = std::env::var("MAX_BODY_SIZE")
+        .ok()
+        .and_then(|size| size.parse::<usize>().ok())
+        .unwrap_or(DEFAULT_MAX_BODY_SIZE);
+
     // Make sure the data store can be accessed by multiple threads safely
     let store = Arc::new(RwLock::new(store));
 
-    // Create an app with a handler for questions
-    // Fallback calls the error handler if the route cannot be found
-    let app = Router::new()
-        .route("/questions", get(get_questions))
-        .route("/question/:id", get(get_question))
-        .route("/question", get(get_random_question))
-        .route("/question", post(add_question))
-        .route("/question/:id", put(update_question))
-        .route("/question/:id", delete(delete_question))
-        .route("/answers", get(get_answers))
-        .route("/answer", post(add_answer))
-        .fallback(return_error)
+    // Create an app with a handler for each route
+    let app = routes(limiter.clone())
+        .layer(DefaultBodyLimit::max(max_body_size))
         .layer(cors)
+        // Copy the request id onto the response so clients can quote it
+        .layer(PropagateRequestIdLayer::new(
+            REQUEST_ID_HEADER.parse().unwrap(),
+        ))
         // Source for trace layer code: https://github.com/tokio-rs/axum/blob/main/examples/tracing-aka-logging/src/main.rs
-        .layer(
-            TraceLayer::new_for_http().make_span_with(|request: &Request<_>| {
-                let matched_path = request
-                    .extensions()
-                    .get::<MatchedPath>()
-                    .map(MatchedPath::as_str);
-
-                info_span!(
-                    "http_request",
-                    method = ?request.method(),
-                    matched_path,
-                    some_other_field = tracing::field::Empty,
-                )
-            }),
-        )
-        .with_state(store);
+        .layer(TraceLayer::new_for_http().make_span_with(make_request_span))
+        .layer(middleware::from_fn_with_state(metrics, track_metrics))
+        // Honour the client's `X-Request-Id` or generate one, this must wrap the trace layer
+        .layer(SetRequestIdLayer::new(
+            REQUEST_ID_HEADER.parse().unwrap(),
+            MakeRequestUuid,
+        ))
+        // Replace request ids from clients that are too long or have unexpected characters
+        .layer(middleware::map_request(drop_invalid_request_id))
+        .with_state(store.clone());
 
     // Host the app on 0.0.0.0 so that it can be accessed outside the docker container
     let ip = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0)), 3000);
 
-    // Run the app
+    // Hash the password checked for unknown usernames before the first login needs it
+    tokio::task::spawn_blocking(dummy_password_hash);
+
+    // Listen for SIGINT and SIGTERM so that the app can shut down gracefully
+    let shutdown = Shutdown::new();
+    tokio::spawn(shutdown.clone().listen());
+
+    // Clear out idle rate limit buckets in the background
+    shutdown
+        .tracker
+        .spawn(limiter.run_cleanup(shutdown.clone()));
+
+    // End the open event streams once shutdown starts so they do not hold up the drain
+    shutdown
+        .tracker
+        .spawn(events.close_on_shutdown(shutdown.clone()));
+
+    // Publish the committed changes from the outbox and send the webhook deliveries it queues
+    shutdown.tracker.spawn(relay.run(shutdown.clone()));
+    shutdown
+        .tracker
+        .spawn(webhooks.run_deliveries(shutdown.clone()));
+
+    // Run the app, once a signal is received stop accepting connections and drain the in-flight
+    // requests, giving up if they take longer than the shutdown timeout
     let listener = tokio::net::TcpListener::bind(ip).await.unwrap();
     tracing::info!("Listening {}", listener.local_addr().unwrap());
-    axum::serve(listener, app).await.unwrap();
+    let server = axum::serve(
+        listener,
+        app.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .with_graceful_shutdown(shutdown.token.clone().cancelled_owned())
+    .into_future();
+    tokio::select! {
+        res = server => res.unwrap(),
+        _ = shutdown.deadline() => tracing::warn!("Timed out draining in-flight requests"),
+    }
+
+    // Stop the background tasks and close the connection pool
+    shutdown.finish(store).await;
 }
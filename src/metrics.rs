@@ -0,0 +1,253 @@
+use crate::*;
+use prometheus::{
+    Encoder, HistogramOpts, HistogramTimer, HistogramVec, IntCounter, IntCounterVec, IntGauge,
+    IntGaugeVec, Opts, Registry, TextEncoder,
+};
+use std::sync::Mutex;
+use std::time::Instant;
+
+/// How long the question and answer totals are kept before they are counted again, so that
+/// scrapes do not count every row each time
+const TOTALS_REFRESH_INTERVAL: Duration = Duration::from_secs(30);
+
+/// How long a scrape waits for the totals before it gives up and returns the other metrics
+const TOTALS_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Metrics struct that holds every collector exposed on the `metrics` route
+/// Collectors are reference counted internally, so clones share the same values
+#[derive(Clone)]
+pub struct Metrics {
+    registry: Registry,
+    http_requests: IntCounterVec,
+    http_duration: HistogramVec,
+    store_duration: HistogramVec,
+    pool_connections: IntGaugeVec,
+    questions: IntGauge,
+    answers: IntGauge,
+    totals_errors: IntCounter,
+    totals_refreshed: Arc<Mutex<Option<Instant>>>,
+}
+
+impl Metrics {
+    /// Constructor to create the collectors and register them with a new registry
+    pub fn new() -> Result<Self, prometheus::Error> {
+        let registry = Registry::new();
+
+        let http_requests = IntCounterVec::new(
+            Opts::new("http_requests_total", "Number of HTTP requests handled"),
+            &["method", "path", "status"],
+        )?;
+        let http_duration = HistogramVec::new(
+            HistogramOpts::new(
+                "http_request_duration_seconds",
+                "Time spent handling HTTP requests",
+            ),
+            &["method", "path", "status"],
+        )?;
+        let store_duration = HistogramVec::new(
+            HistogramOpts::new(
+                "store_method_duration_seconds",
+                "Time spent in each data store method",
+            ),
+            &["method"],
+        )?;
+        let pool_connections = IntGaugeVec::new(
+            Opts::new(
+                "db_pool_connections",
+                "Connections held by the database pool",
+            ),
+            &["state"],
+        )?;
+        let questions = IntGauge::new("questions_count", "Number of questions in the database")?;
+        let answers = IntGauge::new("answers_count", "Number of answers in the database")?;
+        let totals_errors = IntCounter::new(
+            "questions_count_refresh_errors_total",
+            "Number of times the question and answer totals could not be counted",
+        )?;
+
+        registry.register(Box::new(http_requests.clone()))?;
+        registry.register(Box::new(http_duration.clone()))?;
+        registry.register(Box::new(store_duration.clone()))?;
+        registry.register(Box::new(pool_connections.clone()))?;
+        registry.register(Box::new(questions.clone()))?;
+        registry.register(Box::new(answers.clone()))?;
+        registry.register(Box::new(totals_errors.clone()))?;
+
+        Ok(Metrics {
+            registry,
+            http_requests,
+            http_duration,
+            store_duration,
+            pool_connections,
+            questions,
+            answers,
+            totals_errors,
+            totals_refreshed: Arc::new(Mutex::new(None)),
+        })
+    }
+
+    /// Check whether the totals are due to be counted again, marking them as refreshed if so
+    /// Failed refreshes are not retried before the interval is up either, so a database that is
+    /// down does not slow every scrape
+    fn totals_due(&self) -> bool {
+        let mut refreshed = self.totals_refreshed.lock().unwrap();
+        if refreshed.is_some_and(|refreshed| refreshed.elapsed() < TOTALS_REFRESH_INTERVAL) {
+            return false;
+        }
+        *refreshed = Some(Instant::now());
+        true
+    }
+
+    /// Start timing a data store method, the time is recorded when the timer is dropped
+    pub fn time_store(&self, method: &str) -> HistogramTimer {
+        self.store_duration
+            .with_label_values(&[method])
+            .start_timer()
+    }
+
+    /// Record a handled HTTP request and how long it took
+    fn observe_http(&self, method: &str, path: &str, status: &str, seconds: f64) {
+        self.http_requests
+            .with_label_values(&[method, path, status])
+            .inc();
+        self.http_duration
+            .with_label_values(&[method, path, status])
+            .observe(seconds);
+    }
+
+    /// Encode every registered collector in the Prometheus text format
+    fn encode(&self) -> Result<String, prometheus::Error> {
+        let mut buffer = Vec::new();
+        TextEncoder::new().encode(&self.registry.gather(), &mut buffer)?;
+        Ok(String::from_utf8_lossy(&buffer).into_owned())
+    }
+}
+
+/// Middleware to count requests and time them, labelled by method, matched path and status code
+/// Requests that do not match a route share one label so unknown paths cannot flood the registry
+pub async fn track_metrics(
+    State(metrics): State<Metrics>,
+    request: Request<axum::body::Body>,
+    next: Next,
+) -> Response {
+    let start = Instant::now();
+    let method = request.method().to_string();
+    let path = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|p| p.as_str().to_owned())
+        .unwrap_or_else(|| "unmatched".to_owned());
+
+    let response = next.run(request).await;
+
+    metrics.observe_http(
+        &method,
+        &path,
+        response.status().as_str(),
+        start.elapsed().as_secs_f64(),
+    );
+    response
+}
+
+/// Fetch the server metrics from the `metrics` route in the Prometheus text format
+/// # Example query
+/// GET requests to this route refresh the database gauges before returning every metric
+/// The question and answer totals are counted at most every 30 seconds, if that fails they keep
+/// their last value, `questions_count_refresh_errors_total` goes up and the other metrics are
+/// still returned
+/// `/metrics`
+#[utoipa::path(
+    get,
+    path = "/metrics",
+    tag = "health",
+    responses(
+        (status = 200, description = "Metrics in the Prometheus text format", body = String, content_type = "text/plain"),
+    )
+)]
+pub async fn get_metrics(State(store): State<Arc<RwLock<Store>>>) -> Response {
+    let store = store.read().await;
+    let metrics = &store.metrics;
+
+    // Refresh the connection pool gauges
+    let size = store.connection.size() as i64;
+    let idle = store.connection.num_idle() as i64;
+    metrics
+        .pool_connections
+        .with_label_values(&["idle"])
+        .set(idle);
+    metrics
+        .pool_connections
+        .with_label_values(&["active"])
+        .set(size - idle);
+
+    // Refresh the question and answer totals if they are due
+    if metrics.totals_due() {
+        match tokio::time::timeout(TOTALS_TIMEOUT, store.get_totals()).await {
+            Ok(Ok((questions, answers))) => {
+                metrics.questions.set(questions);
+                metrics.answers.set(answers);
+            }
+            Ok(Err(e)) => {
+                tracing::event!(tracing::Level::WARN, "could not count the totals: {:?}", e);
+                metrics.totals_errors.inc();
+            }
+            Err(_) => {
+                tracing::event!(tracing::Level::WARN, "counting the totals timed out");
+                metrics.totals_errors.inc();
+            }
+        }
+    }
+
+    match metrics.encode() {
+        Ok(body) => (
+            StatusCode::OK,
+            [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+            body,
+        )
+            .into_response(),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn scrape(store: &Arc<RwLock<Store>>) -> (StatusCode, String) {
+        let response = get_metrics(State(store.clone())).await;
+        let status = response.status();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        (status, String::from_utf8(body.to_vec()).unwrap())
+    }
+
+    #[tokio::test]
+    async fn metrics_are_served_when_the_database_is_down() {
+        let store = Arc::new(RwLock::new(Store::offline()));
+        store
+            .read()
+            .await
+            .metrics
+            .observe_http("GET", "/healthz", "200", 0.1);
+
+        let (status, body) = scrape(&store).await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(
+            body.contains("questions_count_refresh_errors_total 1"),
+            "{}",
+            body
+        );
+        assert!(body.contains("http_requests_total{"), "{}", body);
+        assert!(body.contains("db_pool_connections{"), "{}", body);
+
+        // The totals are not counted again until the interval is up
+        let (status, body) = scrape(&store).await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(
+            body.contains("questions_count_refresh_errors_total 1"),
+            "{}",
+            body
+        );
+    }
+}
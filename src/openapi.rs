@@ -0,0 +1,333 @@
+use crate::*;
+use utoipa::{Modify, OpenApi};
+use utoipa_swagger_ui::SwaggerUi;
+
+/// OpenAPI document generated from the handler annotations and the data types
+/// Versioned APIs are nested under their prefix, the deprecated unversioned aliases are left out
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "Questions and Answers API",
+        description = "REST API to manage a database of questions and answers",
+        license(name = "MIT")
+    ),
+    paths(get_health, get_ready, get_version, get_metrics),
+    components(schemas(Version)),
+    modifiers(&NestV1, &BasicAuth),
+    tags(
+        (name = "questions", description = "Create, read, update and delete questions"),
+        (name = "answers", description = "Read and create answers"),
+        (name = "import", description = "Bulk import questions and answers"),
+        (name = "export", description = "Stream every question and answer"),
+        (name = "events", description = "Live changes to questions and answers over SSE and WebSockets"),
+        (name = "webhooks", description = "Signed notifications of changes sent to other services, needs an admin login"),
+        (name = "graphql", description = "Query and change questions and answers with GraphQL"),
+        (name = "attachments", description = "Files and images attached to questions and answers"),
+        (name = "render", description = "Preview of how CommonMark content is rendered"),
+        (name = "quiz", description = "Timed quizzes drawn from the questions, scored against their answers"),
+        (name = "study", description = "Spaced repetition over the questions, needs a user login"),
+        (name = "seed", description = "Load sample data, disabled unless `ALLOW_SEEDING=true`"),
+        (name = "health", description = "Liveness, readiness, version and metrics"),
+    )
+)]
+pub struct ApiDoc;
+
+/// OpenAPI document for the version 1 routes
+#[derive(OpenApi)]
+#[openapi(
+    paths(
+        get_questions,
+        get_question,
+        get_random_question,
+        add_question,
+        update_question,
+        delete_question,
+        get_answers,
+        add_answer,
+        import,
+        export,
+        seed_database,
+        get_events,
+        question_socket,
+        get_webhooks,
+        add_webhook,
+        get_webhook,
+        update_webhook,
+        delete_webhook,
+        get_webhook_deliveries,
+        get_dead_letters,
+        retry_dead_letter,
+        graphql_query,
+        graphql,
+        get_next_study_card,
+        reveal_study_answers,
+        grade_study_card,
+        get_related_questions,
+        mark_duplicate_question,
+        add_quiz,
+        submit_quiz_answer,
+        get_quiz_results,
+        preview_markdown,
+        add_question_attachment,
+        get_question_attachments,
+        add_answer_attachment,
+        get_answer_attachments,
+        get_attachment,
+    ),
+    components(schemas(
+        RenderedQuestion,
+        QuestionWithAnswers,
+        NewQuestion,
+        RenderedAnswer,
+        NewAnswer,
+        ImportQuestion,
+        ImportAnswer,
+        ImportStatus,
+        ImportRow,
+        ImportReport,
+        ExportQuestion,
+        ExportAnswer,
+        SeedSet,
+        SeedReport,
+        ChangeKind,
+        ChangeEvent,
+        Webhook,
+        NewWebhook,
+        DeliveryStatus,
+        WebhookDelivery,
+        StudyCard,
+        Review,
+        Grade,
+        AddedQuestion,
+        DuplicateCandidate,
+        DuplicateConflict,
+        Duplicate,
+        RelatedQuestion,
+        NewQuiz,
+        Quiz,
+        NewQuizAnswer,
+        QuizAnswer,
+        QuizQuestionResult,
+        QuizResults,
+        Preview,
+        Rendered,
+        Attachment
+    ))
+)]
+pub struct V1Doc;
+
+/// Modifier that nests the version 1 document under `/api/v1`
+pub struct NestV1;
+
+impl Modify for NestV1 {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        openapi.merge(nest("/api/v1", V1Doc::openapi()));
+    }
+}
+
+/// Modifier that adds the HTTP basic login used by the routes that act for a user
+pub struct BasicAuth;
+
+impl Modify for BasicAuth {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        use utoipa::openapi::security::{HttpAuthScheme, HttpBuilder, SecurityScheme};
+
+        openapi
+            .components
+            .get_or_insert_with(Default::default)
+            .add_security_scheme(
+                "basic",
+                SecurityScheme::Http(HttpBuilder::new().scheme(HttpAuthScheme::Basic).build()),
+            );
+    }
+}
+
+/// Prefix every path in a document, the same way `Router::nest` prefixes every route
+fn nest(prefix: &str, mut openapi: utoipa::openapi::OpenApi) -> utoipa::openapi::OpenApi {
+    openapi.paths.paths = std::mem::take(&mut openapi.paths.paths)
+        .into_iter()
+        .map(|(path, item)| (format!("{}{}", prefix, path), item))
+        .collect();
+    openapi
+}
+
+/// Create a router that serves the OpenAPI document at `/openapi.json` and the Swagger UI docs
+/// page at `/docs`
+/// The Swagger UI files are bundled into the binary so the page works without a CDN
+pub fn docs<S>() -> Router<S>
+where
+    S: Clone + Send + Sync + 'static,
+{
+    SwaggerUi::new("/docs")
+        .url("/openapi.json", ApiDoc::openapi())
+        .into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeSet;
+    use std::time::Duration;
+    use tower::ServiceExt;
+    use utoipa::openapi::PathItemType;
+
+    /// Collect every `(method, path)` pair documented in the OpenAPI document
+    fn documented_routes() -> BTreeSet<(String, String)> {
+        ApiDoc::openapi()
+            .paths
+            .paths
+            .iter()
+            .flat_map(|(path, item)| {
+                item.operations.keys().map(move |method| {
+                    let method = match method {
+                        PathItemType::Get => "get",
+                        PathItemType::Post => "post",
+                        PathItemType::Put => "put",
+                        PathItemType::Delete => "delete",
+                        PathItemType::Options => "options",
+                        PathItemType::Head => "head",
+                        PathItemType::Patch => "patch",
+                        PathItemType::Trace => "trace",
+                        PathItemType::Connect => "connect",
+                    };
+                    (method.to_owned(), path.clone())
+                })
+            })
+            .collect()
+    }
+
+    /// Send a request with an empty body and return the status and, if it ends in time, the body
+    /// Streamed bodies fail part way through without a database and event streams never end,
+    /// both of which still mean the route was matched
+    async fn probe(app: &Router, method: &str, uri: &str) -> (StatusCode, axum::body::Bytes) {
+        let request = Request::builder()
+            .method(method)
+            .uri(uri)
+            .body(axum::body::Body::empty())
+            .unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        let status = response.status();
+        let body = tokio::time::timeout(
+            Duration::from_secs(1),
+            axum::body::to_bytes(response.into_body(), usize::MAX),
+        )
+        .await
+        .ok()
+        .and_then(Result::ok)
+        .unwrap_or_default();
+        (status, body)
+    }
+
+    /// Check whether a probe matched a route rather than the fallback or a path without the method
+    fn is_routed((status, body): &(StatusCode, axum::body::Bytes)) -> bool {
+        *status != StatusCode::METHOD_NOT_ALLOWED && &body[..] != b"Route not found"
+    }
+
+    /// Fill the captures of a documented path so that it can be requested
+    fn uri(path: &str) -> String {
+        path.split('/')
+            .map(|segment| match segment.starts_with('{') {
+                true => "1",
+                false => segment,
+            })
+            .collect::<Vec<_>>()
+            .join("/")
+    }
+
+    /// Create a data store whose pool never connects, so handlers fail fast instead of hanging
+    fn offline_store() -> Arc<RwLock<Store>> {
+        Arc::new(RwLock::new(Store::offline()))
+    }
+
+    #[tokio::test]
+    async fn every_documented_route_is_served() {
+        let app = routes(RateLimiter::unlimited()).with_state(offline_store());
+
+        for (method, path) in documented_routes() {
+            let response = probe(&app, &method.to_uppercase(), &uri(&path)).await;
+            assert!(is_routed(&response), "{} {} is not routed", method, path);
+        }
+    }
+
+    #[tokio::test]
+    async fn every_served_route_is_documented() {
+        let app = routes(RateLimiter::unlimited()).with_state(offline_store());
+        let documented = documented_routes();
+
+        // Convert axum's `:id` captures to OpenAPI's `{id}` parameters
+        let paths = V1_PATHS
+            .iter()
+            .map(|path| format!("/api/v1{}", path))
+            .chain(OPERATIONAL_PATHS.iter().map(|path| path.to_string()))
+            .map(|path| {
+                path.split('/')
+                    .map(|segment| match segment.strip_prefix(':') {
+                        Some(name) => format!("{{{}}}", name),
+                        None => segment.to_owned(),
+                    })
+                    .collect::<Vec<_>>()
+                    .join("/")
+            })
+            .collect::<BTreeSet<_>>();
+
+        // A documented path that is not listed is probably a new route missing from the lists
+        for (method, path) in &documented {
+            assert!(
+                paths.contains(path),
+                "{} {} is missing from V1_PATHS or OPERATIONAL_PATHS",
+                method,
+                path
+            );
+        }
+
+        for path in paths {
+            for method in ["get", "post", "put", "delete", "patch"] {
+                let response = probe(&app, &method.to_uppercase(), &uri(&path)).await;
+                if is_routed(&response) {
+                    assert!(
+                        documented.contains(&(method.to_owned(), path.clone())),
+                        "{} {} is missing from the OpenAPI document",
+                        method,
+                        path
+                    );
+                }
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn only_original_routes_are_served_at_the_root() {
+        let app = routes(RateLimiter::unlimited()).with_state(offline_store());
+
+        for (uri, aliased) in [("/questions", true), ("/quiz/x/results", false)] {
+            let request = Request::builder()
+                .uri(uri)
+                .body(axum::body::Body::empty())
+                .unwrap();
+            let response = app.clone().oneshot(request).await.unwrap();
+            assert_eq!(
+                response.headers().contains_key("deprecation"),
+                aliased,
+                "{} is not aliased as expected",
+                uri
+            );
+            if !aliased {
+                assert_eq!(response.status(), StatusCode::NOT_FOUND);
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn docs_are_served() {
+        let app = routes(RateLimiter::unlimited()).with_state(offline_store());
+
+        for uri in ["/openapi.json", "/docs/"] {
+            let request = Request::builder()
+                .uri(uri)
+                .body(axum::body::Body::empty())
+                .unwrap();
+            let response = app.clone().oneshot(request).await.unwrap();
+            assert_eq!(response.status(), StatusCode::OK, "{} is not served", uri);
+        }
+    }
+}
@@ -0,0 +1,200 @@
+use crate::*;
+use std::time::Instant;
+use tokio::sync::Notify;
+
+/// Number of outbox rows relayed per transaction
+const OUTBOX_BATCH_SIZE: i64 = 100;
+
+/// How often the relay checks for rows written by other processes, such as the admin tool, or
+/// left behind by a relay that stopped before marking them
+const OUTBOX_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How often published rows older than the retention are deleted
+const OUTBOX_CLEANUP_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Default number of hours published rows are kept for
+const DEFAULT_OUTBOX_RETENTION_HOURS: u64 = 24;
+
+/// Outbox struct that wakes the relay once a write with changes has committed
+/// Clones share the same signal
+#[derive(Clone, Default)]
+pub struct Outbox {
+    pending: Arc<Notify>,
+}
+
+impl Outbox {
+    /// Constructor to create an outbox signal
+    pub fn new() -> Self {
+        Outbox::default()
+    }
+
+    /// Tell the relay that there are new rows, a wake with no relay waiting is kept for its next
+    /// wait so that it is never missed
+    pub fn wake(&self) {
+        self.pending.notify_one();
+    }
+}
+
+/// Check whether the old published rows are due to be deleted, which is on the first pass and
+/// then once every cleanup interval
+fn cleanup_due(last_cleanup: Option<Instant>) -> bool {
+    last_cleanup.is_none_or(|last| last.elapsed() >= OUTBOX_CLEANUP_INTERVAL)
+}
+
+/// Outbox relay struct that publishes the changes written to the outbox
+/// Each batch is marked as published in the same transaction as its webhook deliveries are
+/// queued, so a relay that stops part way through publishes the batch again rather than losing
+/// it, subscribers can see an event more than once but never miss one
+pub struct OutboxRelay {
+    store: Store,
+    retention: Duration,
+}
+
+impl OutboxRelay {
+    /// Constructor that reads how many hours to keep the published rows for from
+    /// `OUTBOX_RETENTION_HOURS`
+    pub fn new(store: Store) -> Self {
+        let hours = std::env::var("OUTBOX_RETENTION_HOURS")
+            .ok()
+            .and_then(|hours| hours.parse::<u64>().ok())
+            .unwrap_or(DEFAULT_OUTBOX_RETENTION_HOURS);
+
+        OutboxRelay {
+            store,
+            retention: Duration::from_secs(hours * 60 * 60),
+        }
+    }
+
+    /// Drop the related questions a relayed batch affects and publish it to the event hub
+    /// Returns whether the batch was full, in which case there may be more rows waiting
+    fn publish(&self, events: Vec<ChangeEvent>) -> bool {
+        let relayed = events.len() as i64;
+        for event in events {
+            self.store.related.apply(&event);
+            self.store.events.publish(event);
+        }
+        relayed >= OUTBOX_BATCH_SIZE
+    }
+
+    /// Relay the outbox until the server shuts down, rows left when it stops are relayed on the
+    /// next start
+    pub async fn run(mut self, shutdown: Shutdown) {
+        let outbox = self.store.outbox.clone();
+        let mut interval = tokio::time::interval(OUTBOX_POLL_INTERVAL);
+        let mut last_cleanup: Option<Instant> = None;
+        loop {
+            tokio::select! {
+                _ = outbox.pending.notified() => {}
+                _ = interval.tick() => {}
+                _ = shutdown.token.cancelled() => return,
+            }
+
+            // Keep going while there are full batches waiting
+            loop {
+                match self.store.relay_outbox(OUTBOX_BATCH_SIZE).await {
+                    Ok(events) => {
+                        if !self.publish(events) {
+                            break;
+                        }
+                    }
+                    Err(e) => {
+                        tracing::error!("Could not relay the outbox: {:?}", e);
+                        break;
+                    }
+                }
+            }
+
+            // Delete the old published rows now and then
+            if cleanup_due(last_cleanup) {
+                last_cleanup = Some(Instant::now());
+                if let Err(e) = self.store.delete_published_outbox(self.retention).await {
+                    tracing::error!("Could not clean up the outbox: {:?}", e);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(id: u64, kind: ChangeKind, question_id: i32) -> ChangeEvent {
+        ChangeEvent {
+            id,
+            kind,
+            question_id,
+            tags: None,
+            data: serde_json::json!({ "id": question_id }),
+        }
+    }
+
+    fn related(id: i32) -> RelatedQuestion {
+        RelatedQuestion {
+            question: Question {
+                id,
+                title: String::new(),
+                content: String::new(),
+                tags: None,
+            }
+            .into(),
+            shared_tags: 1,
+            similarity: 0.0,
+            score: 1.0,
+        }
+    }
+
+    #[tokio::test]
+    async fn full_batches_keep_the_relay_going() {
+        let relay = OutboxRelay::new(Store::offline());
+        let full: Vec<ChangeEvent> = (1..=OUTBOX_BATCH_SIZE as u64)
+            .map(|id| event(id, ChangeKind::QuestionCreated, 1))
+            .collect();
+        assert!(relay.publish(full));
+        assert!(!relay.publish(vec![event(101, ChangeKind::AnswerCreated, 1)]));
+        assert!(!relay.publish(Vec::new()));
+
+        // Every relayed event is published in order
+        let mut subscription = relay
+            .store
+            .events
+            .subscribe(EventFilter::default(), Some(0));
+        for id in 1..=101 {
+            assert_eq!(subscription.next().await.unwrap().id, id);
+        }
+    }
+
+    #[tokio::test]
+    async fn relayed_updates_drop_related_questions() {
+        let relay = OutboxRelay::new(Store::offline());
+        let cache = &relay.store.related;
+        cache.insert(1, Arc::new(vec![related(2)]));
+        cache.insert(3, Arc::new(vec![related(4)]));
+
+        relay.publish(vec![event(1, ChangeKind::QuestionUpdated, 2)]);
+        assert!(cache.get(1).is_none());
+        assert!(cache.get(3).is_some());
+    }
+
+    #[test]
+    fn cleanup_runs_first_then_every_interval() {
+        assert!(cleanup_due(None));
+        assert!(!cleanup_due(Some(Instant::now())));
+        let long_ago = Instant::now().checked_sub(OUTBOX_CLEANUP_INTERVAL);
+        if let Some(long_ago) = long_ago {
+            assert!(cleanup_due(Some(long_ago)));
+        }
+    }
+
+    #[tokio::test]
+    async fn wakes_are_kept_until_the_relay_waits() {
+        let outbox = Outbox::new();
+        let wait = || tokio::time::timeout(Duration::from_millis(50), outbox.pending.notified());
+
+        // A wake before the relay waits is not lost, and several wakes only wake it once
+        outbox.wake();
+        outbox.wake();
+        assert!(wait().await.is_ok());
+        assert!(wait().await.is_err());
+    }
+}
@@ -1,7 +1,9 @@
 use crate::*;
 
 /// Question struct used to store questions in the database
-#[derive(Debug, Serialize, Clone, Deserialize)]
+/// The HTTP routes send it to clients as a `RenderedQuestion`
+#[derive(Debug, Serialize, Clone, Deserialize, SimpleObject)]
+#[graphql(complex)]
 pub struct Question {
     pub id: i32,
     pub title: String,
@@ -9,10 +11,107 @@ pub struct Question {
     pub tags: Option<Vec<String>>,
 }
 
+/// Question struct as it is sent to clients, `content` is CommonMark and `content_html` is the
+/// sanitized HTML it renders to
+#[derive(Debug, Serialize, Clone, ToSchema)]
+#[schema(as = Question)]
+pub struct RenderedQuestion {
+    pub id: i32,
+    pub title: String,
+    pub content: String,
+    pub content_html: String,
+    pub tags: Option<Vec<String>>,
+}
+
+impl From<Question> for RenderedQuestion {
+    fn from(question: Question) -> Self {
+        RenderedQuestion {
+            id: question.id,
+            title: question.title,
+            content_html: render_markdown(&question.content),
+            content: question.content,
+            tags: question.tags,
+        }
+    }
+}
+
+/// Question struct with its answers embedded, returned by the `question` route with
+/// `include=answers`
+/// `answers` holds at most the requested number of answers, `answer_count` is the total
+#[derive(Debug, Serialize, Clone, ToSchema)]
+pub struct QuestionWithAnswers {
+    #[serde(flatten)]
+    #[schema(value_type = Question)]
+    pub question: RenderedQuestion,
+    #[schema(value_type = Vec<Answer>)]
+    pub answers: Vec<RenderedAnswer>,
+    pub answer_count: i64,
+}
+
+/// Filter struct for the random question route, every field that is set has to match
+#[derive(Debug, Clone, Default)]
+pub struct RandomFilter {
+    /// Only questions with this tag, ignoring case
+    pub tag: Option<String>,
+    /// Only questions that have at least one answer, or only the ones that have none
+    pub answered: Option<bool>,
+    /// Never these questions
+    pub exclude: Vec<i32>,
+}
+
+/// Candidate duplicate struct for a new question
+/// `similarity` is from 0 to 1, the title counts for two thirds of it and the content for the rest
+#[derive(Debug, Serialize, Clone, ToSchema)]
+pub struct DuplicateCandidate {
+    #[serde(flatten)]
+    #[schema(value_type = Question)]
+    pub question: RenderedQuestion,
+    pub similarity: f64,
+}
+
+/// Conflict struct returned instead of creating a question that looks like a duplicate
+#[derive(Debug, Serialize, Clone, ToSchema)]
+pub struct DuplicateConflict {
+    pub message: String,
+    /// Most similar questions first
+    pub candidates: Vec<DuplicateCandidate>,
+}
+
+/// Added question struct returned once a question is created, with the questions that look like
+/// it but not enough to block it
+#[derive(Debug, Serialize, Clone, ToSchema)]
+pub struct AddedQuestion {
+    pub id: i32,
+    /// Most similar questions first
+    pub candidates: Vec<DuplicateCandidate>,
+}
+
+/// Duplicate struct returned once a question is marked as a duplicate of another
+#[derive(Debug, Serialize, Clone, ToSchema)]
+pub struct Duplicate {
+    pub question_id: i32,
+    /// The question it duplicates, which is never a duplicate itself
+    pub duplicate_of: i32,
+    pub marked_on: NaiveDateTime,
+}
+
 /// New question struct used to create and update questions in the database
-#[derive(Debug, Serialize, Clone, Deserialize)]
+#[derive(Debug, Serialize, Clone, Deserialize, ToSchema, InputObject)]
 pub struct NewQuestion {
     pub title: String,
     pub content: String,
     pub tags: Option<Vec<String>>,
 }
+
+impl NewQuestion {
+    /// Check that the title and content are within the length limits
+    pub fn validate(&self) -> Result<(), Err> {
+        if self.title.chars().count() > MAX_TITLE_LENGTH {
+            return Err(Err::TitleTooLong);
+        }
+        if self.content.chars().count() > MAX_CONTENT_LENGTH {
+            return Err(Err::ContentTooLong);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,352 @@
+use crate::*;
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use std::collections::HashSet;
+
+/// Number of questions drawn for a quiz if no count is given
+const DEFAULT_QUIZ_QUESTIONS: i64 = 10;
+
+/// Largest number of questions a quiz can have
+const MAX_QUIZ_QUESTIONS: i64 = 50;
+
+/// Time to finish a quiz if no time limit is given
+const DEFAULT_QUIZ_TIME_LIMIT_SECONDS: i64 = 10 * 60;
+
+/// Longest time limit a quiz can have
+const MAX_QUIZ_TIME_LIMIT_SECONDS: i64 = 24 * 60 * 60;
+
+/// Similarity a free text answer needs to be counted as correct if no threshold is given
+const DEFAULT_QUIZ_THRESHOLD: f64 = 0.5;
+
+/// New quiz struct that is being extracted from the json body of the `quiz` route
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct NewQuiz {
+    /// Number of questions to draw, fewer are drawn if not enough questions pass the filter
+    #[serde(default = "default_quiz_questions")]
+    pub questions: i64,
+    /// Only draw questions with this tag, ignoring case
+    pub tag: Option<String>,
+    /// Seconds to submit answers in once the quiz is created
+    #[serde(default = "default_quiz_time_limit")]
+    pub time_limit_seconds: i64,
+    /// Similarity between 0 and 1 a submitted answer needs to an answer of the question
+    #[serde(default = "default_quiz_threshold")]
+    pub threshold: f64,
+}
+
+fn default_quiz_questions() -> i64 {
+    DEFAULT_QUIZ_QUESTIONS
+}
+
+fn default_quiz_time_limit() -> i64 {
+    DEFAULT_QUIZ_TIME_LIMIT_SECONDS
+}
+
+fn default_quiz_threshold() -> f64 {
+    DEFAULT_QUIZ_THRESHOLD
+}
+
+impl NewQuiz {
+    /// Check that the count, time limit and threshold are within their limits
+    pub fn validate(&self) -> Result<(), Err> {
+        if !(1..=MAX_QUIZ_QUESTIONS).contains(&self.questions) {
+            return Err(Err::InvalidQuiz(format!(
+                "questions must be between 1 and {}",
+                MAX_QUIZ_QUESTIONS
+            )));
+        }
+        if !(1..=MAX_QUIZ_TIME_LIMIT_SECONDS).contains(&self.time_limit_seconds) {
+            return Err(Err::InvalidQuiz(format!(
+                "time_limit_seconds must be between 1 and {}",
+                MAX_QUIZ_TIME_LIMIT_SECONDS
+            )));
+        }
+        if !(0.0..=1.0).contains(&self.threshold) {
+            return Err(Err::InvalidQuiz(
+                "threshold must be between 0 and 1".to_string(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Quiz struct returned when a quiz is created, the token is needed for every other quiz route
+#[derive(Debug, Serialize, Clone, ToSchema)]
+pub struct Quiz {
+    pub token: String,
+    pub tag: Option<String>,
+    pub threshold: f64,
+    pub created_on: NaiveDateTime,
+    pub expires_on: NaiveDateTime,
+    /// Questions in the order they are asked, without their answers
+    #[schema(value_type = Vec<Question>)]
+    pub questions: Vec<RenderedQuestion>,
+}
+
+/// Quiz answer struct that is being extracted from the json body of the `quiz/question` route
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct NewQuizAnswer {
+    pub answer: String,
+}
+
+/// Scored answer struct returned once an answer has been submitted
+#[derive(Debug, Serialize, Clone, ToSchema)]
+pub struct QuizAnswer {
+    pub question_id: i32,
+    pub answer: String,
+    /// Similarity to the closest answer of the question, from 0 to 1
+    pub similarity: f64,
+    pub correct: bool,
+    pub answered_on: NaiveDateTime,
+}
+
+/// Outcome of submitting an answer to a quiz question
+#[derive(Debug)]
+pub enum QuizSubmission {
+    Scored(QuizAnswer),
+    Expired,
+    AlreadyAnswered,
+}
+
+/// Result struct for one question of a quiz, the answer fields are null until it is answered
+#[derive(Debug, Serialize, Deserialize, Clone, ToSchema)]
+pub struct QuizQuestionResult {
+    pub position: i32,
+    pub question_id: i32,
+    pub title: String,
+    pub answer: Option<String>,
+    pub similarity: Option<f64>,
+    pub correct: bool,
+    pub answered_on: Option<NaiveDateTime>,
+}
+
+/// Results struct returned by the `quiz/results` route
+/// `finished` is true once every question is answered or the time limit has passed, `score` is
+/// the share of the questions answered correctly
+#[derive(Debug, Serialize, Clone, ToSchema)]
+pub struct QuizResults {
+    pub token: String,
+    pub tag: Option<String>,
+    pub threshold: f64,
+    pub created_on: NaiveDateTime,
+    pub expires_on: NaiveDateTime,
+    pub finished: bool,
+    pub total: i64,
+    pub answered: i64,
+    pub correct: i64,
+    pub score: f64,
+    pub questions: Vec<QuizQuestionResult>,
+}
+
+/// Generate a random token for a quiz
+pub fn generate_quiz_token() -> String {
+    let mut bytes = [0u8; 16];
+    OsRng.fill_bytes(&mut bytes);
+    hex::encode(bytes)
+}
+
+/// Trigrams of the words in a text, ignoring case and punctuation, each word is padded with two
+/// spaces in front and one behind like `pg_trgm` does
+fn trigrams(text: &str) -> HashSet<[char; 3]> {
+    let mut trigrams = HashSet::new();
+    for word in text
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+    {
+        let padded: Vec<char> = "  "
+            .chars()
+            .chain(word.chars().flat_map(char::to_lowercase))
+            .chain(" ".chars())
+            .collect();
+        for window in padded.windows(3) {
+            trigrams.insert([window[0], window[1], window[2]]);
+        }
+    }
+    trigrams
+}
+
+/// Similarity of two texts from 0 to 1, the share of their trigrams they have in common
+pub fn text_similarity(a: &str, b: &str) -> f64 {
+    let (a, b) = (trigrams(a), trigrams(b));
+    let union = a.union(&b).count();
+    if union == 0 {
+        return 0.0;
+    }
+    a.intersection(&b).count() as f64 / union as f64
+}
+
+/// Score a submitted answer against every answer of the question, there is no accepted answer
+/// so the closest one counts
+pub fn score_answer(answer: &str, accepted: &[String]) -> f64 {
+    accepted
+        .iter()
+        .map(|accepted| text_similarity(answer, accepted))
+        .fold(0.0, f64::max)
+}
+
+/// Start a quiz from the `quiz` route
+/// # Example query
+/// POST requests to this route have a json body with the number of questions, an optional tag,
+/// the time limit and the similarity threshold, and return the drawn questions with the token
+/// to submit answers with
+/// Only questions with at least one answer are drawn, since the answers are what is scored
+/// `/quiz`
+/// `{
+///     "questions": 5,
+///     "tag": "rust",
+///     "time_limit_seconds": 300,
+///     "threshold": 0.5
+/// }`
+#[utoipa::path(
+    post,
+    path = "/quiz",
+    tag = "quiz",
+    request_body = NewQuiz,
+    responses(
+        (status = 201, description = "Quiz created", body = Quiz),
+        (status = 400, description = "Quiz is invalid or database error", body = String),
+        (status = 404, description = "There are no answered questions that pass the filter", body = String),
+    )
+)]
+pub async fn add_quiz(
+    State(store): State<Arc<RwLock<Store>>>,
+    Json(new_quiz): Json<NewQuiz>,
+) -> Response {
+    if let Err(e) = new_quiz.validate() {
+        return (StatusCode::BAD_REQUEST, e.to_string()).into_response();
+    }
+
+    // Draw the questions under the read lock, the write lock is only taken to save the quiz
+    let questions = match store.read().await.draw_quiz_questions(&new_quiz).await {
+        Ok(questions) => questions,
+        Err(sqlx::Error::RowNotFound) => {
+            return (StatusCode::NOT_FOUND, Err::QuestionNotFound.to_string()).into_response()
+        }
+        Err(e) => return (StatusCode::BAD_REQUEST, e.to_string()).into_response(),
+    };
+
+    let token = generate_quiz_token();
+    match store
+        .write()
+        .await
+        .add_quiz(&new_quiz, &token, questions)
+        .await
+    {
+        Ok(quiz) => (StatusCode::CREATED, Json(quiz)).into_response(),
+        Err(sqlx::Error::RowNotFound) => {
+            (StatusCode::NOT_FOUND, Err::QuestionNotFound.to_string()).into_response()
+        }
+        Err(e) => (StatusCode::BAD_REQUEST, e.to_string()).into_response(),
+    }
+}
+
+/// Answer a question of a quiz from the `quiz/question` route
+/// # Example query
+/// POST requests to this route have a json body with the answer, which is scored right away
+/// Each question can be answered once, and only before the quiz expires
+/// `/quiz/9f2c4e.../question/3`
+/// `{
+///     "answer": "Use std::fs::read_to_string"
+/// }`
+#[utoipa::path(
+    post,
+    path = "/quiz/{token}/question/{id}",
+    tag = "quiz",
+    params(
+        ("token" = String, Path, description = "Quiz token"),
+        ("id" = i32, Path, description = "Question id"),
+    ),
+    request_body = NewQuizAnswer,
+    responses(
+        (status = 200, description = "Scored answer", body = QuizAnswer),
+        (status = 400, description = "Answer is too long or database error", body = String),
+        (status = 404, description = "Quiz not found or the question is not in it", body = String),
+        (status = 409, description = "Quiz expired or question already answered", body = String),
+    )
+)]
+pub async fn submit_quiz_answer(
+    State(store): State<Arc<RwLock<Store>>>,
+    Path((token, id)): Path<(String, i32)>,
+    Json(new_answer): Json<NewQuizAnswer>,
+) -> Response {
+    if new_answer.answer.chars().count() > MAX_CONTENT_LENGTH {
+        return (StatusCode::BAD_REQUEST, Err::ContentTooLong.to_string()).into_response();
+    }
+
+    match store
+        .write()
+        .await
+        .submit_quiz_answer(&token, &id, &new_answer.answer)
+        .await
+    {
+        Ok(QuizSubmission::Scored(answer)) => (StatusCode::OK, Json(answer)).into_response(),
+        Ok(QuizSubmission::Expired) => {
+            (StatusCode::CONFLICT, Err::QuizExpired.to_string()).into_response()
+        }
+        Ok(QuizSubmission::AlreadyAnswered) => {
+            (StatusCode::CONFLICT, Err::AlreadyAnswered.to_string()).into_response()
+        }
+        Err(sqlx::Error::RowNotFound) => {
+            (StatusCode::NOT_FOUND, Err::QuizNotFound.to_string()).into_response()
+        }
+        Err(e) => (StatusCode::BAD_REQUEST, e.to_string()).into_response(),
+    }
+}
+
+/// Fetch the results of a quiz from the `quiz/results` route
+/// # Example query
+/// GET requests to this route return the score so far and every question with its answer
+/// `/quiz/9f2c4e.../results`
+#[utoipa::path(
+    get,
+    path = "/quiz/{token}/results",
+    tag = "quiz",
+    params(("token" = String, Path, description = "Quiz token")),
+    responses(
+        (status = 200, description = "Results of the quiz", body = QuizResults),
+        (status = 400, description = "Database error", body = String),
+        (status = 404, description = "Quiz not found", body = String),
+    )
+)]
+pub async fn get_quiz_results(
+    State(store): State<Arc<RwLock<Store>>>,
+    Path(token): Path<String>,
+) -> Response {
+    match store.read().await.get_quiz_results(&token).await {
+        Ok(results) => (StatusCode::OK, Json(results)).into_response(),
+        Err(sqlx::Error::RowNotFound) => {
+            (StatusCode::NOT_FOUND, Err::QuizNotFound.to_string()).into_response()
+        }
+        Err(e) => (StatusCode::BAD_REQUEST, e.to_string()).into_response(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn similarity_ignores_case_and_punctuation() {
+        assert_eq!(
+            text_similarity("Use read_to_string!", "use READ to string"),
+            1.0
+        );
+        assert_eq!(text_similarity("", ""), 0.0);
+        assert_eq!(text_similarity("borrow", "lifetime"), 0.0);
+
+        let close = text_similarity("std::fs::read_to_string", "fs::read_to_string");
+        let far = text_similarity("std::fs::read_to_string", "a BufReader over the file");
+        assert!(close > far);
+    }
+
+    #[test]
+    fn answers_are_scored_against_the_closest_answer() {
+        let accepted = vec![
+            "Use a Vec".to_string(),
+            "Call std::fs::read_to_string with the path".to_string(),
+        ];
+
+        assert_eq!(score_answer("use a vec", &accepted), 1.0);
+        assert_eq!(score_answer("anything", &[]), 0.0);
+        assert!(score_answer("std::fs::read_to_string(path)", &accepted) > 0.5);
+    }
+}
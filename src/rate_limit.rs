@@ -0,0 +1,310 @@
+use crate::*;
+use axum::http::HeaderMap;
+use std::collections::HashSet;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// How often buckets that have refilled completely are removed from memory
+const CLEANUP_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Header clients can send to be rate limited by key instead of by IP address
+const API_KEY_HEADER: &str = "x-api-key";
+
+/// Budget struct describing a token bucket: how many requests can be made at once and how many
+/// tokens are added back each second
+#[derive(Debug, Clone, Copy)]
+pub struct Budget {
+    pub burst: f64,
+    pub per_second: f64,
+}
+
+impl Budget {
+    /// Read a budget from `<prefix>_BURST` and `<prefix>_PER_SECOND`, using the defaults if unset
+    fn from_env(prefix: &str, burst: f64, per_second: f64) -> Self {
+        let read = |name: &str, default: f64| {
+            std::env::var(format!("{}_{}", prefix, name))
+                .ok()
+                .and_then(|v| v.parse::<f64>().ok())
+                .filter(|v| *v > 0.0)
+                .unwrap_or(default)
+        };
+
+        Budget {
+            burst: read("BURST", burst),
+            per_second: read("PER_SECOND", per_second),
+        }
+    }
+}
+
+/// Bucket struct that tracks the tokens left for one client and budget
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+/// Rate limiter struct with separate budgets for reads and writes
+/// Clones share the same buckets
+#[derive(Clone)]
+pub struct RateLimiter {
+    read: Budget,
+    write: Budget,
+    api_keys: Arc<HashSet<String>>,
+    buckets: Arc<Mutex<HashMap<(String, bool), Bucket>>>,
+}
+
+impl Default for RateLimiter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RateLimiter {
+    /// Constructor that reads the budgets from `RATE_LIMIT_READ_*` and `RATE_LIMIT_WRITE_*`, and
+    /// the comma separated API keys that get their own buckets from `RATE_LIMIT_API_KEYS`
+    pub fn new() -> Self {
+        let api_keys = std::env::var("RATE_LIMIT_API_KEYS")
+            .unwrap_or_default()
+            .split(',')
+            .map(|key| key.trim().to_owned())
+            .filter(|key| !key.is_empty())
+            .collect();
+
+        RateLimiter {
+            read: Budget::from_env("RATE_LIMIT_READ", 50.0, 10.0),
+            write: Budget::from_env("RATE_LIMIT_WRITE", 10.0, 1.0),
+            api_keys: Arc::new(api_keys),
+            buckets: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Rate limiter that never runs out, so tests can send as many requests as they need
+    #[cfg(test)]
+    pub fn unlimited() -> Self {
+        let budget = Budget {
+            burst: f64::MAX,
+            per_second: f64::MAX,
+        };
+        RateLimiter {
+            read: budget,
+            write: budget,
+            api_keys: Arc::new(HashSet::new()),
+            buckets: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Work out which bucket a client uses: its `X-Api-Key` header if the key is one of the
+    /// configured keys, otherwise its IP address
+    /// Unknown keys are ignored so that sending a new key with each request does not get a new
+    /// budget each time, or fill the limiter with buckets
+    pub fn client_key(&self, headers: &HeaderMap, addr: Option<SocketAddr>) -> String {
+        match headers
+            .get(API_KEY_HEADER)
+            .and_then(|key| key.to_str().ok())
+            .filter(|key| self.api_keys.contains(*key))
+        {
+            Some(key) => format!("key:{}", key),
+            None => addr
+                .map(|addr| format!("ip:{}", addr.ip()))
+                .unwrap_or_default(),
+        }
+    }
+
+    /// Take a token from the client's bucket
+    /// Returns how long the client has to wait if the bucket is empty
+    pub fn check(&self, client: String, write: bool) -> Result<(), Duration> {
+        let budget = if write { self.write } else { self.read };
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock().unwrap();
+        let bucket = buckets.entry((client, write)).or_insert(Bucket {
+            tokens: budget.burst,
+            updated: now,
+        });
+
+        // Add the tokens earned since the last request, up to the burst size
+        let earned = now.duration_since(bucket.updated).as_secs_f64() * budget.per_second;
+        bucket.tokens = (bucket.tokens + earned).min(budget.burst);
+        bucket.updated = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64(
+                (1.0 - bucket.tokens) / budget.per_second,
+            ))
+        }
+    }
+
+    /// Remove the buckets that would be full by now, since they behave the same as new ones
+    fn cleanup(&self) {
+        let now = Instant::now();
+        self.buckets.lock().unwrap().retain(|(_, write), bucket| {
+            let budget = if *write { self.write } else { self.read };
+            let earned = now.duration_since(bucket.updated).as_secs_f64() * budget.per_second;
+            bucket.tokens + earned < budget.burst
+        });
+    }
+
+    /// Periodically remove idle buckets until shutdown
+    pub async fn run_cleanup(self, shutdown: Shutdown) {
+        let mut interval = tokio::time::interval(CLEANUP_INTERVAL);
+        loop {
+            tokio::select! {
+                _ = interval.tick() => self.cleanup(),
+                _ = shutdown.token.cancelled() => return,
+            }
+        }
+    }
+}
+
+/// Client a request was rate limited as, added to the request by the middleware so that a handler
+/// that keeps taking writes after the request, such as a socket, can charge them to the same budget
+#[derive(Clone)]
+pub struct RateLimitedClient {
+    limiter: RateLimiter,
+    client: String,
+}
+
+impl RateLimitedClient {
+    /// Take a token from the client's write budget
+    /// Returns how long the client has to wait if the bucket is empty
+    pub fn check_write(&self) -> Result<(), Duration> {
+        self.limiter.check(self.client.clone(), true)
+    }
+}
+
+/// Middleware to apply the rate limits
+/// Clients are identified by their `X-Api-Key` header if it is one of the configured keys,
+/// otherwise by IP address
+/// GET, HEAD and OPTIONS requests use the read budget and everything else uses the write budget
+pub async fn rate_limit(
+    State(limiter): State<RateLimiter>,
+    mut request: Request<axum::body::Body>,
+    next: Next,
+) -> Response {
+    let addr = request
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(addr)| *addr);
+    let client = limiter.client_key(request.headers(), addr);
+    let write = !matches!(
+        *request.method(),
+        Method::GET | Method::HEAD | Method::OPTIONS
+    );
+
+    match limiter.check(client.clone(), write) {
+        Ok(_) => {
+            request
+                .extensions_mut()
+                .insert(RateLimitedClient { limiter, client });
+            next.run(request).await
+        }
+        Err(wait) => rate_limited(wait),
+    }
+}
+
+/// Response for a client that has used up its budget
+fn rate_limited(wait: Duration) -> Response {
+    (
+        StatusCode::TOO_MANY_REQUESTS,
+        [(header::RETRY_AFTER, wait.as_secs_f64().ceil().to_string())],
+        Err::RateLimited.to_string(),
+    )
+        .into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tower::ServiceExt;
+
+    fn limiter(api_keys: &[&str]) -> RateLimiter {
+        let budget = Budget {
+            burst: 2.0,
+            per_second: 0.001,
+        };
+        RateLimiter {
+            read: budget,
+            write: budget,
+            api_keys: Arc::new(api_keys.iter().map(|key| key.to_string()).collect()),
+            buckets: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    fn headers(key: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(API_KEY_HEADER, key.parse().unwrap());
+        headers
+    }
+
+    #[test]
+    fn unknown_keys_share_the_ip_budget() {
+        let limiter = limiter(&["known"]);
+        let addr = Some(SocketAddr::from(([10, 0, 0, 1], 4000)));
+
+        // A new key on every request still draws from the caller's IP bucket
+        for key in ["a", "b"] {
+            let client = limiter.client_key(&headers(key), addr);
+            assert_eq!(client, "ip:10.0.0.1");
+            assert!(limiter.check(client, true).is_ok());
+        }
+        let client = limiter.client_key(&headers("c"), addr);
+        assert!(limiter.check(client, true).is_err());
+        assert_eq!(limiter.buckets.lock().unwrap().len(), 1);
+
+        // A configured key has a budget of its own
+        let client = limiter.client_key(&headers("known"), addr);
+        assert_eq!(client, "key:known");
+        assert!(limiter.check(client, true).is_ok());
+    }
+
+    #[test]
+    fn socket_writes_share_the_request_budget() {
+        let limiter = limiter(&[]);
+        let client = RateLimitedClient {
+            limiter: limiter.clone(),
+            client: "ip:10.0.0.1".to_owned(),
+        };
+
+        assert!(limiter.check(client.client.clone(), true).is_ok());
+        assert!(client.check_write().is_ok());
+        assert!(client.check_write().is_err());
+        assert!(limiter.check(client.client.clone(), true).is_err());
+    }
+
+    #[tokio::test]
+    async fn operational_routes_are_not_rate_limited() {
+        let app = routes(limiter(&[])).with_state(Arc::new(RwLock::new(Store::offline())));
+        let get = |uri: &str| {
+            Request::builder()
+                .uri(uri)
+                .body(axum::body::Body::empty())
+                .unwrap()
+        };
+
+        // Use up the read budget on the API routes
+        for _ in 0..2 {
+            let response = app.clone().oneshot(get("/api/v1/questions")).await.unwrap();
+            assert_ne!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        }
+        for uri in ["/api/v1/questions", "/questions"] {
+            let response = app.clone().oneshot(get(uri)).await.unwrap();
+            assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        }
+
+        let response = app.clone().oneshot(get("/healthz")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        // The metrics fail without a database, but are not turned away
+        for uri in ["/metrics", "/version", "/openapi.json"] {
+            let response = app.clone().oneshot(get(uri)).await.unwrap();
+            assert_ne!(
+                response.status(),
+                StatusCode::TOO_MANY_REQUESTS,
+                "{} was rate limited",
+                uri
+            );
+        }
+    }
+}
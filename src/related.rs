@@ -0,0 +1,245 @@
+use crate::*;
+use std::sync::Mutex;
+use std::time::Instant;
+
+/// Number of related questions returned if no limit is given
+const DEFAULT_RELATED_LIMIT: i64 = 5;
+
+/// Largest number of related questions that can be asked for, and the number that is cached
+pub const MAX_RELATED_LIMIT: i64 = 20;
+
+/// Default number of seconds related questions are cached for
+const DEFAULT_RELATED_CACHE_TTL_SECONDS: u64 = 300;
+
+/// Most questions whose related questions are cached at once
+const RELATED_CACHE_CAPACITY: usize = 10_000;
+
+/// Related question struct returned by the `question/related` route
+/// `score` is the number of tags shared with the question plus `similarity`, which is from 0 to
+/// 1 and counts the title for two thirds and the content for the rest
+#[derive(Debug, Serialize, Clone, ToSchema)]
+pub struct RelatedQuestion {
+    #[serde(flatten)]
+    #[schema(value_type = Question)]
+    pub question: RenderedQuestion,
+    pub shared_tags: i64,
+    pub similarity: f64,
+    pub score: f64,
+}
+
+/// Related questions of one question and when they were computed
+struct CachedRelated {
+    computed: Instant,
+    related: Arc<Vec<RelatedQuestion>>,
+}
+
+/// Related cache struct that keeps the related questions of each question for a while
+/// The entry of a question is dropped when it is updated or deleted, together with every entry
+/// it appears in, other changes show up once the entry expires
+/// Clones share the same cache
+#[derive(Clone)]
+pub struct RelatedCache {
+    entries: Arc<Mutex<HashMap<i32, CachedRelated>>>,
+    ttl: Duration,
+}
+
+impl Default for RelatedCache {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RelatedCache {
+    /// Constructor that reads how many seconds to cache for from `RELATED_CACHE_TTL_SECONDS`
+    pub fn new() -> Self {
+        let seconds = std::env::var("RELATED_CACHE_TTL_SECONDS")
+            .ok()
+            .and_then(|seconds| seconds.parse::<u64>().ok())
+            .unwrap_or(DEFAULT_RELATED_CACHE_TTL_SECONDS);
+
+        RelatedCache {
+            entries: Arc::new(Mutex::new(HashMap::new())),
+            ttl: Duration::from_secs(seconds),
+        }
+    }
+
+    /// Get the cached related questions of a question if they have not expired
+    pub fn get(&self, id: i32) -> Option<Arc<Vec<RelatedQuestion>>> {
+        let entries = self.entries.lock().unwrap();
+        entries
+            .get(&id)
+            .filter(|entry| entry.computed.elapsed() < self.ttl)
+            .map(|entry| entry.related.clone())
+    }
+
+    /// Cache the related questions of a question
+    pub fn insert(&self, id: i32, related: Arc<Vec<RelatedQuestion>>) {
+        let mut entries = self.entries.lock().unwrap();
+        if entries.len() >= RELATED_CACHE_CAPACITY {
+            entries.retain(|_, entry| entry.computed.elapsed() < self.ttl);
+            if entries.len() >= RELATED_CACHE_CAPACITY {
+                entries.clear();
+            }
+        }
+        entries.insert(
+            id,
+            CachedRelated {
+                computed: Instant::now(),
+                related,
+            },
+        );
+    }
+
+    /// Drop the entry of a question that changed and every entry it appears in
+    pub fn invalidate(&self, id: i32) {
+        let mut entries = self.entries.lock().unwrap();
+        entries.retain(|source, entry| {
+            *source != id && entry.related.iter().all(|r| r.question.id != id)
+        });
+    }
+
+    /// Drop every entry
+    pub fn clear(&self) {
+        self.entries.lock().unwrap().clear();
+    }
+
+    /// Drop the entries a change relayed from the outbox affects, this covers writes made by
+    /// other processes such as the admin tool
+    pub fn apply(&self, event: &ChangeEvent) {
+        match event.kind {
+            ChangeKind::QuestionUpdated | ChangeKind::QuestionDeleted => {
+                self.invalidate(event.question_id)
+            }
+            ChangeKind::QuestionCreated | ChangeKind::AnswerCreated => {}
+        }
+    }
+}
+
+/// Fetch the questions most related to a question from the `question/related` route
+/// # Example query
+/// GET requests to this route return up to `limit` questions ranked by the number of tags they
+/// share with the question, then by how similar their title and content are
+/// Only questions that share a tag or have a similar title are considered
+/// `/question/1/related?limit=5`
+#[utoipa::path(
+    get,
+    path = "/question/{id}/related",
+    tag = "questions",
+    params(
+        ("id" = i32, Path, description = "Question id"),
+        ("limit" = Option<i64>, Query, description = "Number of related questions, from 1 to 20, defaults to 5"),
+    ),
+    responses(
+        (status = 200, description = "Related questions, most related first", body = [RelatedQuestion]),
+        (status = 400, description = "Limit is invalid or database error", body = String),
+        (status = 404, description = "Question not found", body = String),
+    )
+)]
+pub async fn get_related_questions(
+    State(store): State<Arc<RwLock<Store>>>,
+    Path(id): Path<i32>,
+    Query(params): Query<HashMap<String, String>>,
+) -> Response {
+    let limit = match params.get("limit").map(|limit| limit.parse::<i64>()) {
+        Some(Ok(limit)) if (1..=MAX_RELATED_LIMIT).contains(&limit) => limit,
+        Some(Ok(_)) => {
+            return (
+                StatusCode::BAD_REQUEST,
+                Err::InvalidRelatedLimit.to_string(),
+            )
+                .into_response()
+        }
+        Some(Err(e)) => {
+            return (StatusCode::BAD_REQUEST, Err::ParseInt(e).to_string()).into_response()
+        }
+        None => DEFAULT_RELATED_LIMIT,
+    };
+
+    match store.read().await.get_related_questions(&id).await {
+        Ok(related) => {
+            let related: Vec<&RelatedQuestion> = related.iter().take(limit as usize).collect();
+            (StatusCode::OK, Json(related)).into_response()
+        }
+        Err(sqlx::Error::RowNotFound) => {
+            (StatusCode::NOT_FOUND, Err::QuestionNotFound.to_string()).into_response()
+        }
+        Err(e) => (StatusCode::BAD_REQUEST, e.to_string()).into_response(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn related(id: i32) -> RelatedQuestion {
+        RelatedQuestion {
+            question: Question {
+                id,
+                title: format!("Question {id}"),
+                content: String::new(),
+                tags: None,
+            }
+            .into(),
+            shared_tags: 1,
+            similarity: 0.0,
+            score: 1.0,
+        }
+    }
+
+    fn event(kind: ChangeKind, question_id: i32) -> ChangeEvent {
+        ChangeEvent {
+            id: 1,
+            kind,
+            question_id,
+            tags: None,
+            data: serde_json::Value::Null,
+        }
+    }
+
+    fn cache() -> RelatedCache {
+        let cache = RelatedCache::new();
+        cache.insert(1, Arc::new(vec![related(2), related(3)]));
+        cache.insert(2, Arc::new(vec![related(1)]));
+        cache.insert(3, Arc::new(vec![related(4)]));
+        cache.insert(4, Arc::new(vec![related(3)]));
+        cache
+    }
+
+    #[test]
+    fn invalidate_drops_the_question_and_every_entry_it_appears_in() {
+        let cache = cache();
+        cache.invalidate(2);
+
+        assert!(cache.get(1).is_none());
+        assert!(cache.get(2).is_none());
+        assert_eq!(cache.get(3).unwrap()[0].question.id, 4);
+        assert_eq!(cache.get(4).unwrap()[0].question.id, 3);
+    }
+
+    #[test]
+    fn only_updates_and_deletes_invalidate() {
+        let cache = cache();
+        cache.apply(&event(ChangeKind::QuestionCreated, 1));
+        cache.apply(&event(ChangeKind::AnswerCreated, 1));
+        assert!((1..=4).all(|id| cache.get(id).is_some()));
+
+        cache.apply(&event(ChangeKind::QuestionUpdated, 4));
+        assert!(cache.get(3).is_none());
+        assert!(cache.get(4).is_none());
+        assert!(cache.get(1).is_some());
+
+        cache.apply(&event(ChangeKind::QuestionDeleted, 1));
+        assert!((1..=4).all(|id| cache.get(id).is_none()));
+    }
+
+    #[test]
+    fn expired_entries_are_not_returned() {
+        let cache = RelatedCache {
+            ttl: Duration::ZERO,
+            ..RelatedCache::new()
+        };
+        cache.insert(1, Arc::new(vec![related(2)]));
+
+        assert!(cache.get(1).is_none());
+    }
+}
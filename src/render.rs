@@ -0,0 +1,95 @@
+use crate::*;
+use pulldown_cmark::{html, Options, Parser};
+use std::sync::OnceLock;
+
+/// Sanitizer for rendered content, it keeps ammonia's allow-list of tags and attributes, which
+/// has no scripts, styles or event handlers, and links get `rel="noopener noreferrer"`
+/// Code blocks also keep their `language-*` class so that the frontend can highlight them
+fn sanitizer() -> &'static ammonia::Builder<'static> {
+    static SANITIZER: OnceLock<ammonia::Builder<'static>> = OnceLock::new();
+    SANITIZER.get_or_init(|| {
+        let mut builder = ammonia::Builder::default();
+        builder
+            .add_tag_attributes("code", &["class"])
+            .attribute_filter(|element, attribute, value| match (element, attribute) {
+                ("code", "class") if !value.starts_with("language-") => None,
+                _ => Some(value.into()),
+            });
+        builder
+    })
+}
+
+/// Render content written in CommonMark to HTML that is safe to put in a page as it is
+pub fn render_markdown(content: &str) -> String {
+    let mut unsafe_html = String::new();
+    html::push_html(&mut unsafe_html, Parser::new_ext(content, Options::empty()));
+    sanitizer().clean(&unsafe_html).to_string()
+}
+
+/// Preview struct that is being extracted from the json body of the `render/preview` route
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct Preview {
+    pub content: String,
+}
+
+/// Rendered struct returned by the `render/preview` route
+#[derive(Debug, Serialize, ToSchema)]
+pub struct Rendered {
+    pub content_html: String,
+}
+
+/// Render content from the `render/preview` route
+/// # Example query
+/// POST requests to this route have a json body with CommonMark content and return the same
+/// sanitized HTML that is sent as `content_html` once the content is saved
+/// `/render/preview`
+/// `{
+///     "content": "Use `read_to_string`:\n\n```rust\nlet s = std::fs::read_to_string(path)?;\n```"
+/// }`
+#[utoipa::path(
+    post,
+    path = "/render/preview",
+    tag = "render",
+    request_body = Preview,
+    responses(
+        (status = 200, description = "Rendered content", body = Rendered),
+        (status = 400, description = "Content is too long", body = String),
+    )
+)]
+pub async fn preview_markdown(Json(preview): Json<Preview>) -> Response {
+    if preview.content.chars().count() > MAX_CONTENT_LENGTH {
+        return (StatusCode::BAD_REQUEST, Err::ContentTooLong.to_string()).into_response();
+    }
+
+    let rendered = Rendered {
+        content_html: render_markdown(&preview.content),
+    };
+    (StatusCode::OK, Json(rendered)).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn code_fences_keep_their_language() {
+        let html = render_markdown("```rust\nlet x = 1 < 2;\n```");
+        assert_eq!(
+            html,
+            "<pre><code class=\"language-rust\">let x = 1 &lt; 2;\n</code></pre>\n"
+        );
+    }
+
+    #[test]
+    fn unsafe_html_is_removed() {
+        let html = render_markdown(
+            "<script>alert(1)</script><img src=x onerror=alert(1)>\n\n\
+            [link](javascript:alert(1)) <code class=\"x\">y</code>",
+        );
+        assert!(!html.contains("script"));
+        assert!(!html.contains("onerror"));
+        assert!(!html.contains("javascript"));
+        assert!(!html.contains("class"));
+        assert!(html.contains("<img src=\"x\">"));
+    }
+}
@@ -0,0 +1,169 @@
+use crate::*;
+
+/// Default number of questions generated by the `load-test` seed set
+const DEFAULT_LOAD_TEST_QUESTIONS: usize = 5_000;
+
+/// Number of generated questions inserted per transaction
+const SEED_BATCH_SIZE: usize = 500;
+
+/// Questions and answers loaded by the `demo` seed set
+const DEMO_SEED: &str = include_str!("../seeds/demo.jsonl");
+
+/// Whether the `seed` route is enabled, read from `ALLOW_SEEDING`
+/// Seeding is off by default so that a production database cannot be filled with sample data
+pub fn seeding_allowed() -> bool {
+    std::env::var("ALLOW_SEEDING")
+        .ok()
+        .and_then(|allow| allow.parse::<bool>().ok())
+        .unwrap_or(false)
+}
+
+/// Number of questions generated by the `load-test` seed set, read from `SEED_LOAD_TEST_QUESTIONS`
+fn load_test_questions() -> usize {
+    std::env::var("SEED_LOAD_TEST_QUESTIONS")
+        .ok()
+        .and_then(|count| count.parse::<usize>().ok())
+        .unwrap_or(DEFAULT_LOAD_TEST_QUESTIONS)
+}
+
+/// Sets of sample data that can be loaded into the database
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, ToSchema)]
+#[serde(rename_all = "kebab-case")]
+pub enum SeedSet {
+    /// No data, combined with a reset this leaves an empty database
+    Empty,
+    /// A handful of hand written questions and answers from `seeds/demo.jsonl`
+    Demo,
+    /// Thousands of generated questions with up to four answers each
+    LoadTest,
+}
+
+impl SeedSet {
+    /// Parse a seed set name, defaulting to the demo set if there is none
+    pub fn parse(set: Option<&str>) -> Result<Self, Err> {
+        match set.map(|s| s.to_lowercase()).as_deref() {
+            Some("empty") => Ok(SeedSet::Empty),
+            None | Some("demo") => Ok(SeedSet::Demo),
+            Some("load-test") | Some("load_test") => Ok(SeedSet::LoadTest),
+            Some(set) => Err(Err::UnknownSeedSet(set.to_owned())),
+        }
+    }
+
+    /// Build the questions in the set
+    fn questions(&self) -> Vec<ImportQuestion> {
+        match self {
+            SeedSet::Empty => Vec::new(),
+            // The demo file is part of the binary, so a row that does not parse is a bug
+            SeedSet::Demo => parse_import(DEMO_SEED, "jsonl")
+                .unwrap_or_default()
+                .into_iter()
+                .map(|(line, question)| {
+                    question.unwrap_or_else(|e| panic!("seeds/demo.jsonl line {}: {}", line, e))
+                })
+                .collect(),
+            SeedSet::LoadTest => (1..=load_test_questions())
+                .map(|i| ImportQuestion {
+                    title: format!("Load test question {}", i),
+                    content: format!("Generated content for load test question {}", i),
+                    tags: Some(vec!["load-test".to_owned(), format!("tag-{}", i % 20)]),
+                    answers: (1..=i % 5)
+                        .map(|j| ImportAnswer {
+                            content: format!("Generated answer {} to load test question {}", j, i),
+                        })
+                        .collect(),
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Seed report struct returned by the `seed` route
+#[derive(Debug, Serialize, Clone, ToSchema)]
+pub struct SeedReport {
+    pub set: SeedSet,
+    pub reset: bool,
+    pub questions: usize,
+    pub answers: usize,
+}
+
+/// Load a seed set into the database, first deleting every question and answer if `reset` is set
+pub async fn seed(store: &mut Store, set: SeedSet, reset: bool) -> Result<SeedReport, sqlx::Error> {
+    if reset {
+        store.delete_all_questions().await?;
+    }
+
+    // Insert the questions in batches so a large set does not hold one huge transaction open
+    let mut report = SeedReport {
+        set,
+        reset,
+        questions: 0,
+        answers: 0,
+    };
+    for batch in set.questions().chunks(SEED_BATCH_SIZE) {
+        let results = store.import_questions(batch, false).await?;
+        for (question, result) in batch.iter().zip(results) {
+            if result.is_ok() {
+                report.questions += 1;
+                report.answers += question.answers.len();
+            }
+        }
+    }
+    tracing::info!(
+        "Seeded {} questions and {} answers from the {:?} set",
+        report.questions,
+        report.answers,
+        set
+    );
+    Ok(report)
+}
+
+/// Load sample data from the `seed` route
+/// # Example query
+/// POST requests to this route load a seed set, `reset=true` deletes every question and answer
+/// first
+/// The route only works when the server is started with `ALLOW_SEEDING=true`, and only for admins
+/// `/seed?set=demo|empty|load-test&reset=true`
+#[utoipa::path(
+    post,
+    path = "/seed",
+    tag = "seed",
+    params(
+        ("set" = Option<String>, Query, description = "`empty`, `demo` or `load-test`, defaults to `demo`"),
+        ("reset" = Option<bool>, Query, description = "Delete every question and answer first"),
+    ),
+    responses(
+        (status = 201, description = "Seed report", body = SeedReport),
+        (status = 400, description = "Parameters are invalid", body = String),
+        (status = 401, description = "Missing or wrong credentials", body = String),
+        (status = 403, description = "Seeding is disabled or the user is not an admin", body = String),
+    ),
+    security(("basic" = []))
+)]
+pub async fn seed_database(
+    State(store): State<Arc<RwLock<Store>>>,
+    _admin: AdminUser,
+    Query(params): Query<HashMap<String, String>>,
+) -> Response {
+    if !seeding_allowed() {
+        return (StatusCode::FORBIDDEN, Err::SeedingDisabled.to_string()).into_response();
+    }
+
+    // Parse the seed set and the reset flag
+    let set = match SeedSet::parse(params.get("set").map(String::as_str)) {
+        Ok(set) => set,
+        Err(e) => return (StatusCode::BAD_REQUEST, e.to_string()).into_response(),
+    };
+    let reset = match params.get("reset").map(|r| r.parse::<bool>()) {
+        Some(Ok(reset)) => reset,
+        Some(Err(e)) => {
+            return (StatusCode::BAD_REQUEST, Err::ParseBool(e).to_string()).into_response()
+        }
+        None => false,
+    };
+
+    // Load the set and return what was added
+    match seed(&mut *store.write().await, set, reset).await {
+        Ok(report) => (StatusCode::CREATED, Json(report)).into_response(),
+        Err(e) => (StatusCode::BAD_REQUEST, e.to_string()).into_response(),
+    }
+}
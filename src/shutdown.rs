@@ -0,0 +1,138 @@
+use crate::*;
+use std::sync::OnceLock;
+use std::time::Duration;
+use tokio::time::Instant;
+use tokio_util::{sync::CancellationToken, task::TaskTracker};
+
+/// Default number of seconds to wait for in-flight requests and background tasks to finish
+const DEFAULT_SHUTDOWN_TIMEOUT: u64 = 30;
+
+/// Shutdown struct shared by the server and any background tasks
+/// Background tasks should be spawned on the tracker and stop once the token is cancelled
+/// The timeout covers the whole drain, from the moment shutdown starts to the background tasks
+/// stopping
+#[derive(Clone)]
+pub struct Shutdown {
+    pub token: CancellationToken,
+    pub tracker: TaskTracker,
+    pub timeout: Duration,
+    drain_deadline: Arc<OnceLock<Instant>>,
+}
+
+impl Default for Shutdown {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Shutdown {
+    /// Constructor that reads the drain timeout (in seconds) from `SHUTDOWN_TIMEOUT`
+    pub fn new() -> Self {
+        let timeout = std::env::var("SHUTDOWN_TIMEOUT")
+            .ok()
+            .and_then(|t| t.parse::<u64>().ok())
+            .unwrap_or(DEFAULT_SHUTDOWN_TIMEOUT);
+
+        Shutdown {
+            token: CancellationToken::new(),
+            tracker: TaskTracker::new(),
+            timeout: Duration::from_secs(timeout),
+            drain_deadline: Arc::new(OnceLock::new()),
+        }
+    }
+
+    /// When the drain has to be over, counted from the first time this is called once shutdown
+    /// has started
+    fn drain_deadline(&self) -> Instant {
+        *self
+            .drain_deadline
+            .get_or_init(|| Instant::now() + self.timeout)
+    }
+
+    /// Wait for SIGINT or SIGTERM and then cancel the token so everything starts shutting down
+    pub async fn listen(self) {
+        let ctrl_c = async {
+            tokio::signal::ctrl_c()
+                .await
+                .expect("failed to install Ctrl+C handler");
+        };
+
+        #[cfg(unix)]
+        let terminate = async {
+            tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+                .expect("failed to install signal handler")
+                .recv()
+                .await;
+        };
+
+        #[cfg(not(unix))]
+        let terminate = std::future::pending::<()>();
+
+        tokio::select! {
+            _ = ctrl_c => {},
+            _ = terminate => {},
+            _ = self.token.cancelled() => {},
+        }
+
+        tracing::info!(
+            "Shutdown signal received, draining for up to {:?}",
+            self.timeout
+        );
+        self.drain_deadline();
+        self.token.cancel();
+    }
+
+    /// Resolve once shutdown has started and the drain timeout has run out
+    pub async fn deadline(&self) {
+        self.token.cancelled().await;
+        tokio::time::sleep_until(self.drain_deadline()).await;
+    }
+
+    /// Stop the background tasks and close the database connections
+    pub async fn finish(&self, store: Arc<RwLock<Store>>) {
+        // Wait for the background tasks to notice the cancelled token and return, in whatever is
+        // left of the drain timeout
+        self.tracker.close();
+        if tokio::time::timeout_at(self.drain_deadline(), self.tracker.wait())
+            .await
+            .is_err()
+        {
+            tracing::warn!("Timed out waiting for background tasks to stop");
+        }
+
+        // Close the pool so the database sees a clean disconnect
+        store.read().await.connection.close().await;
+
+        // Flush the spans that have not been exported yet
+        #[cfg(feature = "otel")]
+        telemetry::shutdown().await;
+        tracing::info!("Shutdown complete");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn the_whole_drain_fits_in_one_timeout() {
+        let shutdown = Shutdown {
+            timeout: Duration::from_millis(300),
+            ..Shutdown::new()
+        };
+        // A background task that never stops
+        let token = CancellationToken::new();
+        shutdown.tracker.spawn(token.cancelled_owned());
+
+        let start = Instant::now();
+        shutdown.token.cancel();
+        shutdown.deadline().await;
+        shutdown
+            .finish(Arc::new(RwLock::new(Store::offline())))
+            .await;
+
+        let elapsed = start.elapsed();
+        assert!(elapsed >= Duration::from_millis(300), "{:?}", elapsed);
+        assert!(elapsed < Duration::from_millis(500), "{:?}", elapsed);
+    }
+}
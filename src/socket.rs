@@ -0,0 +1,231 @@
+use crate::*;
+use axum::extract::ws::{close_code, CloseFrame, Message, WebSocket, WebSocketUpgrade};
+use futures_util::{SinkExt, StreamExt};
+use tokio::sync::{mpsc, oneshot};
+
+/// Default number of messages that can be waiting to be sent to a client before it is disconnected
+const DEFAULT_SOCKET_SEND_QUEUE: usize = 64;
+
+/// How long to wait for the queued messages and the close frame to be sent to a closing client
+const SOCKET_CLOSE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Largest message a client can send, enough for an answer at the content length limit
+const MAX_SOCKET_MESSAGE_SIZE: usize = 64 * 1024;
+
+/// Number of messages that can be waiting to be sent to a client, read from `SOCKET_SEND_QUEUE`
+fn socket_send_queue() -> usize {
+    std::env::var("SOCKET_SEND_QUEUE")
+        .ok()
+        .and_then(|size| size.parse::<usize>().ok())
+        .filter(|size| *size > 0)
+        .unwrap_or(DEFAULT_SOCKET_SEND_QUEUE)
+}
+
+/// Messages that clients can send over a question socket
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type")]
+enum ClientMessage {
+    /// Post an answer to the question the socket is subscribed to
+    #[serde(rename = "answer.create")]
+    CreateAnswer { content: String },
+}
+
+/// Error message sent to a client when one of its messages cannot be handled
+#[derive(Debug, Serialize)]
+struct ErrorMessage {
+    #[serde(rename = "type")]
+    kind: &'static str,
+    message: String,
+}
+
+/// Build the message sent to a client when one of its messages cannot be handled
+fn error_message(message: impl ToString) -> Message {
+    let error = ErrorMessage {
+        kind: "error",
+        message: message.to_string(),
+    };
+    Message::Text(serde_json::to_string(&error).unwrap_or_default())
+}
+
+/// Follow a question over a WebSocket from the `ws/question` route
+/// # Example query
+/// GET requests to this route upgrade to a WebSocket that is sent the `answer.created`,
+/// `question.updated` and `question.deleted` events for the question, in the same format as the
+/// `events` route except that questions and answers carry `content_html` like the HTTP routes,
+/// and is closed once the question is deleted
+/// There are no vote events since questions and answers cannot be voted on
+/// Answers can be posted over the socket with `{"type": "answer.create", "content": "Answer"}`,
+/// each one counts against the client's write rate limit like a POST to the `answer` route
+/// A client that does not keep up with its messages is disconnected
+/// `/ws/question/3`
+#[utoipa::path(
+    get,
+    path = "/ws/question/{id}",
+    tag = "events",
+    params(("id" = i32, Path, description = "Question id")),
+    responses(
+        (status = 101, description = "Switched to a WebSocket that is sent ChangeEvent messages"),
+        (status = 400, description = "Not a WebSocket request or database error", body = String),
+        (status = 404, description = "Question not found", body = String),
+    )
+)]
+pub async fn question_socket(
+    State(store): State<Arc<RwLock<Store>>>,
+    Extension(client): Extension<RateLimitedClient>,
+    Path(id): Path<i32>,
+    upgrade: WebSocketUpgrade,
+) -> Response {
+    // Only open a socket for a question that exists
+    match store.read().await.get_question(&id).await {
+        Ok(_) => {}
+        Err(sqlx::Error::RowNotFound) => {
+            return (StatusCode::NOT_FOUND, Err::QuestionNotFound.to_string()).into_response()
+        }
+        Err(e) => return (StatusCode::BAD_REQUEST, e.to_string()).into_response(),
+    }
+
+    upgrade
+        .max_message_size(MAX_SOCKET_MESSAGE_SIZE)
+        .on_upgrade(move |socket| run_socket(socket, store, client, id))
+}
+
+/// Send the question's events to the client and handle the messages it sends until either side
+/// closes the socket
+async fn run_socket(
+    socket: WebSocket,
+    store: Arc<RwLock<Store>>,
+    client: RateLimitedClient,
+    id: i32,
+) {
+    let (mut sink, mut incoming) = socket.split();
+
+    // Messages for the client go through a bounded queue so a slow client cannot make the
+    // server buffer without limit, the writer closes the socket straight away if it overflows
+    let (queue, mut outgoing) = mpsc::channel::<Message>(socket_send_queue());
+    let (overflow, mut overflowed) = oneshot::channel::<CloseFrame<'static>>();
+    let writer = tokio::spawn(async move {
+        loop {
+            tokio::select! {
+                biased;
+                frame = &mut overflowed => {
+                    let _ = sink.send(Message::Close(frame.ok())).await;
+                    return;
+                }
+                message = outgoing.recv() => match message {
+                    Some(message) => {
+                        if sink.send(message).await.is_err() {
+                            return;
+                        }
+                    }
+                    None => return,
+                },
+            }
+        }
+    });
+
+    // Subscribe after the question was found so that the socket sees every later change
+    let mut subscription = store
+        .read()
+        .await
+        .events
+        .subscribe(EventFilter::question(id), None);
+    let close = loop {
+        tokio::select! {
+            event = subscription.next() => {
+                let Some(event) = event else {
+                    // The server is shutting down or the client fell behind the event hub
+                    break Some(CloseFrame {
+                        code: close_code::AWAY,
+                        reason: "Event stream ended".into(),
+                    });
+                };
+                let message = Message::Text(serde_json::to_string(&event.rendered()).unwrap_or_default());
+                if queue.try_send(message).is_err() {
+                    let _ = overflow.send(CloseFrame {
+                        code: close_code::POLICY,
+                        reason: "Too many messages waiting to be sent".into(),
+                    });
+                    break None;
+                }
+                if event.kind == ChangeKind::QuestionDeleted {
+                    break Some(CloseFrame {
+                        code: close_code::NORMAL,
+                        reason: "Question deleted".into(),
+                    });
+                }
+            }
+            message = incoming.next() => match message {
+                Some(Ok(Message::Text(text))) => {
+                    // The new answer reaches the client through the event hub like any other
+                    // change, so only errors are sent back directly
+                    if let Err(e) = handle_message(&store, &client, id, &text).await {
+                        if queue.try_send(error_message(e)).is_err() {
+                            let _ = overflow.send(CloseFrame {
+                                code: close_code::POLICY,
+                                reason: "Too many messages waiting to be sent".into(),
+                            });
+                            break None;
+                        }
+                    }
+                }
+                Some(Ok(Message::Binary(_))) => {
+                    let _ = queue.try_send(error_message("Binary messages are not supported"));
+                }
+                // Pings are answered automatically
+                Some(Ok(Message::Ping(_))) | Some(Ok(Message::Pong(_))) => {}
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break None,
+            },
+        }
+    };
+
+    // Send the close frame after any queued messages and wait for the writer to finish, giving
+    // up on a client that has stopped reading
+    let writer_handle = writer.abort_handle();
+    let drain = async move {
+        if let Some(frame) = close {
+            let _ = queue.send(Message::Close(Some(frame))).await;
+        }
+        drop(queue);
+        let _ = writer.await;
+    };
+    if tokio::time::timeout(SOCKET_CLOSE_TIMEOUT, drain)
+        .await
+        .is_err()
+    {
+        tracing::warn!("Timed out closing the socket for question {}", id);
+        writer_handle.abort();
+    }
+}
+
+/// Handle one message from a client
+async fn handle_message(
+    store: &Arc<RwLock<Store>>,
+    client: &RateLimitedClient,
+    id: i32,
+    text: &str,
+) -> Result<(), String> {
+    match serde_json::from_str::<ClientMessage>(text).map_err(|e| e.to_string())? {
+        ClientMessage::CreateAnswer { content } => {
+            // The upgrade only counted as a read, so every answer takes from the write budget
+            client.check_write().map_err(|wait| {
+                format!(
+                    "{}, retry after {} seconds",
+                    Err::RateLimited,
+                    wait.as_secs_f64().ceil()
+                )
+            })?;
+            let new_answer = NewAnswer {
+                content,
+                corresponding_question: id,
+            };
+            new_answer.validate().map_err(|e| e.to_string())?;
+            store
+                .write()
+                .await
+                .add_answer(new_answer)
+                .await
+                .map(|_| ())
+                .map_err(|e| e.to_string())
+        }
+    }
+}
@@ -1,14 +1,118 @@
 use crate::*;
 
+/// Migrations embedded from the `migrations` directory at compile time
+pub static MIGRATOR: Migrator = sqlx::migrate!();
+
+/// Number of questions read from the export cursor at a time
+const EXPORT_BATCH_SIZE: i64 = 500;
+
+/// Default number of hours a random question session is remembered after its last question
+const DEFAULT_RANDOM_SESSION_TTL_HOURS: u64 = 24;
+
+/// How long a random question session is remembered, read from `RANDOM_SESSION_TTL_HOURS`
+fn random_session_ttl() -> Duration {
+    let hours = std::env::var("RANDOM_SESSION_TTL_HOURS")
+        .ok()
+        .and_then(|hours| hours.parse::<u64>().ok())
+        .unwrap_or(DEFAULT_RANDOM_SESSION_TTL_HOURS);
+    Duration::from_secs(hours * 60 * 60)
+}
+
+/// Default number of hours an expired quiz is kept for so that its results can still be read
+const DEFAULT_QUIZ_RETENTION_HOURS: u64 = 7 * 24;
+
+/// How long an expired quiz is kept for, read from `QUIZ_RETENTION_HOURS`
+fn quiz_retention() -> Duration {
+    let hours = std::env::var("QUIZ_RETENTION_HOURS")
+        .ok()
+        .and_then(|hours| hours.parse::<u64>().ok())
+        .unwrap_or(DEFAULT_QUIZ_RETENTION_HOURS);
+    Duration::from_secs(hours * 60 * 60)
+}
+
+/// Number of random ids looked up at once before falling back to counting the matches
+const RANDOM_PROBES: usize = 16;
+
+/// Turn 64 random bits into a point between 0 and 1
+fn bits_to_fraction(bits: u64) -> f64 {
+    (bits >> 11) as f64 / (1u64 << 53) as f64
+}
+
+/// Points between 0 and 1 to pick a random question from
+fn random_fractions() -> impl Iterator<Item = f64> {
+    use argon2::password_hash::rand_core::{OsRng, RngCore};
+
+    std::iter::repeat_with(|| bits_to_fraction(OsRng.next_u64()))
+}
+
+/// Points between 0 and 1 to pick the next question of a session from, derived from the session
+/// and the number of questions it has been served so that they do not change between runs
+fn session_fractions(session: &str, served: i64) -> impl Iterator<Item = f64> + '_ {
+    use sha2::{Digest, Sha256};
+
+    (0..).map(move |attempt: u64| {
+        let hash = Sha256::digest(format!("{}:{}:{}", session, served, attempt));
+        bits_to_fraction(u64::from_be_bytes(hash[..8].try_into().unwrap()))
+    })
+}
+
+/// Position picked by a point between 0 and 1 out of `count` candidates
+fn random_position(fraction: f64, count: i64) -> i64 {
+    ((fraction * count as f64) as i64).clamp(0, count - 1)
+}
+
+/// Ids to look up for a random question, each equally likely to be any id from the lowest to the
+/// highest
+fn probe_ids(min: i32, max: i32, fractions: impl Iterator<Item = f64>) -> Vec<i32> {
+    let span = max as i64 - min as i64 + 1;
+    fractions
+        .take(RANDOM_PROBES)
+        .map(|fraction| (min as i64 + random_position(fraction, span)) as i32)
+        .collect()
+}
+
+/// First probed id that was found, so the pick does not depend on the order of the results
+fn first_probed(probes: &[i32], found: &[i32]) -> Option<i32> {
+    probes.iter().copied().find(|id| found.contains(id))
+}
+
+/// Work out which question a duplicate mark of `id` points at, given what `other` is marked as a
+/// duplicate of, so that marks never point at another duplicate
+/// Also returns whether `other` has to stop being a duplicate, which is when it duplicates `id`
+fn duplicate_target(id: i32, other: i32, other_duplicate_of: Option<i32>) -> (i32, bool) {
+    match other_duplicate_of {
+        Some(target) if target == id => (other, true),
+        Some(target) => (target, false),
+        None => (other, false),
+    }
+}
+
 /// Store struct that has a connection to a database
 #[derive(Clone)]
 pub struct Store {
     pub connection: PgPool,
+    pub metrics: Metrics,
+    pub events: Events,
+    pub outbox: Outbox,
+    pub related: RelatedCache,
+    pub files: Arc<dyn FileStore>,
 }
 
 impl Store {
-    /// Constructor to create a datastore and connect to the database
+    /// Constructor to create a datastore, connect to the database and run the migrations
     pub async fn new() -> Result<Self, Box<dyn Error>> {
+        let store = Store::connect().await?;
+
+        // Run the migration files (in the 'migrations' directory)
+        // migrate!() will search the directory with the .toml file for the 'migrations' directory
+        MIGRATOR.run(&store.connection).await?;
+
+        Ok(store)
+    }
+
+    /// Connect to the database without running the migrations, the admin tool uses this so that
+    /// it can run or revert the migrations itself
+    pub async fn connect() -> Result<Self, Box<dyn Error>> {
         use std::env::var;
 
         // Get all of the environment variables and prepare the url
@@ -25,36 +129,150 @@ impl Store {
         // Connect to the database
         let pool = PgPool::connect(&url).await?;
 
-        // Run the migration files (in the 'migrations' directory)
-        // migrate!() will search the directory with the .toml file for the 'migrations' directory
-        sqlx::migrate!().run(&pool).await?;
-
         // Return the data store with a connection to the database
-        Ok(Store { connection: pool })
+        Ok(Store {
+            connection: pool,
+            metrics: Metrics::new()?,
+            events: Events::new(),
+            outbox: Outbox::new(),
+            related: RelatedCache::new(),
+            files: Arc::new(LocalFileStore::new()),
+        })
+    }
+
+    /// Create a data store whose pool never connects, so tests can route requests and handlers
+    /// fail fast instead of hanging
+    #[cfg(test)]
+    pub fn offline() -> Self {
+        let connection = sqlx::postgres::PgPoolOptions::new()
+            .acquire_timeout(Duration::from_millis(100))
+            .connect_lazy("postgres://localhost:1/offline")
+            .unwrap();
+        Store::with_pool(connection)
+    }
+
+    /// Create a data store over a pool that is already set up, such as the migrated database
+    /// `sqlx::test` gives each test
+    #[cfg(test)]
+    pub fn with_pool(connection: PgPool) -> Self {
+        Store {
+            connection,
+            metrics: Metrics::new().unwrap(),
+            events: Events::new(),
+            outbox: Outbox::new(),
+            related: RelatedCache::new(),
+            files: Arc::new(LocalFileStore::new()),
+        }
     }
 
     // Questions
 
-    /// Add a given question to database
-    pub async fn add_question(&mut self, new_question: NewQuestion) -> Result<(), sqlx::Error> {
+    /// Add a given question to database, returning the new question
+    #[tracing::instrument(
+        name = "store.add_question",
+        skip_all,
+        fields(
+            db.system = "postgresql",
+            db.operation = "INSERT",
+            db.sql.table = "questions",
+            db.rows = tracing::field::Empty,
+        )
+    )]
+    pub async fn add_question(
+        &mut self,
+        new_question: NewQuestion,
+    ) -> Result<Question, sqlx::Error> {
+        let _timer = self.metrics.time_store("add_question");
+
         // Create a transaction so that the operation will be atomic since we are modifying the db
         let mut transaction = self.connection.begin().await?;
 
         // Write and execute the query
         match sqlx::query(
             "INSERT INTO questions (title, content, tags)
-                VALUES ($1, $2, $3);",
+                VALUES ($1, $2, $3)
+                RETURNING id;",
         )
-            .bind(new_question.title)
-            .bind(new_question.content)
-            .bind(new_question.tags)
-            .execute(&mut *transaction)
+            .bind(&new_question.title)
+            .bind(&new_question.content)
+            .bind(&new_question.tags)
+            .map(|row: PgRow| row.get("id"))
+            .fetch_one(&mut *transaction)
             .await
         // Match the results from the query and commit the query if ok
         {
-            Ok(_) => {
+            Ok(id) => {
+                tracing::Span::current().record("db.rows", 1);
+                let question = Question {
+                    id,
+                    title: new_question.title,
+                    content: new_question.content,
+                    tags: new_question.tags,
+                };
+                let (kind, tags) = (ChangeKind::QuestionCreated, &question.tags);
+                Self::write_outbox(&mut transaction, kind, id, tags, question.clone()).await?;
                 transaction.commit().await?;
-                Ok(())
+                self.outbox.wake();
+                Ok(question)
+            }
+            Err(e) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", e);
+                Err(e)
+            }
+        }
+    }
+
+    /// Find the questions most similar to a new one, leaving out questions marked as duplicates
+    /// Only questions whose title or content shares enough trigrams with the new one to pass the
+    /// `pg_trgm.similarity_threshold` are compared, so the trigram indexes are used
+    #[tracing::instrument(
+        name = "store.find_duplicate_questions",
+        skip_all,
+        fields(
+            db.system = "postgresql",
+            db.operation = "SELECT",
+            db.sql.table = "questions",
+            db.rows = tracing::field::Empty,
+        )
+    )]
+    pub async fn find_duplicate_questions(
+        &self,
+        title: &str,
+        content: &str,
+        limit: i64,
+    ) -> Result<Vec<DuplicateCandidate>, sqlx::Error> {
+        let _timer = self.metrics.time_store("find_duplicate_questions");
+
+        // Write and execute the query
+        match sqlx::query(
+            "SELECT q.id, q.title, q.content, q.tags,
+                (2 * similarity(q.title, $1) + similarity(q.content, $2)) / 3 AS similarity
+            FROM questions q
+            WHERE (q.title % $1 OR q.content % $2)
+                AND NOT EXISTS (SELECT 1 FROM question_duplicates d WHERE d.question_id = q.id)
+            ORDER BY similarity DESC, q.id
+            LIMIT $3;",
+        )
+        .bind(title)
+        .bind(content)
+        .bind(limit)
+        .map(|row: PgRow| DuplicateCandidate {
+            question: Question {
+                id: row.get("id"),
+                title: row.get("title"),
+                content: row.get("content"),
+                tags: row.get("tags"),
+            }
+            .into(),
+            similarity: row.get("similarity"),
+        })
+        .fetch_all(&self.connection)
+        .await
+        // Match the results from the query and return the candidates if ok
+        {
+            Ok(candidates) => {
+                tracing::Span::current().record("db.rows", candidates.len());
+                Ok(candidates)
             }
             Err(e) => {
                 tracing::event!(tracing::Level::ERROR, "{:?}", e);
@@ -64,11 +282,23 @@ impl Store {
     }
 
     /// Get items from the database, apply a limit and offset if applicable
+    #[tracing::instrument(
+        name = "store.get_questions",
+        skip_all,
+        fields(
+            db.system = "postgresql",
+            db.operation = "SELECT",
+            db.sql.table = "questions",
+            db.rows = tracing::field::Empty,
+        )
+    )]
     pub async fn get_questions(
         &self,
         limit: Option<i32>,
         offset: i32,
     ) -> Result<Vec<Question>, sqlx::Error> {
+        let _timer = self.metrics.time_store("get_questions");
+
         // Write and execute the query
         match sqlx::query("SELECT * FROM questions LIMIT $1 OFFSET $2;")
             .bind(limit)
@@ -83,7 +313,10 @@ impl Store {
             .await
         // Match the results from the query and return the questions if ok
         {
-            Ok(questions) => Ok(questions),
+            Ok(questions) => {
+                tracing::Span::current().record("db.rows", questions.len());
+                Ok(questions)
+            }
             Err(e) => {
                 tracing::event!(tracing::Level::ERROR, "{:?}", e);
                 Err(e)
@@ -92,7 +325,19 @@ impl Store {
     }
 
     /// Get an item from the database given a specified id
+    #[tracing::instrument(
+        name = "store.get_question",
+        skip_all,
+        fields(
+            db.system = "postgresql",
+            db.operation = "SELECT",
+            db.sql.table = "questions",
+            db.rows = tracing::field::Empty,
+        )
+    )]
     pub async fn get_question(&self, id: &i32) -> Result<Question, sqlx::Error> {
+        let _timer = self.metrics.time_store("get_question");
+
         // Write and execute the query
         match sqlx::query("SELECT * FROM questions WHERE id = $1;")
             .bind(id)
@@ -106,7 +351,446 @@ impl Store {
             .await
         // Match the results from the query and return the question if ok
         {
-            Ok(q) => Ok(q),
+            Ok(q) => {
+                tracing::Span::current().record("db.rows", 1);
+                Ok(q)
+            }
+            Err(e) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", e);
+                Err(e)
+            }
+        }
+    }
+
+    /// Get an item from the database given a specified id, with up to `answer_limit` of its oldest
+    /// answers and the total number of answers
+    /// Everything is read in one statement so the answers are from the same snapshot as the
+    /// question
+    #[tracing::instrument(
+        name = "store.get_question_with_answers",
+        skip_all,
+        fields(
+            db.system = "postgresql",
+            db.operation = "SELECT",
+            db.sql.table = "questions, answers",
+            db.rows = tracing::field::Empty,
+        )
+    )]
+    pub async fn get_question_with_answers(
+        &self,
+        id: &i32,
+        answer_limit: i64,
+    ) -> Result<QuestionWithAnswers, sqlx::Error> {
+        let _timer = self.metrics.time_store("get_question_with_answers");
+
+        // Write and execute the query
+        match sqlx::query(
+            "SELECT q.id, q.title, q.content, q.tags,
+                (SELECT COALESCE(json_agg(a ORDER BY a.id), '[]')
+                    FROM (
+                        SELECT id, content, corresponding_question FROM answers
+                        WHERE corresponding_question = q.id
+                        ORDER BY id
+                        LIMIT $2
+                    ) a) AS answers,
+                (SELECT COUNT(*) FROM answers WHERE corresponding_question = q.id) AS answer_count
+            FROM questions q
+            WHERE q.id = $1;",
+        )
+        .bind(id)
+        .bind(answer_limit)
+        .map(|row: PgRow| QuestionWithAnswers {
+            question: Question {
+                id: row.get("id"),
+                title: row.get("title"),
+                content: row.get("content"),
+                tags: row.get("tags"),
+            }
+            .into(),
+            answers: row
+                .get::<sqlx::types::Json<Vec<Answer>>, _>("answers")
+                .0
+                .into_iter()
+                .map(Into::into)
+                .collect(),
+            answer_count: row.get("answer_count"),
+        })
+        .fetch_one(&self.connection)
+        .await
+        // Match the results from the query and return the question if ok
+        {
+            Ok(q) => {
+                tracing::Span::current().record("db.rows", 1 + q.answers.len());
+                Ok(q)
+            }
+            Err(e) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", e);
+                Err(e)
+            }
+        }
+    }
+
+    /// Get a random item from the database that passes a filter
+    /// Random ids between the lowest and highest are looked up and the first one that exists and
+    /// passes the filter is returned, so only the index is read rather than the whole table
+    /// If none of them match, the questions that pass the filter are counted and one is picked by
+    /// its position, so every question that passes is as likely to come up however the ids are
+    /// spread out
+    /// With a session no question is returned twice until every question that passes the filter
+    /// has been, then the session starts over, and the same session gets the same order
+    #[tracing::instrument(
+        name = "store.get_random_question",
+        skip_all,
+        fields(
+            db.system = "postgresql",
+            db.operation = "SELECT",
+            db.sql.table = "questions",
+            db.rows = tracing::field::Empty,
+        )
+    )]
+    pub async fn get_random_question(
+        &self,
+        filter: &RandomFilter,
+        session: Option<&str>,
+    ) -> Result<Question, sqlx::Error> {
+        let _timer = self.metrics.time_store("get_random_question");
+
+        // Write and execute the queries
+        let res = match session {
+            Some(session) => self.get_random_session_question(filter, session).await,
+            None => {
+                let mut connection = self.connection.acquire().await?;
+                Self::probe_random_question(&mut connection, random_fractions(), filter, None).await
+            }
+        };
+
+        // Match the results from the query and return the question if ok
+        match res {
+            Ok(Some(q)) => {
+                tracing::Span::current().record("db.rows", 1);
+                Ok(q)
+            }
+            Ok(None) => Err(sqlx::Error::RowNotFound),
+            Err(e) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", e);
+                Err(e)
+            }
+        }
+    }
+
+    /// Pick the next question for a session and record that it has been served
+    async fn get_random_session_question(
+        &self,
+        filter: &RandomFilter,
+        session: &str,
+    ) -> Result<Option<Question>, sqlx::Error> {
+        // Create a transaction so that the operation will be atomic since we are modifying the db
+        let mut transaction = self.connection.begin().await?;
+
+        // Serve one question at a time per session so that parallel requests do not get the same
+        sqlx::query("SELECT pg_advisory_xact_lock(hashtext($1));")
+            .bind(session)
+            .execute(&mut *transaction)
+            .await?;
+
+        // Forget what idle sessions were served
+        sqlx::query(
+            "DELETE FROM question_sessions
+                WHERE served_on < NOW() - make_interval(secs => $1);",
+        )
+        .bind(random_session_ttl().as_secs_f64())
+        .execute(&mut *transaction)
+        .await?;
+
+        // Seed the pick with the session and how far through it is
+        let served: i64 =
+            sqlx::query("SELECT COUNT(*) AS served FROM question_sessions WHERE session = $1;")
+                .bind(session)
+                .map(|row: PgRow| row.get("served"))
+                .fetch_one(&mut *transaction)
+                .await?;
+        let fractions = || session_fractions(session, served);
+
+        let question =
+            match Self::probe_random_question(&mut transaction, fractions(), filter, Some(session))
+                .await?
+            {
+                Some(question) => question,
+                None => {
+                    // Every question that passes the filter has been served, start the session over
+                    // without repeating the last question unless it is the only one left
+                    let last: Option<i32> = sqlx::query(
+                        "SELECT question_id FROM question_sessions WHERE session = $1
+                        ORDER BY served_on DESC LIMIT 1;",
+                    )
+                    .bind(session)
+                    .map(|row: PgRow| row.get("question_id"))
+                    .fetch_optional(&mut *transaction)
+                    .await?;
+                    sqlx::query("DELETE FROM question_sessions WHERE session = $1;")
+                        .bind(session)
+                        .execute(&mut *transaction)
+                        .await?;
+                    let mut not_last = filter.clone();
+                    not_last.exclude.extend(last);
+                    match Self::probe_random_question(
+                        &mut transaction,
+                        fractions(),
+                        &not_last,
+                        None,
+                    )
+                    .await?
+                    {
+                        Some(question) => question,
+                        None => match Self::probe_random_question(
+                            &mut transaction,
+                            fractions(),
+                            filter,
+                            None,
+                        )
+                        .await?
+                        {
+                            Some(question) => question,
+                            None => return Ok(None),
+                        },
+                    }
+                }
+            };
+
+        sqlx::query(
+            "INSERT INTO question_sessions (session, question_id)
+                VALUES ($1, $2)
+                ON CONFLICT (session, question_id) DO UPDATE SET served_on = NOW();",
+        )
+        .bind(session)
+        .bind(question.id)
+        .execute(&mut *transaction)
+        .await?;
+        transaction.commit().await?;
+        Ok(Some(question))
+    }
+
+    /// Pick a question that passes the filter, every one that does is as likely
+    /// `fractions` are the points between 0 and 1 that the ids to look up and the position to fall
+    /// back on are picked with
+    async fn probe_random_question(
+        connection: &mut PgConnection,
+        mut fractions: impl Iterator<Item = f64>,
+        filter: &RandomFilter,
+        session: Option<&str>,
+    ) -> Result<Option<Question>, sqlx::Error> {
+        let filters = "($1::text IS NULL
+                OR EXISTS (SELECT 1 FROM unnest(q.tags) AS tag WHERE lower(tag) = lower($1)))
+            AND ($2::boolean IS NULL
+                OR EXISTS (SELECT 1 FROM answers WHERE corresponding_question = q.id) = $2)
+            AND q.id <> ALL($3)
+            AND ($4::text IS NULL OR NOT EXISTS (
+                SELECT 1 FROM question_sessions s WHERE s.session = $4 AND s.question_id = q.id
+            ))";
+        let to_question = |row: PgRow| Question {
+            id: row.get("id"),
+            title: row.get("title"),
+            content: row.get("content"),
+            tags: row.get("tags"),
+        };
+
+        let (min, max): (Option<i32>, Option<i32>) =
+            sqlx::query("SELECT MIN(id) AS min, MAX(id) AS max FROM questions;")
+                .map(|row: PgRow| (row.get("min"), row.get("max")))
+                .fetch_one(&mut *connection)
+                .await?;
+        let (Some(min), Some(max)) = (min, max) else {
+            return Ok(None);
+        };
+
+        // Look up random ids through the primary key index and take the first that matches, an
+        // id only counts if it is hit exactly so that questions after a gap are not favoured
+        let probes = probe_ids(min, max, &mut fractions);
+        let found: Vec<Question> = sqlx::query(&format!(
+            "SELECT q.id, q.title, q.content, q.tags FROM questions q
+                WHERE q.id = ANY($5) AND {filters};"
+        ))
+        .bind(&filter.tag)
+        .bind(filter.answered)
+        .bind(&filter.exclude)
+        .bind(session)
+        .bind(&probes)
+        .map(to_question)
+        .fetch_all(&mut *connection)
+        .await?;
+        let ids: Vec<i32> = found.iter().map(|q| q.id).collect();
+        if let Some(id) = first_probed(&probes, &ids) {
+            return Ok(found.into_iter().find(|q| q.id == id));
+        }
+
+        // Few questions pass the filter, so count them and pick one by its position
+        let count: i64 = sqlx::query(&format!(
+            "SELECT COUNT(*) AS count FROM questions q WHERE {filters};"
+        ))
+        .bind(&filter.tag)
+        .bind(filter.answered)
+        .bind(&filter.exclude)
+        .bind(session)
+        .map(|row: PgRow| row.get("count"))
+        .fetch_one(&mut *connection)
+        .await?;
+        if count == 0 {
+            return Ok(None);
+        }
+        let offset = random_position(fractions.next().unwrap_or_default(), count);
+        sqlx::query(&format!(
+            "SELECT q.id, q.title, q.content, q.tags FROM questions q
+                WHERE {filters}
+                ORDER BY q.id OFFSET $5 LIMIT 1;"
+        ))
+        .bind(&filter.tag)
+        .bind(filter.answered)
+        .bind(&filter.exclude)
+        .bind(session)
+        .bind(offset)
+        .map(to_question)
+        .fetch_optional(connection)
+        .await
+    }
+
+    /// Get the questions most related to a question, from the cache if they are there
+    /// Questions that share a tag or have a similar title are ranked by the number of tags they
+    /// share, plus how similar their title and content are, and the top ones are cached
+    #[tracing::instrument(
+        name = "store.get_related_questions",
+        skip_all,
+        fields(
+            db.system = "postgresql",
+            db.operation = "SELECT",
+            db.sql.table = "questions",
+            db.rows = tracing::field::Empty,
+        )
+    )]
+    pub async fn get_related_questions(
+        &self,
+        id: &i32,
+    ) -> Result<Arc<Vec<RelatedQuestion>>, sqlx::Error> {
+        if let Some(related) = self.related.get(*id) {
+            return Ok(related);
+        }
+        let _timer = self.metrics.time_store("get_related_questions");
+
+        // Check that the question exists so that a missing one is not cached as having none
+        sqlx::query("SELECT id FROM questions WHERE id = $1;")
+            .bind(id)
+            .fetch_one(&self.connection)
+            .await?;
+
+        // Write and execute the query
+        match sqlx::query(
+            "SELECT q.id, q.title, q.content, q.tags, r.shared_tags, r.similarity,
+                r.shared_tags + r.similarity AS score
+            FROM questions s
+            JOIN questions q ON q.id <> s.id
+            CROSS JOIN LATERAL (
+                SELECT
+                    (SELECT COUNT(DISTINCT lower(tag)) FROM unnest(q.tags) AS tag
+                        WHERE lower(tag) IN (SELECT lower(other) FROM unnest(s.tags) AS other)
+                    ) AS shared_tags,
+                    (2 * similarity(q.title, s.title) + similarity(q.content, s.content)) / 3
+                        AS similarity
+            ) r
+            WHERE s.id = $1 AND (r.shared_tags > 0 OR q.title % s.title)
+            ORDER BY score DESC, q.id
+            LIMIT $2;",
+        )
+        .bind(id)
+        .bind(MAX_RELATED_LIMIT)
+        .map(|row: PgRow| RelatedQuestion {
+            question: Question {
+                id: row.get("id"),
+                title: row.get("title"),
+                content: row.get("content"),
+                tags: row.get("tags"),
+            }
+            .into(),
+            shared_tags: row.get("shared_tags"),
+            similarity: row.get("similarity"),
+            score: row.get("score"),
+        })
+        .fetch_all(&self.connection)
+        .await
+        // Match the results from the query, cache them and return them if ok
+        {
+            Ok(related) => {
+                tracing::Span::current().record("db.rows", related.len());
+                let related = Arc::new(related);
+                self.related.insert(*id, related.clone());
+                Ok(related)
+            }
+            Err(e) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", e);
+                Err(e)
+            }
+        }
+    }
+
+    /// Find questions that pass a filter, sorted and paginated
+    #[tracing::instrument(
+        name = "store.search_questions",
+        skip_all,
+        fields(
+            db.system = "postgresql",
+            db.operation = "SELECT",
+            db.sql.table = "questions",
+            db.rows = tracing::field::Empty,
+        )
+    )]
+    pub async fn search_questions(
+        &self,
+        filter: &QuestionFilter,
+        sort: QuestionSort,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<Question>, sqlx::Error> {
+        let _timer = self.metrics.time_store("search_questions");
+
+        // Escape the wildcards in the search text so that it is matched literally
+        let search = filter.search.as_ref().map(|search| {
+            let escaped = search
+                .replace('\\', "\\\\")
+                .replace('%', "\\%")
+                .replace('_', "\\_");
+            format!("%{}%", escaped)
+        });
+
+        // Write and execute the query, the order comes from a fixed list so it is safe to format in
+        match sqlx::query(&format!(
+            "SELECT * FROM questions
+                WHERE ($1::text IS NULL
+                        OR EXISTS (SELECT 1 FROM unnest(tags) AS tag WHERE lower(tag) = lower($1)))
+                    AND ($2::text IS NULL OR title ILIKE $2 OR content ILIKE $2)
+                    AND ($3::boolean IS NULL OR $3 = EXISTS (
+                        SELECT 1 FROM answers WHERE corresponding_question = questions.id
+                    ))
+                ORDER BY {}
+                LIMIT $4 OFFSET $5;",
+            sort.order_by()
+        ))
+        .bind(&filter.tag)
+        .bind(search)
+        .bind(filter.answered)
+        .bind(limit)
+        .bind(offset)
+        .map(|row: PgRow| Question {
+            id: row.get("id"),
+            title: row.get("title"),
+            content: row.get("content"),
+            tags: row.get("tags"),
+        })
+        .fetch_all(&self.connection)
+        .await
+        // Match the results from the query and return the questions if ok
+        {
+            Ok(questions) => {
+                tracing::Span::current().record("db.rows", questions.len());
+                Ok(questions)
+            }
             Err(e) => {
                 tracing::event!(tracing::Level::ERROR, "{:?}", e);
                 Err(e)
@@ -114,21 +798,76 @@ impl Store {
         }
     }
 
-    /// Get a random item from the database
-    pub async fn get_random_question(&self) -> Result<Question, sqlx::Error> {
+    /// Get the questions with any of the given ids, in no particular order
+    #[tracing::instrument(
+        name = "store.get_questions_by_ids",
+        skip_all,
+        fields(
+            db.system = "postgresql",
+            db.operation = "SELECT",
+            db.sql.table = "questions",
+            db.rows = tracing::field::Empty,
+        )
+    )]
+    pub async fn get_questions_by_ids(&self, ids: &[i32]) -> Result<Vec<Question>, sqlx::Error> {
+        let _timer = self.metrics.time_store("get_questions_by_ids");
+
         // Write and execute the query
-        match sqlx::query("SELECT * FROM questions ORDER BY RANDOM () LIMIT 1;")
+        match sqlx::query("SELECT * FROM questions WHERE id = ANY($1);")
+            .bind(ids)
             .map(|row: PgRow| Question {
                 id: row.get("id"),
                 title: row.get("title"),
                 content: row.get("content"),
                 tags: row.get("tags"),
             })
-            .fetch_one(&self.connection)
+            .fetch_all(&self.connection)
             .await
-        // Match the results from the query and return the question if ok
+        // Match the results from the query and return the questions if ok
+        {
+            Ok(questions) => {
+                tracing::Span::current().record("db.rows", questions.len());
+                Ok(questions)
+            }
+            Err(e) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", e);
+                Err(e)
+            }
+        }
+    }
+
+    /// Get every tag used by a question and the number of questions using it, most used first
+    #[tracing::instrument(
+        name = "store.get_tags",
+        skip_all,
+        fields(
+            db.system = "postgresql",
+            db.operation = "SELECT",
+            db.sql.table = "questions",
+            db.rows = tracing::field::Empty,
+        )
+    )]
+    pub async fn get_tags(&self) -> Result<Vec<TagCount>, sqlx::Error> {
+        let _timer = self.metrics.time_store("get_tags");
+
+        // Write and execute the query
+        match sqlx::query(
+            "SELECT tag, COUNT(*) AS questions FROM questions, unnest(tags) AS tag
+                GROUP BY tag
+                ORDER BY questions DESC, tag;",
+        )
+        .map(|row: PgRow| TagCount {
+            tag: row.get("tag"),
+            questions: row.get("questions"),
+        })
+        .fetch_all(&self.connection)
+        .await
+        // Match the results from the query and return the tags if ok
         {
-            Ok(q) => Ok(q),
+            Ok(tags) => {
+                tracing::Span::current().record("db.rows", tags.len());
+                Ok(tags)
+            }
             Err(e) => {
                 tracing::event!(tracing::Level::ERROR, "{:?}", e);
                 Err(e)
@@ -136,32 +875,61 @@ impl Store {
         }
     }
 
-    /// Update a question in the database given a specified id and new data
+    /// Update a question in the database given a specified id and new data, returning the updated
+    /// question or `None` if there is no question with the id
+    #[tracing::instrument(
+        name = "store.update_question",
+        skip_all,
+        fields(
+            db.system = "postgresql",
+            db.operation = "UPDATE",
+            db.sql.table = "questions",
+            db.rows = tracing::field::Empty,
+        )
+    )]
     pub async fn update_question(
         &mut self,
         id: &i32,
         new_question: NewQuestion,
-    ) -> Result<(), sqlx::Error> {
+    ) -> Result<Option<Question>, sqlx::Error> {
+        let _timer = self.metrics.time_store("update_question");
+
         // Create a transaction so that the operation will be atomic since we are modifying the db
         let mut transaction = self.connection.begin().await?;
 
         // Write and execute the query
         match sqlx::query(
             "UPDATE questions 
-                SET title = $1, content = $2, tags = $3
-                WHERE id = $4;",
+                SET title = $1, content = $2, tags = $3, updated_on = NOW()
+                WHERE id = $4
+                RETURNING id, title, content, tags;",
         )
         .bind(new_question.title)
         .bind(new_question.content)
         .bind(new_question.tags)
         .bind(id)
-        .execute(&mut *transaction)
+        .map(|row: PgRow| Question {
+            id: row.get("id"),
+            title: row.get("title"),
+            content: row.get("content"),
+            tags: row.get("tags"),
+        })
+        .fetch_optional(&mut *transaction)
         .await
         // Match the results from the query and commit the query if ok
         {
-            Ok(_) => {
+            Ok(question) => {
+                tracing::Span::current().record("db.rows", question.iter().count());
+                if let Some(question) = &question {
+                    let (kind, tags) = (ChangeKind::QuestionUpdated, &question.tags);
+                    Self::write_outbox(&mut transaction, kind, question.id, tags, question.clone())
+                        .await?;
+                }
                 transaction.commit().await?;
-                Ok(())
+                self.outbox.wake();
+                // The relay drops the cached entries too, this makes the change show up at once
+                self.related.invalidate(*id);
+                Ok(question)
             }
             Err(e) => {
                 tracing::event!(tracing::Level::ERROR, "{:?}", e);
@@ -170,21 +938,46 @@ impl Store {
         }
     }
 
-    /// Delete a question from the database fr given a specified id
-    pub async fn delete_question(&mut self, id: &i32) -> Result<(), sqlx::Error> {
+    /// Delete a question from the database fr given a specified id, returning whether it existed
+    #[tracing::instrument(
+        name = "store.delete_question",
+        skip_all,
+        fields(
+            db.system = "postgresql",
+            db.operation = "DELETE",
+            db.sql.table = "questions",
+            db.rows = tracing::field::Empty,
+        )
+    )]
+    pub async fn delete_question(&mut self, id: &i32) -> Result<bool, sqlx::Error> {
+        let _timer = self.metrics.time_store("delete_question");
+
         // Create a transaction so that the operation will be atomic since we are modifying the db
         let mut transaction = self.connection.begin().await?;
 
+        // The attachment rows go with the question, so their files are looked up first
+        let keys = Self::attachment_keys(&mut transaction, Some(*id)).await?;
+
         // Write and execute the query
-        match sqlx::query("DELETE FROM questions WHERE id = $1;")
+        match sqlx::query("DELETE FROM questions WHERE id = $1 RETURNING id, tags;")
             .bind(id)
-            .execute(&mut *transaction)
+            .map(|row: PgRow| (row.get("id"), row.get("tags")))
+            .fetch_optional(&mut *transaction)
             .await
         // Match the results from the query and commit the query if ok
         {
-            Ok(_) => {
+            Ok(deleted) => {
+                tracing::Span::current().record("db.rows", deleted.iter().count());
+                let found = deleted.is_some();
+                if let Some((id, tags)) = deleted {
+                    let (kind, data) = (ChangeKind::QuestionDeleted, serde_json::json!({ "id": id }));
+                    Self::write_outbox(&mut transaction, kind, id, &tags, data).await?;
+                }
                 transaction.commit().await?;
-                Ok(())
+                self.outbox.wake();
+                self.related.invalidate(*id);
+                self.delete_files(keys).await;
+                Ok(found)
             }
             Err(e) => {
                 tracing::event!(tracing::Level::ERROR, "{:?}", e);
@@ -193,28 +986,52 @@ impl Store {
         }
     }
 
-    // Answers
+    /// Delete every question from the database, their answers are deleted with them
+    #[tracing::instrument(
+        name = "store.delete_all_questions",
+        skip_all,
+        fields(
+            db.system = "postgresql",
+            db.operation = "DELETE",
+            db.sql.table = "questions",
+            db.rows = tracing::field::Empty,
+        )
+    )]
+    pub async fn delete_all_questions(&mut self) -> Result<u64, sqlx::Error> {
+        let _timer = self.metrics.time_store("delete_all_questions");
+
+        // Create a transaction so that the operation will be atomic since we are modifying the db
+        let mut transaction = self.connection.begin().await?;
+
+        // The attachment rows go with the questions, so their files are looked up first
+        let keys = Self::attachment_keys(&mut transaction, None).await?;
 
-    /// Get items from the database, apply a limit and offset if applicable
-    pub async fn get_answers(
-        &self,
-        limit: Option<i32>,
-        offset: i32,
-    ) -> Result<Vec<Answer>, sqlx::Error> {
         // Write and execute the query
-        match sqlx::query("SELECT * FROM answers LIMIT $1 OFFSET $2;")
-            .bind(limit)
-            .bind(offset)
-            .map(|row: PgRow| Answer {
-                id: row.get("id"),
-                content: row.get("content"),
-                corresponding_question: row.get("corresponding_question"),
-            })
-            .fetch_all(&self.connection)
-            .await
-        // Match the results from the query and return the answers if ok
+        // The outbox gets a deleted event for every question in the same statement
+        match sqlx::query(
+            "WITH deleted AS (
+                DELETE FROM questions RETURNING id, tags
+            ), events AS (
+                INSERT INTO outbox (event_type, question_id, tags, payload)
+                SELECT $1, id, tags, jsonb_build_object('id', id) FROM deleted
+                RETURNING id
+            )
+            SELECT COUNT(*) AS count FROM events;",
+        )
+        .bind(ChangeKind::QuestionDeleted.as_str())
+        .map(|row: PgRow| row.get::<i64, _>("count"))
+        .fetch_one(&mut *transaction)
+        .await
+        // Match the results from the query and commit the query if ok
         {
-            Ok(answers) => Ok(answers),
+            Ok(count) => {
+                tracing::Span::current().record("db.rows", count);
+                transaction.commit().await?;
+                self.outbox.wake();
+                self.related.clear();
+                self.delete_files(keys).await;
+                Ok(count as u64)
+            }
             Err(e) => {
                 tracing::event!(tracing::Level::ERROR, "{:?}", e);
                 Err(e)
@@ -222,25 +1039,90 @@ impl Store {
         }
     }
 
-    /// Add a given answer to database
-    pub async fn add_answer(&mut self, new_answer: NewAnswer) -> Result<(), sqlx::Error> {
+    /// Mark a question as a duplicate of another
+    /// Duplicates always point at a question that is not a duplicate itself: if the other
+    /// question is a duplicate the mark points at what it duplicates, unless that is this
+    /// question, then the other question stops being a duplicate, and anything marked as a
+    /// duplicate of this question is moved over
+    #[tracing::instrument(
+        name = "store.mark_duplicate_question",
+        skip_all,
+        fields(
+            db.system = "postgresql",
+            db.operation = "INSERT",
+            db.sql.table = "question_duplicates",
+            db.rows = tracing::field::Empty,
+        )
+    )]
+    pub async fn mark_duplicate_question(
+        &mut self,
+        id: &i32,
+        other: &i32,
+    ) -> Result<Duplicate, sqlx::Error> {
+        let _timer = self.metrics.time_store("mark_duplicate_question");
+
         // Create a transaction so that the operation will be atomic since we are modifying the db
         let mut transaction = self.connection.begin().await?;
 
+        // Both questions have to exist, lock them so that parallel marks of either wait
+        let found: i64 = sqlx::query(
+            "SELECT COUNT(*) AS found FROM (
+                SELECT id FROM questions WHERE id IN ($1, $2) ORDER BY id FOR UPDATE
+            ) q;",
+        )
+        .bind(id)
+        .bind(other)
+        .map(|row: PgRow| row.get("found"))
+        .fetch_one(&mut *transaction)
+        .await?;
+        if found < 2 {
+            return Err(sqlx::Error::RowNotFound);
+        }
+
+        // Point at the question the other one duplicates, if it is one
+        let other_duplicate_of: Option<i32> =
+            sqlx::query("SELECT duplicate_of FROM question_duplicates WHERE question_id = $1;")
+                .bind(other)
+                .map(|row: PgRow| row.get("duplicate_of"))
+                .fetch_optional(&mut *transaction)
+                .await?;
+        let (target, unmark_other) = duplicate_target(*id, *other, other_duplicate_of);
+        if unmark_other {
+            sqlx::query("DELETE FROM question_duplicates WHERE question_id = $1;")
+                .bind(other)
+                .execute(&mut *transaction)
+                .await?;
+        }
+        sqlx::query("UPDATE question_duplicates SET duplicate_of = $2 WHERE duplicate_of = $1;")
+            .bind(id)
+            .bind(target)
+            .execute(&mut *transaction)
+            .await?;
+
         // Write and execute the query
         match sqlx::query(
-            "INSERT INTO answers (content, corresponding_question)
-                VALUES ($1, $2);",
+            "INSERT INTO question_duplicates (question_id, duplicate_of)
+                VALUES ($1, $2)
+                ON CONFLICT (question_id) DO UPDATE SET
+                    duplicate_of = EXCLUDED.duplicate_of,
+                    marked_on = NOW()
+                RETURNING question_id, duplicate_of, marked_on;",
         )
-            .bind(new_answer.content)
-            .bind(new_answer.corresponding_question)
-            .execute(&mut *transaction)
-            .await
+        .bind(id)
+        .bind(target)
+        .map(|row: PgRow| Duplicate {
+            question_id: row.get("question_id"),
+            duplicate_of: row.get("duplicate_of"),
+            marked_on: row.get("marked_on"),
+        })
+        .fetch_one(&mut *transaction)
+        .await
         // Match the results from the query and commit the query if ok
         {
-            Ok(_) => {
+            Ok(duplicate) => {
+                tracing::Span::current().record("db.rows", 1);
                 transaction.commit().await?;
-                Ok(())
+                Ok(duplicate)
             }
             Err(e) => {
                 tracing::event!(tracing::Level::ERROR, "{:?}", e);
@@ -248,4 +1130,1963 @@ impl Store {
             }
         }
     }
+
+    // Answers
+
+    /// Get items from the database, apply a limit and offset if applicable
+    #[tracing::instrument(
+        name = "store.get_answers",
+        skip_all,
+        fields(
+            db.system = "postgresql",
+            db.operation = "SELECT",
+            db.sql.table = "answers",
+            db.rows = tracing::field::Empty,
+        )
+    )]
+    pub async fn get_answers(
+        &self,
+        limit: Option<i32>,
+        offset: i32,
+    ) -> Result<Vec<Answer>, sqlx::Error> {
+        let _timer = self.metrics.time_store("get_answers");
+
+        // Write and execute the query
+        match sqlx::query("SELECT * FROM answers LIMIT $1 OFFSET $2;")
+            .bind(limit)
+            .bind(offset)
+            .map(|row: PgRow| Answer {
+                id: row.get("id"),
+                content: row.get("content"),
+                corresponding_question: row.get("corresponding_question"),
+            })
+            .fetch_all(&self.connection)
+            .await
+        // Match the results from the query and return the answers if ok
+        {
+            Ok(answers) => {
+                tracing::Span::current().record("db.rows", answers.len());
+                Ok(answers)
+            }
+            Err(e) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", e);
+                Err(e)
+            }
+        }
+    }
+
+    /// Get the answers to any of the given questions, oldest first
+    #[tracing::instrument(
+        name = "store.get_answers_for_questions",
+        skip_all,
+        fields(
+            db.system = "postgresql",
+            db.operation = "SELECT",
+            db.sql.table = "answers",
+            db.rows = tracing::field::Empty,
+        )
+    )]
+    pub async fn get_answers_for_questions(
+        &self,
+        question_ids: &[i32],
+    ) -> Result<Vec<Answer>, sqlx::Error> {
+        let _timer = self.metrics.time_store("get_answers_for_questions");
+
+        // Write and execute the query
+        match sqlx::query("SELECT * FROM answers WHERE corresponding_question = ANY($1) ORDER BY id;")
+            .bind(question_ids)
+            .map(|row: PgRow| Answer {
+                id: row.get("id"),
+                content: row.get("content"),
+                corresponding_question: row.get("corresponding_question"),
+            })
+            .fetch_all(&self.connection)
+            .await
+        // Match the results from the query and return the answers if ok
+        {
+            Ok(answers) => {
+                tracing::Span::current().record("db.rows", answers.len());
+                Ok(answers)
+            }
+            Err(e) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", e);
+                Err(e)
+            }
+        }
+    }
+
+    /// Add a given answer to database, returning the new answer
+    #[tracing::instrument(
+        name = "store.add_answer",
+        skip_all,
+        fields(
+            db.system = "postgresql",
+            db.operation = "INSERT",
+            db.sql.table = "answers",
+            db.rows = tracing::field::Empty,
+        )
+    )]
+    pub async fn add_answer(&mut self, new_answer: NewAnswer) -> Result<Answer, sqlx::Error> {
+        let _timer = self.metrics.time_store("add_answer");
+
+        // Create a transaction so that the operation will be atomic since we are modifying the db
+        let mut transaction = self.connection.begin().await?;
+
+        // Write and execute the query
+        // The tags of the question are returned so that the new answer event can be filtered by tag
+        match sqlx::query(
+            "WITH answer AS (
+                INSERT INTO answers (content, corresponding_question)
+                VALUES ($1, $2)
+                RETURNING id, corresponding_question
+            )
+            SELECT answer.id, questions.tags FROM answer
+                LEFT JOIN questions ON questions.id = answer.corresponding_question;",
+        )
+            .bind(&new_answer.content)
+            .bind(new_answer.corresponding_question)
+            .map(|row: PgRow| (row.get("id"), row.get("tags")))
+            .fetch_one(&mut *transaction)
+            .await
+        // Match the results from the query and commit the query if ok
+        {
+            Ok((id, tags)) => {
+                tracing::Span::current().record("db.rows", 1);
+                let question_id = new_answer.corresponding_question;
+                let answer = Answer {
+                    id,
+                    content: new_answer.content,
+                    corresponding_question: question_id,
+                };
+                let kind = ChangeKind::AnswerCreated;
+                Self::write_outbox(&mut transaction, kind, question_id, &tags, answer.clone()).await?;
+                transaction.commit().await?;
+                self.outbox.wake();
+                Ok(answer)
+            }
+            Err(e) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", e);
+                Err(e)
+            }
+        }
+    }
+
+    // Health
+
+    /// Check that the database can be reached by running a trivial query
+    #[tracing::instrument(
+        name = "store.ping",
+        skip_all,
+        fields(
+            db.system = "postgresql",
+            db.operation = "SELECT",
+            db.rows = tracing::field::Empty,
+        )
+    )]
+    pub async fn ping(&self) -> Result<(), sqlx::Error> {
+        let _timer = self.metrics.time_store("ping");
+
+        // Write and execute the query
+        match sqlx::query("SELECT 1;").execute(&self.connection).await {
+            Ok(res) => {
+                tracing::Span::current().record("db.rows", res.rows_affected());
+                Ok(())
+            }
+            Err(e) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", e);
+                Err(e)
+            }
+        }
+    }
+
+    /// Get the version of the latest migration that was successfully applied to the database
+    #[tracing::instrument(
+        name = "store.get_migration_version",
+        skip_all,
+        fields(
+            db.system = "postgresql",
+            db.operation = "SELECT",
+            db.sql.table = "_sqlx_migrations",
+            db.rows = tracing::field::Empty,
+        )
+    )]
+    pub async fn get_migration_version(&self) -> Result<Option<i64>, sqlx::Error> {
+        let _timer = self.metrics.time_store("get_migration_version");
+
+        // Write and execute the query
+        match sqlx::query("SELECT MAX(version) AS version FROM _sqlx_migrations WHERE success;")
+            .map(|row: PgRow| row.get("version"))
+            .fetch_one(&self.connection)
+            .await
+        // Match the results from the query and return the version if ok
+        {
+            Ok(version) => {
+                tracing::Span::current().record("db.rows", 1);
+                Ok(version)
+            }
+            Err(e) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", e);
+                Err(e)
+            }
+        }
+    }
+
+    /// Count the questions and answers in the database
+    #[tracing::instrument(
+        name = "store.get_totals",
+        skip_all,
+        fields(
+            db.system = "postgresql",
+            db.operation = "SELECT",
+            db.sql.table = "questions, answers",
+            db.rows = tracing::field::Empty,
+        )
+    )]
+    pub async fn get_totals(&self) -> Result<(i64, i64), sqlx::Error> {
+        let _timer = self.metrics.time_store("get_totals");
+
+        // Write and execute the query
+        match sqlx::query(
+            "SELECT (SELECT COUNT(*) FROM questions) AS questions,
+                (SELECT COUNT(*) FROM answers) AS answers;",
+        )
+        .map(|row: PgRow| (row.get("questions"), row.get("answers")))
+        .fetch_one(&self.connection)
+        .await
+        // Match the results from the query and return the totals if ok
+        {
+            Ok(totals) => {
+                tracing::Span::current().record("db.rows", 1);
+                Ok(totals)
+            }
+            Err(e) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", e);
+                Err(e)
+            }
+        }
+    }
+
+    // Webhooks
+
+    /// Add a webhook to the database, returning it with its id
+    #[tracing::instrument(
+        name = "store.add_webhook",
+        skip_all,
+        fields(
+            db.system = "postgresql",
+            db.operation = "INSERT",
+            db.sql.table = "webhooks",
+            db.rows = tracing::field::Empty,
+        )
+    )]
+    pub async fn add_webhook(
+        &mut self,
+        new_webhook: NewWebhook,
+        secret: String,
+    ) -> Result<Webhook, sqlx::Error> {
+        let _timer = self.metrics.time_store("add_webhook");
+
+        // Create a transaction so that the operation will be atomic since we are modifying the db
+        let mut transaction = self.connection.begin().await?;
+
+        // Write and execute the query
+        match sqlx::query(
+            "INSERT INTO webhooks (url, secret, event_types, active)
+                VALUES ($1, $2, $3, $4)
+                RETURNING id, url, event_types, active, created_on;",
+        )
+        .bind(new_webhook.url)
+        .bind(&secret)
+        .bind(new_webhook.event_types)
+        .bind(new_webhook.active.unwrap_or(true))
+        .map(|row: PgRow| Webhook {
+            secret: Some(secret.clone()),
+            ..Webhook::from_row(&row)
+        })
+        .fetch_one(&mut *transaction)
+        .await
+        // Match the results from the query and commit the query if ok
+        {
+            Ok(webhook) => {
+                tracing::Span::current().record("db.rows", 1);
+                transaction.commit().await?;
+                Ok(webhook)
+            }
+            Err(e) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", e);
+                Err(e)
+            }
+        }
+    }
+
+    /// Get every webhook from the database
+    #[tracing::instrument(
+        name = "store.get_webhooks",
+        skip_all,
+        fields(
+            db.system = "postgresql",
+            db.operation = "SELECT",
+            db.sql.table = "webhooks",
+            db.rows = tracing::field::Empty,
+        )
+    )]
+    pub async fn get_webhooks(&self) -> Result<Vec<Webhook>, sqlx::Error> {
+        let _timer = self.metrics.time_store("get_webhooks");
+
+        // Write and execute the query
+        match sqlx::query(
+            "SELECT id, url, event_types, active, created_on FROM webhooks ORDER BY id;",
+        )
+        .map(|row: PgRow| Webhook::from_row(&row))
+        .fetch_all(&self.connection)
+        .await
+        // Match the results from the query and return the webhooks if ok
+        {
+            Ok(webhooks) => {
+                tracing::Span::current().record("db.rows", webhooks.len());
+                Ok(webhooks)
+            }
+            Err(e) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", e);
+                Err(e)
+            }
+        }
+    }
+
+    /// Get a webhook from the database given a specified id
+    #[tracing::instrument(
+        name = "store.get_webhook",
+        skip_all,
+        fields(
+            db.system = "postgresql",
+            db.operation = "SELECT",
+            db.sql.table = "webhooks",
+            db.rows = tracing::field::Empty,
+        )
+    )]
+    pub async fn get_webhook(&self, id: &i32) -> Result<Webhook, sqlx::Error> {
+        let _timer = self.metrics.time_store("get_webhook");
+
+        // Write and execute the query
+        match sqlx::query(
+            "SELECT id, url, event_types, active, created_on FROM webhooks WHERE id = $1;",
+        )
+        .bind(id)
+        .map(|row: PgRow| Webhook::from_row(&row))
+        .fetch_one(&self.connection)
+        .await
+        // Match the results from the query and return the webhook if ok
+        {
+            Ok(webhook) => {
+                tracing::Span::current().record("db.rows", 1);
+                Ok(webhook)
+            }
+            Err(e) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", e);
+                Err(e)
+            }
+        }
+    }
+
+    /// Update a webhook in the database given a specified id, the secret is kept
+    #[tracing::instrument(
+        name = "store.update_webhook",
+        skip_all,
+        fields(
+            db.system = "postgresql",
+            db.operation = "UPDATE",
+            db.sql.table = "webhooks",
+            db.rows = tracing::field::Empty,
+        )
+    )]
+    pub async fn update_webhook(
+        &mut self,
+        id: &i32,
+        new_webhook: NewWebhook,
+    ) -> Result<Webhook, sqlx::Error> {
+        let _timer = self.metrics.time_store("update_webhook");
+
+        // Create a transaction so that the operation will be atomic since we are modifying the db
+        let mut transaction = self.connection.begin().await?;
+
+        // Write and execute the query
+        match sqlx::query(
+            "UPDATE webhooks
+                SET url = $1, event_types = $2, active = $3
+                WHERE id = $4
+                RETURNING id, url, event_types, active, created_on;",
+        )
+        .bind(new_webhook.url)
+        .bind(new_webhook.event_types)
+        .bind(new_webhook.active.unwrap_or(true))
+        .bind(id)
+        .map(|row: PgRow| Webhook::from_row(&row))
+        .fetch_one(&mut *transaction)
+        .await
+        // Match the results from the query and commit the query if ok
+        {
+            Ok(webhook) => {
+                tracing::Span::current().record("db.rows", 1);
+                transaction.commit().await?;
+                Ok(webhook)
+            }
+            Err(e) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", e);
+                Err(e)
+            }
+        }
+    }
+
+    /// Delete a webhook and its deliveries from the database given a specified id
+    #[tracing::instrument(
+        name = "store.delete_webhook",
+        skip_all,
+        fields(
+            db.system = "postgresql",
+            db.operation = "DELETE",
+            db.sql.table = "webhooks",
+            db.rows = tracing::field::Empty,
+        )
+    )]
+    pub async fn delete_webhook(&mut self, id: &i32) -> Result<u64, sqlx::Error> {
+        let _timer = self.metrics.time_store("delete_webhook");
+
+        // Create a transaction so that the operation will be atomic since we are modifying the db
+        let mut transaction = self.connection.begin().await?;
+
+        // Write and execute the query
+        match sqlx::query("DELETE FROM webhooks WHERE id = $1;")
+            .bind(id)
+            .execute(&mut *transaction)
+            .await
+        // Match the results from the query and commit the query if ok
+        {
+            Ok(res) => {
+                tracing::Span::current().record("db.rows", res.rows_affected());
+                transaction.commit().await?;
+                Ok(res.rows_affected())
+            }
+            Err(e) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", e);
+                Err(e)
+            }
+        }
+    }
+
+    /// Get the deliveries from the database, newest first, either for one webhook or only the
+    /// dead letters
+    #[tracing::instrument(
+        name = "store.get_webhook_deliveries",
+        skip_all,
+        fields(
+            db.system = "postgresql",
+            db.operation = "SELECT",
+            db.sql.table = "webhook_deliveries",
+            db.rows = tracing::field::Empty,
+        )
+    )]
+    pub async fn get_webhook_deliveries(
+        &self,
+        webhook_id: Option<i32>,
+        status: Option<DeliveryStatus>,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<WebhookDelivery>, sqlx::Error> {
+        let _timer = self.metrics.time_store("get_webhook_deliveries");
+
+        // Write and execute the query
+        match sqlx::query(
+            "SELECT * FROM webhook_deliveries
+                WHERE ($1::INTEGER IS NULL OR webhook_id = $1)
+                    AND ($2::TEXT IS NULL OR status = $2)
+                ORDER BY id DESC
+                LIMIT $3 OFFSET $4;",
+        )
+        .bind(webhook_id)
+        .bind(status.map(|s| s.as_str()))
+        .bind(limit)
+        .bind(offset)
+        .map(|row: PgRow| WebhookDelivery::from_row(&row))
+        .fetch_all(&self.connection)
+        .await
+        // Match the results from the query and return the deliveries if ok
+        {
+            Ok(deliveries) => {
+                tracing::Span::current().record("db.rows", deliveries.len());
+                Ok(deliveries)
+            }
+            Err(e) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", e);
+                Err(e)
+            }
+        }
+    }
+
+    /// Move a dead letter back to pending so that it is retried straight away
+    #[tracing::instrument(
+        name = "store.retry_webhook_delivery",
+        skip_all,
+        fields(
+            db.system = "postgresql",
+            db.operation = "UPDATE",
+            db.sql.table = "webhook_deliveries",
+            db.rows = tracing::field::Empty,
+        )
+    )]
+    pub async fn retry_webhook_delivery(
+        &mut self,
+        id: &i64,
+    ) -> Result<WebhookDelivery, sqlx::Error> {
+        let _timer = self.metrics.time_store("retry_webhook_delivery");
+
+        // Create a transaction so that the operation will be atomic since we are modifying the db
+        let mut transaction = self.connection.begin().await?;
+
+        // Write and execute the query
+        match sqlx::query(
+            "UPDATE webhook_deliveries
+                SET status = 'pending', attempts = 0, next_attempt_on = NOW()
+                WHERE id = $1 AND status = 'dead'
+                RETURNING *;",
+        )
+        .bind(id)
+        .map(|row: PgRow| WebhookDelivery::from_row(&row))
+        .fetch_one(&mut *transaction)
+        .await
+        // Match the results from the query and commit the query if ok
+        {
+            Ok(delivery) => {
+                tracing::Span::current().record("db.rows", 1);
+                transaction.commit().await?;
+                Ok(delivery)
+            }
+            Err(e) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", e);
+                Err(e)
+            }
+        }
+    }
+
+    /// Claim the pending deliveries that are due, counting the attempt and pushing the next one
+    /// back by `lease` so that no other worker picks them up while they are being sent
+    #[tracing::instrument(
+        name = "store.claim_webhook_deliveries",
+        level = "debug",
+        skip_all,
+        fields(
+            db.system = "postgresql",
+            db.operation = "UPDATE",
+            db.sql.table = "webhook_deliveries",
+            db.rows = tracing::field::Empty,
+        )
+    )]
+    pub async fn claim_webhook_deliveries(
+        &mut self,
+        limit: i64,
+        lease: Duration,
+    ) -> Result<Vec<PendingDelivery>, sqlx::Error> {
+        let _timer = self.metrics.time_store("claim_webhook_deliveries");
+
+        // Write and execute the query
+        match sqlx::query(
+            "UPDATE webhook_deliveries AS deliveries
+                SET attempts = deliveries.attempts + 1,
+                    next_attempt_on = NOW() + make_interval(secs => $2)
+                FROM webhooks
+                WHERE webhooks.id = deliveries.webhook_id
+                    AND deliveries.id IN (
+                        SELECT id FROM webhook_deliveries
+                        WHERE status = 'pending' AND next_attempt_on <= NOW()
+                        ORDER BY next_attempt_on
+                        LIMIT $1
+                        FOR UPDATE SKIP LOCKED
+                    )
+                RETURNING deliveries.id, deliveries.event_type, deliveries.payload,
+                    deliveries.attempts, webhooks.url, webhooks.secret;",
+        )
+        .bind(limit)
+        .bind(lease.as_secs_f64())
+        .map(|row: PgRow| PendingDelivery {
+            id: row.get("id"),
+            event_type: row.get("event_type"),
+            payload: row.get::<sqlx::types::Json<serde_json::Value>, _>("payload").0,
+            attempts: row.get("attempts"),
+            url: row.get("url"),
+            secret: row.get("secret"),
+        })
+        .fetch_all(&self.connection)
+        .await
+        // Match the results from the query and return the deliveries if ok
+        {
+            Ok(deliveries) => {
+                tracing::Span::current().record("db.rows", deliveries.len());
+                Ok(deliveries)
+            }
+            Err(e) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", e);
+                Err(e)
+            }
+        }
+    }
+
+    /// Record the outcome of a delivery attempt
+    /// A failed attempt is retried after `retry_in`, or becomes a dead letter if there is none
+    #[tracing::instrument(
+        name = "store.record_webhook_delivery",
+        skip_all,
+        fields(
+            db.system = "postgresql",
+            db.operation = "UPDATE",
+            db.sql.table = "webhook_deliveries",
+            db.rows = tracing::field::Empty,
+        )
+    )]
+    pub async fn record_webhook_delivery(
+        &mut self,
+        id: &i64,
+        outcome: &DeliveryOutcome,
+        retry_in: Option<Duration>,
+    ) -> Result<(), sqlx::Error> {
+        let _timer = self.metrics.time_store("record_webhook_delivery");
+
+        let status = match (outcome.delivered, retry_in) {
+            (true, _) => DeliveryStatus::Delivered,
+            (false, Some(_)) => DeliveryStatus::Pending,
+            (false, None) => DeliveryStatus::Dead,
+        };
+
+        // Write and execute the query
+        match sqlx::query(
+            "UPDATE webhook_deliveries
+                SET status = $2,
+                    last_status_code = $3,
+                    last_error = $4,
+                    next_attempt_on = NOW() + make_interval(secs => $5),
+                    delivered_on = CASE WHEN $2 = 'delivered' THEN NOW() END
+                WHERE id = $1;",
+        )
+        .bind(id)
+        .bind(status.as_str())
+        .bind(outcome.status_code)
+        .bind(&outcome.error)
+        .bind(retry_in.unwrap_or_default().as_secs_f64())
+        .execute(&self.connection)
+        .await
+        // Match the results from the query and return if ok
+        {
+            Ok(res) => {
+                tracing::Span::current().record("db.rows", res.rows_affected());
+                Ok(())
+            }
+            Err(e) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", e);
+                Err(e)
+            }
+        }
+    }
+
+    // Outbox
+
+    /// Write a change to the outbox as part of the transaction that made it, the relay publishes
+    /// it once the transaction has committed
+    async fn write_outbox(
+        connection: &mut PgConnection,
+        kind: ChangeKind,
+        question_id: i32,
+        tags: &Option<Vec<String>>,
+        data: impl Serialize + Send + 'static,
+    ) -> Result<(), sqlx::Error> {
+        // Write and execute the query
+        sqlx::query(
+            "INSERT INTO outbox (event_type, question_id, tags, payload)
+                VALUES ($1, $2, $3, $4);",
+        )
+        .bind(kind.as_str())
+        .bind(question_id)
+        .bind(tags)
+        .bind(sqlx::types::Json(data))
+        .execute(connection)
+        .await?;
+        Ok(())
+    }
+
+    /// Claim a batch of unpublished outbox rows, queue their webhook deliveries and mark them as
+    /// published in one transaction, returning the events to publish to the event hub
+    #[tracing::instrument(
+        name = "store.relay_outbox",
+        level = "debug",
+        skip_all,
+        fields(
+            db.system = "postgresql",
+            db.operation = "UPDATE",
+            db.sql.table = "outbox, webhook_deliveries",
+            db.rows = tracing::field::Empty,
+        )
+    )]
+    pub async fn relay_outbox(&mut self, limit: i64) -> Result<Vec<ChangeEvent>, sqlx::Error> {
+        let _timer = self.metrics.time_store("relay_outbox");
+
+        // Create a transaction so that the operation will be atomic since we are modifying the db
+        let mut transaction = self.connection.begin().await?;
+
+        // Write and execute the query
+        // Locked rows are being relayed by another process and are skipped
+        let rows = match sqlx::query(
+            "SELECT id, event_type, question_id, tags, payload FROM outbox
+                WHERE published_on IS NULL
+                ORDER BY id
+                LIMIT $1
+                FOR UPDATE SKIP LOCKED;",
+        )
+        .bind(limit)
+        .map(|row: PgRow| {
+            (
+                row.get::<i64, _>("id"),
+                row.get::<String, _>("event_type"),
+                row.get::<i32, _>("question_id"),
+                row.get::<Option<Vec<String>>, _>("tags"),
+                row.get::<sqlx::types::Json<serde_json::Value>, _>("payload")
+                    .0,
+            )
+        })
+        .fetch_all(&mut *transaction)
+        .await
+        {
+            Ok(rows) => rows,
+            Err(e) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", e);
+                return Err(e);
+            }
+        };
+        if rows.is_empty() {
+            return Ok(Vec::new());
+        }
+        tracing::Span::current().record("db.rows", rows.len());
+
+        let ids: Vec<i64> = rows.iter().map(|row| row.0).collect();
+        let mut events = Vec::with_capacity(rows.len());
+        for (id, event_type, question_id, tags, data) in rows {
+            // Only this version writes to the outbox, so every type should be known
+            let Some(kind) = ChangeKind::parse(&event_type) else {
+                tracing::warn!("Skipping outbox row {} of unknown type {}", id, event_type);
+                continue;
+            };
+            let event = ChangeEvent {
+                id: id as u64,
+                kind,
+                question_id,
+                tags,
+                data,
+            };
+
+            // Queue a delivery for every active webhook that subscribes to the type
+            sqlx::query(
+                "INSERT INTO webhook_deliveries (webhook_id, event_id, event_type, payload)
+                    SELECT id, $1, $2, $3 FROM webhooks
+                    WHERE active AND (event_types IS NULL OR $2 = ANY(event_types));",
+            )
+            .bind(id)
+            .bind(kind.as_str())
+            .bind(sqlx::types::Json(&event))
+            .execute(&mut *transaction)
+            .await?;
+            events.push(event);
+        }
+
+        // Mark the batch and commit the query
+        sqlx::query("UPDATE outbox SET published_on = NOW() WHERE id = ANY($1);")
+            .bind(&ids)
+            .execute(&mut *transaction)
+            .await?;
+        transaction.commit().await?;
+        Ok(events)
+    }
+
+    /// Delete the published outbox rows that are older than `retention`
+    #[tracing::instrument(
+        name = "store.delete_published_outbox",
+        level = "debug",
+        skip_all,
+        fields(
+            db.system = "postgresql",
+            db.operation = "DELETE",
+            db.sql.table = "outbox",
+            db.rows = tracing::field::Empty,
+        )
+    )]
+    pub async fn delete_published_outbox(
+        &mut self,
+        retention: Duration,
+    ) -> Result<u64, sqlx::Error> {
+        let _timer = self.metrics.time_store("delete_published_outbox");
+
+        // Write and execute the query
+        match sqlx::query(
+            "DELETE FROM outbox
+                WHERE published_on < NOW() - make_interval(secs => $1);",
+        )
+        .bind(retention.as_secs_f64())
+        .execute(&self.connection)
+        .await
+        // Match the results from the query and return the number of rows if ok
+        {
+            Ok(res) => {
+                tracing::Span::current().record("db.rows", res.rows_affected());
+                Ok(res.rows_affected())
+            }
+            Err(e) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", e);
+                Err(e)
+            }
+        }
+    }
+
+    // Users
+
+    /// Add a user to the database, returning the id of the new user
+    #[tracing::instrument(
+        name = "store.add_user",
+        skip_all,
+        fields(
+            db.system = "postgresql",
+            db.operation = "INSERT",
+            db.sql.table = "users",
+            db.rows = tracing::field::Empty,
+        )
+    )]
+    pub async fn add_user(&mut self, new_user: NewUser) -> Result<i32, sqlx::Error> {
+        let _timer = self.metrics.time_store("add_user");
+
+        // Create a transaction so that the operation will be atomic since we are modifying the db
+        let mut transaction = self.connection.begin().await?;
+
+        // Write and execute the query
+        match sqlx::query(
+            "INSERT INTO users (username, password_hash, is_admin) VALUES ($1, $2, $3) RETURNING id;",
+        )
+        .bind(new_user.username)
+        .bind(new_user.password_hash)
+        .bind(new_user.is_admin)
+        .map(|row: PgRow| row.get("id"))
+        .fetch_one(&mut *transaction)
+        .await
+        // Match the results from the query and commit the query if ok
+        {
+            Ok(id) => {
+                tracing::Span::current().record("db.rows", 1);
+                transaction.commit().await?;
+                Ok(id)
+            }
+            Err(e) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", e);
+                Err(e)
+            }
+        }
+    }
+
+    /// Get the id, password hash and admin flag of a user given their username
+    #[tracing::instrument(
+        name = "store.get_user_credentials",
+        skip_all,
+        fields(
+            db.system = "postgresql",
+            db.operation = "SELECT",
+            db.sql.table = "users",
+            db.rows = tracing::field::Empty,
+        )
+    )]
+    pub async fn get_user_credentials(
+        &self,
+        username: &str,
+    ) -> Result<Option<(i32, String, bool)>, sqlx::Error> {
+        let _timer = self.metrics.time_store("get_user_credentials");
+
+        // Write and execute the query
+        match sqlx::query("SELECT id, password_hash, is_admin FROM users WHERE username = $1;")
+            .bind(username)
+            .map(|row: PgRow| (row.get("id"), row.get("password_hash"), row.get("is_admin")))
+            .fetch_optional(&self.connection)
+            .await
+        // Match the results from the query and return the user if ok
+        {
+            Ok(user) => {
+                tracing::Span::current().record("db.rows", user.iter().count());
+                Ok(user)
+            }
+            Err(e) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", e);
+                Err(e)
+            }
+        }
+    }
+
+    // Study
+
+    /// Get the next question a user should study: the review that has been due the longest, or a
+    /// question they have never studied if nothing is due
+    #[tracing::instrument(
+        name = "store.get_next_study_card",
+        skip_all,
+        fields(
+            db.system = "postgresql",
+            db.operation = "SELECT",
+            db.sql.table = "study_reviews, questions",
+            db.rows = tracing::field::Empty,
+        )
+    )]
+    pub async fn get_next_study_card(
+        &self,
+        user_id: i32,
+        tag: Option<&str>,
+    ) -> Result<Option<StudyCard>, sqlx::Error> {
+        let _timer = self.metrics.time_store("get_next_study_card");
+
+        // Write and execute the query
+        match sqlx::query(
+            "SELECT card.*,
+                (SELECT COUNT(*) FROM answers WHERE corresponding_question = card.id) AS answer_count
+            FROM (
+                (SELECT q.id, q.title, q.content, q.tags,
+                        r.ease, r.interval_days, r.repetitions, r.due_on, r.last_reviewed_on
+                    FROM study_reviews r
+                    JOIN questions q ON q.id = r.question_id
+                    WHERE r.user_id = $1 AND r.due_on <= NOW()
+                        AND ($2::text IS NULL OR EXISTS (
+                            SELECT 1 FROM unnest(q.tags) AS tag WHERE lower(tag) = lower($2)
+                        ))
+                    ORDER BY r.due_on
+                    LIMIT 1)
+                UNION ALL
+                (SELECT q.id, q.title, q.content, q.tags,
+                        NULL, NULL, NULL, NULL, NULL
+                    FROM questions q
+                    WHERE NOT EXISTS (
+                            SELECT 1 FROM study_reviews r
+                            WHERE r.user_id = $1 AND r.question_id = q.id
+                        )
+                        AND ($2::text IS NULL OR EXISTS (
+                            SELECT 1 FROM unnest(q.tags) AS tag WHERE lower(tag) = lower($2)
+                        ))
+                    ORDER BY q.id
+                    LIMIT 1)
+                LIMIT 1
+            ) card;",
+        )
+        .bind(user_id)
+        .bind(tag)
+        .map(|row: PgRow| StudyCard {
+            question: Question {
+                id: row.get("id"),
+                title: row.get("title"),
+                content: row.get("content"),
+                tags: row.get("tags"),
+            }
+            .into(),
+            answer_count: row.get("answer_count"),
+            review: row
+                .get::<Option<NaiveDateTime>, _>("due_on")
+                .map(|due_on| Review {
+                    question_id: row.get("id"),
+                    ease: row.get("ease"),
+                    interval_days: row.get("interval_days"),
+                    repetitions: row.get("repetitions"),
+                    due_on,
+                    last_reviewed_on: row.get("last_reviewed_on"),
+                }),
+        })
+        .fetch_optional(&self.connection)
+        .await
+        // Match the results from the query and return the card if ok
+        {
+            Ok(card) => {
+                tracing::Span::current().record("db.rows", card.iter().count());
+                Ok(card)
+            }
+            Err(e) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", e);
+                Err(e)
+            }
+        }
+    }
+
+    /// Record how well a user recalled a question and schedule its next review
+    #[tracing::instrument(
+        name = "store.grade_study_card",
+        skip_all,
+        fields(
+            db.system = "postgresql",
+            db.operation = "INSERT",
+            db.sql.table = "study_reviews",
+            db.rows = tracing::field::Empty,
+        )
+    )]
+    pub async fn grade_study_card(
+        &mut self,
+        user_id: i32,
+        question_id: &i32,
+        quality: u8,
+    ) -> Result<Review, sqlx::Error> {
+        let _timer = self.metrics.time_store("grade_study_card");
+
+        // Create a transaction so that the operation will be atomic since we are modifying the db
+        let mut transaction = self.connection.begin().await?;
+
+        // Check that the question exists, then read the current schedule, locking it so that a
+        // parallel grade waits for this one
+        sqlx::query("SELECT id FROM questions WHERE id = $1;")
+            .bind(question_id)
+            .fetch_one(&mut *transaction)
+            .await?;
+        let schedule = sqlx::query(
+            "SELECT ease, interval_days, repetitions FROM study_reviews
+                WHERE user_id = $1 AND question_id = $2
+                FOR UPDATE;",
+        )
+        .bind(user_id)
+        .bind(question_id)
+        .map(|row: PgRow| Schedule {
+            ease: row.get("ease"),
+            interval_days: row.get("interval_days"),
+            repetitions: row.get("repetitions"),
+        })
+        .fetch_optional(&mut *transaction)
+        .await?
+        .unwrap_or_default()
+        .graded(quality);
+
+        // Write and execute the query
+        match sqlx::query(
+            "INSERT INTO study_reviews
+                (user_id, question_id, ease, interval_days, repetitions, due_on, last_reviewed_on)
+                VALUES ($1, $2, $3, $4, $5, NOW() + make_interval(days => $4), NOW())
+                ON CONFLICT (user_id, question_id) DO UPDATE SET
+                    ease = EXCLUDED.ease,
+                    interval_days = EXCLUDED.interval_days,
+                    repetitions = EXCLUDED.repetitions,
+                    due_on = EXCLUDED.due_on,
+                    last_reviewed_on = EXCLUDED.last_reviewed_on
+                RETURNING *;",
+        )
+        .bind(user_id)
+        .bind(question_id)
+        .bind(schedule.ease)
+        .bind(schedule.interval_days)
+        .bind(schedule.repetitions)
+        .map(|row: PgRow| Review {
+            question_id: row.get("question_id"),
+            ease: row.get("ease"),
+            interval_days: row.get("interval_days"),
+            repetitions: row.get("repetitions"),
+            due_on: row.get("due_on"),
+            last_reviewed_on: row.get("last_reviewed_on"),
+        })
+        .fetch_one(&mut *transaction)
+        .await
+        // Match the results from the query and commit the query if ok
+        {
+            Ok(review) => {
+                tracing::Span::current().record("db.rows", 1);
+                transaction.commit().await?;
+                Ok(review)
+            }
+            Err(e) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", e);
+                Err(e)
+            }
+        }
+    }
+
+    // Quizzes
+
+    /// Draw the questions of a new quiz one at a time with the random question picker, leaving
+    /// out the ones already drawn
+    /// Only questions with answers are drawn, and fewer than asked for if not enough pass the
+    /// filter, this only reads so that it can run without holding the write lock
+    pub async fn draw_quiz_questions(
+        &self,
+        new_quiz: &NewQuiz,
+    ) -> Result<Vec<Question>, sqlx::Error> {
+        let mut filter = RandomFilter {
+            tag: new_quiz.tag.clone().filter(|tag| !tag.is_empty()),
+            answered: Some(true),
+            exclude: Vec::new(),
+        };
+        let mut questions = Vec::new();
+        while (questions.len() as i64) < new_quiz.questions {
+            match self.get_random_question(&filter, None).await {
+                Ok(question) => {
+                    filter.exclude.push(question.id);
+                    questions.push(question);
+                }
+                Err(sqlx::Error::RowNotFound) => break,
+                Err(e) => return Err(e),
+            }
+        }
+        if questions.is_empty() {
+            return Err(sqlx::Error::RowNotFound);
+        }
+        Ok(questions)
+    }
+
+    /// Save a quiz with the questions drawn for it, quizzes that expired longer ago than the
+    /// retention are deleted at the same time
+    #[tracing::instrument(
+        name = "store.add_quiz",
+        skip_all,
+        fields(
+            db.system = "postgresql",
+            db.operation = "INSERT",
+            db.sql.table = "quizzes, quiz_questions",
+            db.rows = tracing::field::Empty,
+        )
+    )]
+    pub async fn add_quiz(
+        &mut self,
+        new_quiz: &NewQuiz,
+        token: &str,
+        questions: Vec<Question>,
+    ) -> Result<Quiz, sqlx::Error> {
+        let _timer = self.metrics.time_store("add_quiz");
+        let tag = new_quiz.tag.clone().filter(|tag| !tag.is_empty());
+
+        // Create a transaction so that the operation will be atomic since we are modifying the db
+        let mut transaction = self.connection.begin().await?;
+
+        sqlx::query("DELETE FROM quizzes WHERE expires_on < NOW() - make_interval(secs => $1);")
+            .bind(quiz_retention().as_secs_f64())
+            .execute(&mut *transaction)
+            .await?;
+
+        // Write and execute the queries
+        let res = match sqlx::query(
+            "INSERT INTO quizzes (token, tag, threshold, expires_on)
+                VALUES ($1, $2, $3, NOW() + make_interval(secs => $4))
+                RETURNING created_on, expires_on;",
+        )
+        .bind(token)
+        .bind(&tag)
+        .bind(new_quiz.threshold)
+        .bind(new_quiz.time_limit_seconds as f64)
+        .map(|row: PgRow| {
+            (
+                row.get::<NaiveDateTime, _>("created_on"),
+                row.get::<NaiveDateTime, _>("expires_on"),
+            )
+        })
+        .fetch_one(&mut *transaction)
+        .await
+        {
+            Ok(dates) => sqlx::query(
+                "INSERT INTO quiz_questions (token, position, question_id)
+                    SELECT $1, drawn.position::int, drawn.id
+                    FROM unnest($2::int[]) WITH ORDINALITY AS drawn(id, position);",
+            )
+            .bind(token)
+            .bind(questions.iter().map(|q| q.id).collect::<Vec<_>>())
+            .execute(&mut *transaction)
+            .await
+            .map(|_| dates),
+            Err(e) => Err(e),
+        };
+
+        // Match the results from the queries and commit the query if ok
+        match res {
+            Ok((created_on, expires_on)) => {
+                tracing::Span::current().record("db.rows", 1 + questions.len());
+                transaction.commit().await?;
+                Ok(Quiz {
+                    token: token.to_owned(),
+                    tag,
+                    threshold: new_quiz.threshold,
+                    created_on,
+                    expires_on,
+                    questions: questions.into_iter().map(Into::into).collect(),
+                })
+            }
+            Err(e) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", e);
+                Err(e)
+            }
+        }
+    }
+
+    /// Score an answer to a question of a quiz against the answers of the question and save it
+    /// Returns `RowNotFound` if there is no such quiz or the question is not in it
+    #[tracing::instrument(
+        name = "store.submit_quiz_answer",
+        skip_all,
+        fields(
+            db.system = "postgresql",
+            db.operation = "UPDATE",
+            db.sql.table = "quiz_questions",
+            db.rows = tracing::field::Empty,
+        )
+    )]
+    pub async fn submit_quiz_answer(
+        &mut self,
+        token: &str,
+        question_id: &i32,
+        answer: &str,
+    ) -> Result<QuizSubmission, sqlx::Error> {
+        let _timer = self.metrics.time_store("submit_quiz_answer");
+
+        // Create a transaction so that the operation will be atomic since we are modifying the db
+        let mut transaction = self.connection.begin().await?;
+
+        // Lock the question of the quiz so that a parallel answer to it waits for this one
+        let (answered, expired, threshold): (bool, bool, f64) = sqlx::query(
+            "SELECT qq.answer IS NOT NULL AS answered, z.expires_on <= NOW() AS expired,
+                    z.threshold
+                FROM quiz_questions qq
+                JOIN quizzes z ON z.token = qq.token
+                WHERE qq.token = $1 AND qq.question_id = $2
+                FOR UPDATE OF qq;",
+        )
+        .bind(token)
+        .bind(question_id)
+        .map(|row: PgRow| {
+            (
+                row.get("answered"),
+                row.get("expired"),
+                row.get("threshold"),
+            )
+        })
+        .fetch_one(&mut *transaction)
+        .await?;
+        if answered {
+            return Ok(QuizSubmission::AlreadyAnswered);
+        }
+        if expired {
+            return Ok(QuizSubmission::Expired);
+        }
+
+        // Score the answer against every answer of the question
+        let accepted: Vec<String> =
+            sqlx::query("SELECT content FROM answers WHERE corresponding_question = $1;")
+                .bind(question_id)
+                .map(|row: PgRow| row.get("content"))
+                .fetch_all(&mut *transaction)
+                .await?;
+        let similarity = score_answer(answer, &accepted);
+
+        // Write and execute the query
+        match sqlx::query(
+            "UPDATE quiz_questions
+                SET answer = $3, similarity = $4, correct = $5, answered_on = NOW()
+                WHERE token = $1 AND question_id = $2
+                RETURNING answered_on;",
+        )
+        .bind(token)
+        .bind(question_id)
+        .bind(answer)
+        .bind(similarity)
+        .bind(similarity >= threshold)
+        .map(|row: PgRow| QuizAnswer {
+            question_id: *question_id,
+            answer: answer.to_owned(),
+            similarity,
+            correct: similarity >= threshold,
+            answered_on: row.get("answered_on"),
+        })
+        .fetch_one(&mut *transaction)
+        .await
+        // Match the results from the query and commit the query if ok
+        {
+            Ok(answer) => {
+                tracing::Span::current().record("db.rows", 1);
+                transaction.commit().await?;
+                Ok(QuizSubmission::Scored(answer))
+            }
+            Err(e) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", e);
+                Err(e)
+            }
+        }
+    }
+
+    /// Get a quiz with every question in the order they are asked and the answers so far
+    #[tracing::instrument(
+        name = "store.get_quiz_results",
+        skip_all,
+        fields(
+            db.system = "postgresql",
+            db.operation = "SELECT",
+            db.sql.table = "quizzes, quiz_questions",
+            db.rows = tracing::field::Empty,
+        )
+    )]
+    pub async fn get_quiz_results(&self, token: &str) -> Result<QuizResults, sqlx::Error> {
+        let _timer = self.metrics.time_store("get_quiz_results");
+
+        // Write and execute the query
+        match sqlx::query(
+            "SELECT z.tag, z.threshold, z.created_on, z.expires_on,
+                z.expires_on <= NOW() AS expired,
+                COALESCE((
+                    SELECT json_agg(json_build_object(
+                        'position', qq.position,
+                        'question_id', qq.question_id,
+                        'title', q.title,
+                        'answer', qq.answer,
+                        'similarity', qq.similarity,
+                        'correct', COALESCE(qq.correct, FALSE),
+                        'answered_on', qq.answered_on
+                    ) ORDER BY qq.position)
+                    FROM quiz_questions qq
+                    JOIN questions q ON q.id = qq.question_id
+                    WHERE qq.token = z.token
+                ), '[]') AS questions
+            FROM quizzes z
+            WHERE z.token = $1;",
+        )
+        .bind(token)
+        .map(|row: PgRow| {
+            let questions: Vec<QuizQuestionResult> = row
+                .get::<sqlx::types::Json<Vec<QuizQuestionResult>>, _>("questions")
+                .0;
+            let total = questions.len() as i64;
+            let answered = questions.iter().filter(|q| q.answer.is_some()).count() as i64;
+            let correct = questions.iter().filter(|q| q.correct).count() as i64;
+            QuizResults {
+                token: token.to_owned(),
+                tag: row.get("tag"),
+                threshold: row.get("threshold"),
+                created_on: row.get("created_on"),
+                expires_on: row.get("expires_on"),
+                finished: row.get::<bool, _>("expired") || answered == total,
+                total,
+                answered,
+                correct,
+                score: if total > 0 {
+                    correct as f64 / total as f64
+                } else {
+                    0.0
+                },
+                questions,
+            }
+        })
+        .fetch_one(&self.connection)
+        .await
+        // Match the results from the query and return the results if ok
+        {
+            Ok(results) => {
+                tracing::Span::current().record("db.rows", 1 + results.questions.len());
+                Ok(results)
+            }
+            Err(e) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", e);
+                Err(e)
+            }
+        }
+    }
+
+    // Attachments
+
+    /// Record a file saved in the file store against a question or answer, returning the new
+    /// attachment, the row is only inserted if the question or answer exists
+    #[tracing::instrument(
+        name = "store.add_attachment",
+        skip_all,
+        fields(
+            db.system = "postgresql",
+            db.operation = "INSERT",
+            db.sql.table = "attachments",
+            db.rows = tracing::field::Empty,
+        )
+    )]
+    pub async fn add_attachment(
+        &mut self,
+        parent: AttachmentParent,
+        filename: &str,
+        content_type: &str,
+        size: i64,
+        storage_key: &str,
+    ) -> Result<Attachment, sqlx::Error> {
+        let _timer = self.metrics.time_store("add_attachment");
+
+        // Create a transaction so that the operation will be atomic since we are modifying the db
+        let mut transaction = self.connection.begin().await?;
+
+        // Write and execute the query
+        match sqlx::query(
+            "INSERT INTO attachments (question_id, answer_id, filename, content_type, size, storage_key)
+                SELECT $1, $2, $3, $4, $5, $6
+                WHERE EXISTS(SELECT 1 FROM questions WHERE id = $1)
+                    OR EXISTS(SELECT 1 FROM answers WHERE id = $2)
+                RETURNING *;",
+        )
+        .bind(parent.question_id())
+        .bind(parent.answer_id())
+        .bind(filename)
+        .bind(content_type)
+        .bind(size)
+        .bind(storage_key)
+        .map(Self::attachment_from_row)
+        .fetch_one(&mut *transaction)
+        .await
+        // Match the results from the query and commit the query if ok
+        {
+            Ok(attachment) => {
+                tracing::Span::current().record("db.rows", 1);
+                transaction.commit().await?;
+                Ok(attachment)
+            }
+            Err(e) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", e);
+                Err(e)
+            }
+        }
+    }
+
+    /// Get the attachments of a question or answer, oldest first, failing with `RowNotFound` if
+    /// the question or answer does not exist
+    #[tracing::instrument(
+        name = "store.get_attachments",
+        skip_all,
+        fields(
+            db.system = "postgresql",
+            db.operation = "SELECT",
+            db.sql.table = "attachments",
+            db.rows = tracing::field::Empty,
+        )
+    )]
+    pub async fn get_attachments(
+        &self,
+        parent: AttachmentParent,
+    ) -> Result<Vec<Attachment>, sqlx::Error> {
+        let _timer = self.metrics.time_store("get_attachments");
+
+        // Check that the question or answer exists so that it is not mistaken for one with none
+        let exists: bool = sqlx::query_scalar(
+            "SELECT EXISTS(SELECT 1 FROM questions WHERE id = $1)
+                OR EXISTS(SELECT 1 FROM answers WHERE id = $2);",
+        )
+        .bind(parent.question_id())
+        .bind(parent.answer_id())
+        .fetch_one(&self.connection)
+        .await?;
+        if !exists {
+            return Err(sqlx::Error::RowNotFound);
+        }
+
+        // Write and execute the query
+        match sqlx::query(
+            "SELECT * FROM attachments WHERE question_id = $1 OR answer_id = $2
+                ORDER BY created_on, id;",
+        )
+        .bind(parent.question_id())
+        .bind(parent.answer_id())
+        .map(Self::attachment_from_row)
+        .fetch_all(&self.connection)
+        .await
+        // Match the results from the query and return the attachments if ok
+        {
+            Ok(attachments) => {
+                tracing::Span::current().record("db.rows", attachments.len());
+                Ok(attachments)
+            }
+            Err(e) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", e);
+                Err(e)
+            }
+        }
+    }
+
+    /// Get an attachment from the database given a specified id
+    #[tracing::instrument(
+        name = "store.get_attachment",
+        skip_all,
+        fields(
+            db.system = "postgresql",
+            db.operation = "SELECT",
+            db.sql.table = "attachments",
+            db.rows = tracing::field::Empty,
+        )
+    )]
+    pub async fn get_attachment(&self, id: &i32) -> Result<Attachment, sqlx::Error> {
+        let _timer = self.metrics.time_store("get_attachment");
+
+        // Write and execute the query
+        match sqlx::query("SELECT * FROM attachments WHERE id = $1;")
+            .bind(id)
+            .map(Self::attachment_from_row)
+            .fetch_one(&self.connection)
+            .await
+        // Match the results from the query and return the attachment if ok
+        {
+            Ok(attachment) => {
+                tracing::Span::current().record("db.rows", 1);
+                Ok(attachment)
+            }
+            Err(e) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", e);
+                Err(e)
+            }
+        }
+    }
+
+    /// Build an attachment from a row of the attachments table
+    fn attachment_from_row(row: PgRow) -> Attachment {
+        Attachment {
+            id: row.get("id"),
+            question_id: row.get("question_id"),
+            answer_id: row.get("answer_id"),
+            filename: row.get("filename"),
+            content_type: row.get("content_type"),
+            size: row.get("size"),
+            created_on: row.get("created_on"),
+            storage_key: row.get("storage_key"),
+        }
+    }
+
+    /// Storage keys of the files attached to a question and its answers, or to every question
+    /// if no id is given
+    async fn attachment_keys(
+        transaction: &mut Transaction<'_, Postgres>,
+        question_id: Option<i32>,
+    ) -> Result<Vec<String>, sqlx::Error> {
+        sqlx::query_scalar(
+            "SELECT a.storage_key FROM attachments a
+                LEFT JOIN answers ans ON ans.id = a.answer_id
+                WHERE $1::integer IS NULL
+                    OR a.question_id = $1
+                    OR ans.corresponding_question = $1;",
+        )
+        .bind(question_id)
+        .fetch_all(&mut **transaction)
+        .await
+    }
+
+    /// Delete files from the file store once their attachment rows are gone, a file that cannot
+    /// be deleted is logged and left behind rather than failing a delete that already happened
+    async fn delete_files(&self, keys: Vec<String>) {
+        for key in keys {
+            if let Err(e) = self.files.delete(&key).await {
+                tracing::event!(
+                    tracing::Level::ERROR,
+                    "Could not delete attachment {}: {:?}",
+                    key,
+                    e
+                );
+            }
+        }
+    }
+
+    // Import
+
+    /// Add many questions and their answers to the database in a single transaction
+    /// Each question is inserted under a savepoint so a row the database rejects does not undo the
+    /// others, and a dry run rolls everything back once every row has been checked
+    #[tracing::instrument(
+        name = "store.import_questions",
+        skip_all,
+        fields(
+            db.system = "postgresql",
+            db.operation = "INSERT",
+            db.sql.table = "questions, answers",
+            db.rows = tracing::field::Empty,
+        )
+    )]
+    pub async fn import_questions(
+        &mut self,
+        questions: &[ImportQuestion],
+        dry_run: bool,
+    ) -> Result<Vec<Result<i32, sqlx::Error>>, sqlx::Error> {
+        let _timer = self.metrics.time_store("import_questions");
+
+        // Create a transaction so that the operation will be atomic since we are modifying the db
+        let mut transaction = self.connection.begin().await?;
+
+        let mut results = Vec::with_capacity(questions.len());
+        for question in questions {
+            // Create a savepoint for each question, committing it keeps the row in the transaction
+            let mut savepoint = transaction.begin().await?;
+            match Self::insert_imported_question(&mut savepoint, question).await {
+                Ok(id) => {
+                    savepoint.commit().await?;
+                    results.push(Ok(id));
+                }
+                Err(e) => {
+                    tracing::event!(tracing::Level::WARN, "{:?}", e);
+                    savepoint.rollback().await?;
+                    results.push(Err(e));
+                }
+            }
+        }
+        tracing::Span::current().record("db.rows", results.iter().filter(|r| r.is_ok()).count());
+
+        // Only keep the rows if this is not a dry run
+        if dry_run {
+            transaction.rollback().await?;
+        } else {
+            transaction.commit().await?;
+            self.outbox.wake();
+        }
+        Ok(results)
+    }
+
+    /// Insert one imported question and its answers, returning the id of the new question
+    async fn insert_imported_question(
+        connection: &mut PgConnection,
+        question: &ImportQuestion,
+    ) -> Result<i32, sqlx::Error> {
+        // Write and execute the query for the question
+        let id: i32 = sqlx::query(
+            "INSERT INTO questions (title, content, tags)
+                VALUES ($1, $2, $3)
+                RETURNING id;",
+        )
+        .bind(&question.title)
+        .bind(&question.content)
+        .bind(&question.tags)
+        .map(|row: PgRow| row.get("id"))
+        .fetch_one(&mut *connection)
+        .await?;
+
+        // Record the new question in the outbox under the same savepoint
+        let data = Question {
+            id,
+            title: question.title.clone(),
+            content: question.content.clone(),
+            tags: question.tags.clone(),
+        };
+        Self::write_outbox(
+            &mut *connection,
+            ChangeKind::QuestionCreated,
+            id,
+            &question.tags,
+            data,
+        )
+        .await?;
+
+        // Write and execute the query for each answer, recording each one in the outbox after the
+        // question like `add_answer` does
+        for answer in &question.answers {
+            let answer_id: i32 = sqlx::query(
+                "INSERT INTO answers (content, corresponding_question)
+                    VALUES ($1, $2)
+                    RETURNING id;",
+            )
+            .bind(&answer.content)
+            .bind(id)
+            .map(|row: PgRow| row.get("id"))
+            .fetch_one(&mut *connection)
+            .await?;
+
+            let data = Answer {
+                id: answer_id,
+                content: answer.content.clone(),
+                corresponding_question: id,
+            };
+            Self::write_outbox(
+                &mut *connection,
+                ChangeKind::AnswerCreated,
+                id,
+                &question.tags,
+                data,
+            )
+            .await?;
+        }
+        Ok(id)
+    }
+
+    // Export
+
+    /// Stream every question with its answers, in batches read from a database cursor
+    /// Only questions created, edited or answered after `since` are included if it is given
+    /// The stream owns a clone of the pool, so the data store does not need to stay locked
+    pub fn export_questions(
+        &self,
+        since: Option<NaiveDateTime>,
+    ) -> impl Stream<Item = Result<Vec<ExportQuestion>, sqlx::Error>> + Send + 'static {
+        let store = self.clone();
+        futures_util::stream::try_unfold(None, move |transaction| {
+            let store = store.clone();
+            async move {
+                // Open the cursor in a transaction the first time the stream is polled
+                let mut transaction = match transaction {
+                    Some(transaction) => transaction,
+                    None => store.open_export_cursor(since).await?,
+                };
+
+                // Read the next batch, closing the cursor and the transaction once it runs out
+                let batch = store.fetch_export_batch(&mut transaction).await?;
+                if batch.is_empty() {
+                    transaction.commit().await?;
+                    Ok(None)
+                } else {
+                    Ok(Some((batch, Some(transaction))))
+                }
+            }
+        })
+    }
+
+    /// Declare the cursor used to export questions and their answers
+    async fn open_export_cursor(
+        &self,
+        since: Option<NaiveDateTime>,
+    ) -> Result<Transaction<'static, Postgres>, sqlx::Error> {
+        // Cursors only live as long as the transaction that declared them
+        let mut transaction = self.connection.begin().await?;
+
+        // Write and execute the query
+        match sqlx::query(
+            "DECLARE export_cursor NO SCROLL CURSOR FOR
+                SELECT q.id, q.title, q.content, q.tags, q.created_on,
+                    (SELECT COALESCE(json_agg(json_build_object(
+                        'id', a.id,
+                        'content', a.content,
+                        'created_on', a.created_on
+                    ) ORDER BY a.id), '[]')
+                    FROM answers a
+                    WHERE a.corresponding_question = q.id) AS answers
+                FROM questions q
+                WHERE $1::timestamp IS NULL
+                    OR q.updated_on > $1
+                    OR EXISTS (
+                        SELECT 1 FROM answers a
+                        WHERE a.corresponding_question = q.id AND a.created_on > $1
+                    )
+                ORDER BY q.id;",
+        )
+        .bind(since)
+        .execute(&mut *transaction)
+        .await
+        // Match the results from the query and return the transaction holding the cursor if ok
+        {
+            Ok(_) => Ok(transaction),
+            Err(e) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", e);
+                Err(e)
+            }
+        }
+    }
+
+    /// Read the next batch of questions from the export cursor
+    #[tracing::instrument(
+        name = "store.export_questions",
+        skip_all,
+        fields(
+            db.system = "postgresql",
+            db.operation = "FETCH",
+            db.sql.table = "questions, answers",
+            db.rows = tracing::field::Empty,
+        )
+    )]
+    async fn fetch_export_batch(
+        &self,
+        transaction: &mut Transaction<'static, Postgres>,
+    ) -> Result<Vec<ExportQuestion>, sqlx::Error> {
+        let _timer = self.metrics.time_store("export_questions");
+
+        // Write and execute the query
+        match sqlx::query(&format!("FETCH {} FROM export_cursor;", EXPORT_BATCH_SIZE))
+            .map(|row: PgRow| ExportQuestion {
+                id: row.get("id"),
+                title: row.get("title"),
+                content: row.get("content"),
+                tags: row.get("tags"),
+                created_on: row.get("created_on"),
+                answers: row.get::<sqlx::types::Json<Vec<ExportAnswer>>, _>("answers").0,
+            })
+            .fetch_all(&mut **transaction)
+            .await
+        // Match the results from the query and return the questions if ok
+        {
+            Ok(questions) => {
+                tracing::Span::current().record("db.rows", questions.len());
+                Ok(questions)
+            }
+            Err(e) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", e);
+                Err(e)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Pick from `matching` the way `probe_random_question` does, with ids from `min` to `max`,
+    /// and count how often each one comes up
+    fn picks(matching: &[i32], min: i32, max: i32, trials: i64) -> HashMap<i32, i64> {
+        let mut picks = HashMap::new();
+        for trial in 0..trials {
+            let mut fractions = session_fractions("test", trial);
+            let probes = probe_ids(min, max, &mut fractions);
+            let found: Vec<i32> = probes
+                .iter()
+                .copied()
+                .filter(|id| matching.contains(id))
+                .collect();
+            let id = first_probed(&probes, &found).unwrap_or_else(|| {
+                let position = random_position(fractions.next().unwrap(), matching.len() as i64);
+                matching[position as usize]
+            });
+            *picks.entry(id).or_insert(0) += 1;
+        }
+        picks
+    }
+
+    #[test]
+    fn sparse_matches_are_picked_evenly() {
+        // Two questions pass the filter, with a bigger gap before the second
+        let picks = picks(&[8, 11], 1, 20, 4000);
+        for id in [8, 11] {
+            assert!((1800..=2200).contains(&picks[&id]), "{:?}", picks);
+        }
+    }
+
+    #[test]
+    fn questions_after_a_gap_are_not_favoured() {
+        let picks = picks(&[1, 2, 3, 100], 1, 100, 4000);
+        for id in [1, 2, 3, 100] {
+            assert!((850..=1150).contains(&picks[&id]), "{:?}", picks);
+        }
+    }
+
+    #[test]
+    fn positions_stay_in_range() {
+        assert_eq!(random_position(0.0, 5), 0);
+        assert_eq!(random_position(0.999_999, 5), 4);
+        assert_eq!(random_position(1.0, 5), 4);
+        assert!(probe_ids(i32::MAX - 1, i32::MAX, random_fractions())
+            .iter()
+            .all(|id| *id >= i32::MAX - 1));
+    }
+
+    /// Mark `id` as a duplicate of `other` the way `mark_duplicate_question` does, with the marks
+    /// kept as question id to the id it duplicates
+    fn mark(marks: &mut HashMap<i32, i32>, id: i32, other: i32) {
+        let (target, unmark_other) = duplicate_target(id, other, marks.get(&other).copied());
+        if unmark_other {
+            marks.remove(&other);
+        }
+        for duplicate_of in marks.values_mut() {
+            if *duplicate_of == id {
+                *duplicate_of = target;
+            }
+        }
+        marks.insert(id, target);
+    }
+
+    #[test]
+    fn duplicate_marks_never_chain() {
+        let mut marks = HashMap::new();
+
+        // Marking a duplicate of a duplicate points at the original
+        mark(&mut marks, 2, 1);
+        mark(&mut marks, 3, 2);
+        assert_eq!(marks, HashMap::from([(2, 1), (3, 1)]));
+
+        // Marking the original as a duplicate moves its duplicates over
+        mark(&mut marks, 1, 4);
+        assert_eq!(marks, HashMap::from([(1, 4), (2, 4), (3, 4)]));
+
+        // Marking a question as a duplicate of its own duplicate swaps them
+        mark(&mut marks, 4, 2);
+        assert_eq!(marks, HashMap::from([(1, 2), (3, 2), (4, 2)]));
+        assert!(marks.values().all(|target| !marks.contains_key(target)));
+    }
+
+    #[test]
+    fn sessions_get_the_same_points() {
+        let first: Vec<f64> = session_fractions("a", 3).take(4).collect();
+        assert_eq!(first, session_fractions("a", 3).take(4).collect::<Vec<_>>());
+        assert_ne!(first, session_fractions("a", 4).take(4).collect::<Vec<_>>());
+    }
+
+    #[sqlx::test(migrator = "MIGRATOR")]
+    #[ignore = "needs a Postgres server at DATABASE_URL"]
+    async fn duplicates_are_found_by_content_alone(pool: PgPool) {
+        let mut store = Store::with_pool(pool);
+        let content =
+            "Call std::fs::read_to_string with the path to get the whole file as a String";
+        let original = store
+            .add_question(NewQuestion {
+                title: "How do I load a file?".to_string(),
+                content: content.to_string(),
+                tags: None,
+            })
+            .await
+            .unwrap();
+
+        let candidates = store
+            .find_duplicate_questions("Reading text from disk", content, 5)
+            .await
+            .unwrap();
+        assert_eq!(candidates.len(), 1);
+        assert_eq!(candidates[0].question.id, original.id);
+    }
+
+    #[sqlx::test(migrator = "MIGRATOR")]
+    #[ignore = "needs a Postgres server at DATABASE_URL"]
+    async fn edited_questions_are_exported_since_their_edit(pool: PgPool) {
+        use futures_util::TryStreamExt;
+
+        let mut store = Store::with_pool(pool.clone());
+        let question = |title: &str| NewQuestion {
+            title: title.to_string(),
+            content: "Content".to_string(),
+            tags: None,
+        };
+        let edited = store.add_question(question("Edited")).await.unwrap();
+        store.add_question(question("Untouched")).await.unwrap();
+        sqlx::query("UPDATE questions SET created_on = '2024-01-01', updated_on = '2024-01-01';")
+            .execute(&pool)
+            .await
+            .unwrap();
+        store
+            .update_question(&edited.id, question("Edited again"))
+            .await
+            .unwrap();
+
+        let since = parse_since("2024-06-01").unwrap();
+        let exported: Vec<Vec<ExportQuestion>> = store
+            .export_questions(Some(since))
+            .try_collect()
+            .await
+            .unwrap();
+        let titles: Vec<&str> = exported
+            .iter()
+            .flatten()
+            .map(|q| q.title.as_str())
+            .collect();
+        assert_eq!(titles, ["Edited again"]);
+    }
+
+    #[sqlx::test(migrator = "MIGRATOR")]
+    #[ignore = "needs a Postgres server at DATABASE_URL"]
+    async fn imported_answers_are_written_to_the_outbox(pool: PgPool) {
+        let mut store = Store::with_pool(pool);
+        let answer = |content: &str| ImportAnswer {
+            content: content.to_string(),
+        };
+        let imported = ImportQuestion {
+            title: "Imported".to_string(),
+            content: "Content".to_string(),
+            tags: Some(vec!["import".to_string()]),
+            answers: vec![answer("First"), answer("Second")],
+        };
+        let id = store.import_questions(&[imported], false).await.unwrap()[0]
+            .as_ref()
+            .copied()
+            .unwrap();
+
+        let events = store.relay_outbox(10).await.unwrap();
+        let kinds: Vec<ChangeKind> = events.iter().map(|e| e.kind).collect();
+        assert_eq!(
+            kinds,
+            [
+                ChangeKind::QuestionCreated,
+                ChangeKind::AnswerCreated,
+                ChangeKind::AnswerCreated
+            ]
+        );
+        assert!(events.iter().all(|e| e.question_id == id));
+        assert_eq!(events[2].data["content"], "Second");
+        assert_eq!(events[2].tags, Some(vec!["import".to_string()]));
+    }
 }
@@ -0,0 +1,248 @@
+use crate::*;
+
+/// Ease every question starts with
+const DEFAULT_EASE: f64 = 2.5;
+
+/// Lowest the ease can drop to, so that hard questions still come up less often after a while
+const MIN_EASE: f64 = 1.3;
+
+/// Highest recall quality a grade can have
+const MAX_QUALITY: u8 = 5;
+
+/// Lowest recall quality that counts as remembering the answer
+const PASSING_QUALITY: u8 = 3;
+
+/// Spaced repetition schedule of a question for one user
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Schedule {
+    pub ease: f64,
+    pub interval_days: i32,
+    pub repetitions: i32,
+}
+
+impl Default for Schedule {
+    fn default() -> Self {
+        Schedule {
+            ease: DEFAULT_EASE,
+            interval_days: 0,
+            repetitions: 0,
+        }
+    }
+}
+
+impl Schedule {
+    /// Reschedule after a review using SM-2
+    /// A passing grade waits 1 day, then 6 days, then the last interval times the ease, a
+    /// failing grade starts over from 1 day, and the ease moves with the quality of every grade
+    pub fn graded(self, quality: u8) -> Self {
+        let quality = quality.min(MAX_QUALITY);
+        let (interval_days, repetitions) = if quality >= PASSING_QUALITY {
+            let interval_days = match self.repetitions {
+                0 => 1,
+                1 => 6,
+                _ => (self.interval_days as f64 * self.ease).round() as i32,
+            };
+            (interval_days, self.repetitions + 1)
+        } else {
+            (1, 0)
+        };
+
+        let miss = (MAX_QUALITY - quality) as f64;
+        let ease = (self.ease + 0.1 - miss * (0.08 + miss * 0.02)).max(MIN_EASE);
+        Schedule {
+            ease,
+            interval_days,
+            repetitions,
+        }
+    }
+}
+
+/// Review struct with the study state of a question for the logged in user
+#[derive(Debug, Serialize, Clone, ToSchema)]
+pub struct Review {
+    pub question_id: i32,
+    pub ease: f64,
+    pub interval_days: i32,
+    pub repetitions: i32,
+    pub due_on: NaiveDateTime,
+    pub last_reviewed_on: NaiveDateTime,
+}
+
+/// Study card struct returned by the `study/next` route
+/// The answers are left out until they are revealed, `review` is null for a new question
+/// This only hides them from the card, they can still be read from the `answers` route or with
+/// `include=answers` on the `question` route
+#[derive(Debug, Serialize, Clone, ToSchema)]
+pub struct StudyCard {
+    #[schema(value_type = Question)]
+    pub question: RenderedQuestion,
+    pub answer_count: i64,
+    pub review: Option<Review>,
+}
+
+/// Grade struct that is being extracted from the json body of the `study/grade` route
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct Grade {
+    /// How well the answer was recalled, from 0 (not at all) to 5 (perfectly)
+    pub quality: u8,
+}
+
+/// Fetch the next question to study from the `study/next` route
+/// # Example query
+/// GET requests to this route return the question that has been due the longest for the logged
+/// in user, or a question they have not studied yet if nothing is due, without its answers
+/// `/study/next?tag=rust`
+#[utoipa::path(
+    get,
+    path = "/study/next",
+    tag = "study",
+    params(("tag" = Option<String>, Query, description = "Only study questions with this tag")),
+    responses(
+        (status = 200, description = "Next question to study", body = StudyCard),
+        (status = 400, description = "Database error", body = String),
+        (status = 401, description = "Missing or wrong credentials", body = String),
+        (status = 404, description = "Nothing is due and every question has been studied", body = String),
+    ),
+    security(("basic" = []))
+)]
+pub async fn get_next_study_card(
+    State(store): State<Arc<RwLock<Store>>>,
+    user: AuthUser,
+    Query(params): Query<HashMap<String, String>>,
+) -> Response {
+    let tag = params
+        .get("tag")
+        .map(String::as_str)
+        .filter(|t| !t.is_empty());
+    match store.read().await.get_next_study_card(user.id, tag).await {
+        Ok(Some(card)) => (StatusCode::OK, Json(card)).into_response(),
+        Ok(None) => (StatusCode::NOT_FOUND, Err::NothingToStudy.to_string()).into_response(),
+        Err(e) => (StatusCode::BAD_REQUEST, e.to_string()).into_response(),
+    }
+}
+
+/// Reveal the answers of a question from the `study/answers` route
+/// # Example query
+/// GET requests to this route return the answers once the user has tried to recall them
+/// Revealing does not change the schedule, and the answers are public through the other routes
+/// anyway, the study card only leaves them out so they are not seen by accident
+/// `/study/3/answers`
+#[utoipa::path(
+    get,
+    path = "/study/{id}/answers",
+    tag = "study",
+    params(("id" = i32, Path, description = "Question id")),
+    responses(
+        (status = 200, description = "Answers to the question", body = [Answer]),
+        (status = 400, description = "Database error", body = String),
+        (status = 401, description = "Missing or wrong credentials", body = String),
+        (status = 404, description = "Question not found", body = String),
+    ),
+    security(("basic" = []))
+)]
+pub async fn reveal_study_answers(
+    State(store): State<Arc<RwLock<Store>>>,
+    _user: AuthUser,
+    Path(id): Path<i32>,
+) -> Response {
+    let store = store.read().await;
+
+    // Check that the question exists since a missing one has no answers either
+    match store.get_question(&id).await {
+        Ok(_) => {}
+        Err(sqlx::Error::RowNotFound) => {
+            return (StatusCode::NOT_FOUND, Err::QuestionNotFound.to_string()).into_response()
+        }
+        Err(e) => return (StatusCode::BAD_REQUEST, e.to_string()).into_response(),
+    }
+
+    match store.get_answers_for_questions(&[id]).await {
+        Ok(answers) => {
+            let answers: Vec<RenderedAnswer> = answers.into_iter().map(Into::into).collect();
+            (StatusCode::OK, Json(answers)).into_response()
+        }
+        Err(e) => (StatusCode::BAD_REQUEST, e.to_string()).into_response(),
+    }
+}
+
+/// Grade how well a question was recalled from the `study/grade` route
+/// # Example query
+/// POST requests to this route have a json body with the recall quality from 0 to 5, and the
+/// question is rescheduled for the logged in user
+/// `/study/3/grade`
+/// `{
+///     "quality": 4
+/// }`
+#[utoipa::path(
+    post,
+    path = "/study/{id}/grade",
+    tag = "study",
+    params(("id" = i32, Path, description = "Question id")),
+    request_body = Grade,
+    responses(
+        (status = 200, description = "New study state of the question", body = Review),
+        (status = 400, description = "Quality is invalid or database error", body = String),
+        (status = 401, description = "Missing or wrong credentials", body = String),
+        (status = 404, description = "Question not found", body = String),
+    ),
+    security(("basic" = []))
+)]
+pub async fn grade_study_card(
+    State(store): State<Arc<RwLock<Store>>>,
+    user: AuthUser,
+    Path(id): Path<i32>,
+    Json(grade): Json<Grade>,
+) -> Response {
+    if grade.quality > MAX_QUALITY {
+        return (StatusCode::BAD_REQUEST, Err::InvalidQuality.to_string()).into_response();
+    }
+
+    match store
+        .write()
+        .await
+        .grade_study_card(user.id, &id, grade.quality)
+        .await
+    {
+        Ok(review) => (StatusCode::OK, Json(review)).into_response(),
+        Err(sqlx::Error::RowNotFound) => {
+            (StatusCode::NOT_FOUND, Err::QuestionNotFound.to_string()).into_response()
+        }
+        Err(e) => (StatusCode::BAD_REQUEST, e.to_string()).into_response(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn passing_grades_grow_the_interval() {
+        let first = Schedule::default().graded(4);
+        let second = first.graded(4);
+        let third = second.graded(4);
+
+        assert_eq!(first.interval_days, 1);
+        assert_eq!(second.interval_days, 6);
+        // A grade of 4 leaves the ease at 2.5
+        assert_eq!(third.interval_days, 15);
+        assert_eq!(third.repetitions, 3);
+        assert!((third.ease - DEFAULT_EASE).abs() < 1e-9);
+    }
+
+    #[test]
+    fn failing_grades_start_over_and_lower_the_ease() {
+        let learned = Schedule::default().graded(5).graded(5).graded(5);
+        let forgotten = learned.graded(1);
+
+        assert_eq!(forgotten.interval_days, 1);
+        assert_eq!(forgotten.repetitions, 0);
+        assert!(forgotten.ease < learned.ease);
+
+        // The ease never drops below the minimum
+        let mut schedule = Schedule::default();
+        for _ in 0..20 {
+            schedule = schedule.graded(0);
+        }
+        assert_eq!(schedule.ease, MIN_EASE);
+    }
+}
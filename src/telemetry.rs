@@ -0,0 +1,124 @@
+use opentelemetry::{trace::TracerProvider as _, KeyValue};
+use opentelemetry_otlp::WithExportConfig;
+use opentelemetry_sdk::{export::trace::SpanExporter, runtime, trace::TracerProvider, Resource};
+use std::sync::OnceLock;
+use tracing_subscriber::{registry::LookupSpan, Layer};
+
+/// Tracer provider kept around so that it can be flushed on shutdown
+static PROVIDER: OnceLock<TracerProvider> = OnceLock::new();
+
+/// Build the layer that exports spans to the OTLP collector at `OTEL_EXPORTER_OTLP_ENDPOINT`
+/// Returns `None` when no endpoint is configured so the server can run without a collector
+pub fn layer<S>() -> Option<Box<dyn Layer<S> + Send + Sync>>
+where
+    S: tracing::Subscriber + for<'a> LookupSpan<'a> + Send + Sync,
+{
+    let endpoint = std::env::var("OTEL_EXPORTER_OTLP_ENDPOINT").ok()?;
+
+    // Tracing has not been set up yet, so report errors on stderr
+    match opentelemetry_otlp::SpanExporter::builder()
+        .with_tonic()
+        .with_endpoint(endpoint)
+        .build()
+    {
+        Ok(exporter) => Some(layer_with_exporter(exporter)),
+        Err(e) => {
+            eprintln!("Failed to create the OTLP exporter: {:?}", e);
+            None
+        }
+    }
+}
+
+/// Build the export layer around any span exporter
+/// This lets tests swap the collector for an in-process exporter and inspect the spans
+pub fn layer_with_exporter<S, E>(exporter: E) -> Box<dyn Layer<S> + Send + Sync>
+where
+    S: tracing::Subscriber + for<'a> LookupSpan<'a> + Send + Sync,
+    E: SpanExporter + 'static,
+{
+    let service_name =
+        std::env::var("OTEL_SERVICE_NAME").unwrap_or_else(|_| env!("CARGO_PKG_NAME").to_owned());
+
+    let provider = TracerProvider::builder()
+        .with_batch_exporter(exporter, runtime::Tokio)
+        .with_resource(Resource::new([KeyValue::new("service.name", service_name)]))
+        .build();
+    let tracer = provider.tracer(env!("CARGO_PKG_NAME"));
+
+    // Keep the provider so it can be flushed on shutdown
+    let _ = PROVIDER.set(provider);
+
+    tracing_opentelemetry::layer().with_tracer(tracer).boxed()
+}
+
+/// Export any spans that are still buffered and stop the exporter
+pub async fn shutdown() {
+    // Shutting down the provider blocks until the batch has been flushed
+    if let Some(provider) = PROVIDER.get() {
+        let provider = provider.clone();
+        if let Ok(Err(e)) = tokio::task::spawn_blocking(move || provider.shutdown()).await {
+            tracing::warn!("Failed to flush spans: {:?}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::*;
+    use opentelemetry_sdk::testing::trace::InMemorySpanExporter;
+    use tower::ServiceExt;
+    use tower_http::trace::TraceLayer;
+    use tracing_subscriber::layer::SubscriberExt;
+
+    /// The store span is created even though the offline store's query fails, so no database is
+    /// needed
+    #[tokio::test(flavor = "multi_thread")]
+    async fn store_spans_are_children_of_the_request_span() {
+        let exporter = InMemorySpanExporter::default();
+        let subscriber = tracing_subscriber::registry().with(layer_with_exporter(exporter.clone()));
+        let _guard = tracing::subscriber::set_default(subscriber);
+
+        let app = routes(RateLimiter::new())
+            .layer(TraceLayer::new_for_http().make_span_with(make_request_span))
+            .with_state(Arc::new(RwLock::new(Store::offline())));
+        let request = Request::builder()
+            .uri("/api/v1/questions")
+            .body(axum::body::Body::empty())
+            .unwrap();
+        let response = app.oneshot(request).await.unwrap();
+
+        // The request span ends once the body has been sent
+        axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+
+        // Spans are exported in batches, so flush them before reading what was exported
+        let provider = PROVIDER.get().unwrap().clone();
+        tokio::task::spawn_blocking(move || provider.force_flush())
+            .await
+            .unwrap();
+        let spans = exporter.get_finished_spans().unwrap();
+
+        let request_span = spans
+            .iter()
+            .find(|span| span.name == "http_request")
+            .expect("no request span was exported");
+        let store_span = spans
+            .iter()
+            .find(|span| span.name == "store.get_questions")
+            .expect("no store span was exported");
+        assert_eq!(
+            store_span.parent_span_id,
+            request_span.span_context.span_id()
+        );
+        assert_eq!(
+            store_span.span_context.trace_id(),
+            request_span.span_context.trace_id()
+        );
+        assert!(store_span
+            .attributes
+            .iter()
+            .any(|attribute| attribute.key.as_str() == "db.operation"));
+    }
+}
@@ -0,0 +1,170 @@
+use crate::*;
+use argon2::{
+    password_hash::{rand_core::OsRng, PasswordHash, PasswordHasher, PasswordVerifier, SaltString},
+    Argon2,
+};
+use axum::extract::FromRequestParts;
+use axum::http::request::Parts;
+use base64::Engine;
+use std::sync::OnceLock;
+
+/// Realm sent to clients that have to log in
+const AUTH_REALM: &str = "Basic realm=\"questions\"";
+
+/// New user struct used to create users in the database
+#[derive(Debug, Clone)]
+pub struct NewUser {
+    pub username: String,
+    pub password_hash: String,
+    pub is_admin: bool,
+}
+
+impl NewUser {
+    /// Create a user, hashing the password with Argon2 and a random salt so that it is never
+    /// stored in plain text
+    pub fn new(username: &str, password: &str, is_admin: bool) -> Result<Self, Box<dyn Error>> {
+        let salt = SaltString::generate(&mut OsRng);
+        let password_hash = Argon2::default()
+            .hash_password(password.as_bytes(), &salt)
+            .map_err(|e| e.to_string())?
+            .to_string();
+        Ok(NewUser {
+            username: username.to_owned(),
+            password_hash,
+            is_admin,
+        })
+    }
+}
+
+/// Hash of a password nobody has, checked when a username is not found so that a missing user
+/// takes as long to reject as a wrong password and usernames cannot be found by timing
+/// The server hashes it on start so that the first unknown username is not slower either
+pub fn dummy_password_hash() -> &'static str {
+    static DUMMY_PASSWORD_HASH: OnceLock<String> = OnceLock::new();
+    DUMMY_PASSWORD_HASH.get_or_init(|| {
+        let salt = SaltString::generate(&mut OsRng);
+        Argon2::default()
+            .hash_password(b"dummy password", &salt)
+            .map(|hash| hash.to_string())
+            .unwrap_or_default()
+    })
+}
+
+/// Authenticated user extracted from the `Authorization: Basic` header of a request
+/// The password is checked against the hash in the `users` table
+#[derive(Debug, Clone)]
+pub struct AuthUser {
+    pub id: i32,
+    pub username: String,
+    pub is_admin: bool,
+}
+
+#[axum::async_trait]
+impl FromRequestParts<Arc<RwLock<Store>>> for AuthUser {
+    type Rejection = Response;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        store: &Arc<RwLock<Store>>,
+    ) -> Result<Self, Self::Rejection> {
+        let unauthorized = || {
+            (
+                StatusCode::UNAUTHORIZED,
+                [(header::WWW_AUTHENTICATE, AUTH_REALM)],
+                Err::Unauthorized.to_string(),
+            )
+                .into_response()
+        };
+
+        // Decode the username and password from the header
+        let credentials = parts
+            .headers
+            .get(header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Basic "))
+            .and_then(|encoded| {
+                base64::engine::general_purpose::STANDARD
+                    .decode(encoded.trim())
+                    .ok()
+            })
+            .and_then(|decoded| String::from_utf8(decoded).ok());
+        let Some((username, password)) = credentials
+            .as_deref()
+            .and_then(|credentials| credentials.split_once(':'))
+        else {
+            return Err(unauthorized());
+        };
+
+        // Look the user up and check the password off the async runtime, hashing is slow on
+        // purpose
+        // A username that is not found is checked against a dummy hash so it costs the same
+        let (user, password_hash) = match store.read().await.get_user_credentials(username).await {
+            Ok(Some((id, password_hash, is_admin))) => (Some((id, is_admin)), Some(password_hash)),
+            Ok(None) => (None, None),
+            Err(e) => return Err((StatusCode::BAD_REQUEST, e.to_string()).into_response()),
+        };
+        let password = password.to_owned();
+        let verified = tokio::task::spawn_blocking(move || {
+            let password_hash = match &password_hash {
+                Some(password_hash) => password_hash.as_str(),
+                None => dummy_password_hash(),
+            };
+            PasswordHash::new(password_hash).is_ok_and(|hash| {
+                Argon2::default()
+                    .verify_password(password.as_bytes(), &hash)
+                    .is_ok()
+            })
+        })
+        .await
+        .unwrap_or(false);
+
+        match user {
+            Some((id, is_admin)) if verified => Ok(AuthUser {
+                id,
+                username: username.to_owned(),
+                is_admin,
+            }),
+            _ => Err(unauthorized()),
+        }
+    }
+}
+
+/// Authenticated user that is an admin, for the routes that delete data or act for the operators
+/// Users that are not admins get a 403 instead of a 401 so that clients do not ask them to log in
+/// again
+#[derive(Debug, Clone)]
+pub struct AdminUser(pub AuthUser);
+
+#[axum::async_trait]
+impl FromRequestParts<Arc<RwLock<Store>>> for AdminUser {
+    type Rejection = Response;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        store: &Arc<RwLock<Store>>,
+    ) -> Result<Self, Self::Rejection> {
+        let user = AuthUser::from_request_parts(parts, store).await?;
+        if user.is_admin {
+            Ok(AdminUser(user))
+        } else {
+            Err((StatusCode::FORBIDDEN, Err::AdminRequired.to_string()).into_response())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_dummy_hash_costs_as_much_as_a_real_one() {
+        let user = NewUser::new("user", "password", false).unwrap();
+        let real = PasswordHash::new(&user.password_hash).unwrap();
+        let dummy = PasswordHash::new(dummy_password_hash()).unwrap();
+        assert_eq!(dummy.algorithm, real.algorithm);
+        assert_eq!(dummy.params, real.params);
+        assert!(Argon2::default()
+            .verify_password(b"password", &dummy)
+            .is_err());
+    }
+}
@@ -0,0 +1,909 @@
+use crate::*;
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use hmac::{Hmac, Mac};
+use reqwest::Url;
+use sha2::Sha256;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+/// Default number of attempts before a delivery becomes a dead letter
+const DEFAULT_WEBHOOK_MAX_ATTEMPTS: i32 = 8;
+
+/// Default delay before the first retry in seconds, doubled after each failed attempt
+const DEFAULT_WEBHOOK_BACKOFF: u64 = 5;
+
+/// Longest delay between two attempts
+const MAX_WEBHOOK_BACKOFF: Duration = Duration::from_secs(60 * 60);
+
+/// Default time to wait for a webhook to respond in seconds
+const DEFAULT_WEBHOOK_TIMEOUT: u64 = 10;
+
+/// How often the worker looks for deliveries that are due
+const WEBHOOK_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Number of deliveries claimed and sent at a time
+const WEBHOOK_BATCH_SIZE: i64 = 20;
+
+/// Default number of deliveries returned by the delivery log and the dead letter list
+const DEFAULT_DELIVERY_PAGE_SIZE: i64 = 50;
+
+/// Headers sent with every delivery
+pub const WEBHOOK_SIGNATURE_HEADER: &str = "x-webhook-signature";
+pub const WEBHOOK_TIMESTAMP_HEADER: &str = "x-webhook-timestamp";
+pub const WEBHOOK_EVENT_HEADER: &str = "x-webhook-event";
+pub const WEBHOOK_DELIVERY_HEADER: &str = "x-webhook-delivery";
+
+/// Webhook struct used to store webhooks in the database
+/// The secret is only returned when the webhook is created
+#[derive(Debug, Serialize, Clone, Deserialize, ToSchema)]
+pub struct Webhook {
+    pub id: i32,
+    pub url: String,
+    pub event_types: Option<Vec<String>>,
+    pub active: bool,
+    pub created_on: NaiveDateTime,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub secret: Option<String>,
+}
+
+impl Webhook {
+    /// Read a webhook from a row of the `webhooks` table, leaving out the secret
+    pub fn from_row(row: &PgRow) -> Self {
+        Webhook {
+            id: row.get("id"),
+            url: row.get("url"),
+            event_types: row.get("event_types"),
+            active: row.get("active"),
+            created_on: row.get("created_on"),
+            secret: None,
+        }
+    }
+}
+
+/// New webhook struct used to create and update webhooks in the database
+/// A random secret is generated if none is given, it cannot be changed afterwards
+/// Webhooks without `event_types` receive every event
+#[derive(Debug, Serialize, Clone, Deserialize, ToSchema)]
+pub struct NewWebhook {
+    pub url: String,
+    pub secret: Option<String>,
+    pub event_types: Option<Vec<String>>,
+    pub active: Option<bool>,
+}
+
+/// Hosts that webhooks can be sent to even though they are private, read from the comma
+/// separated `WEBHOOK_ALLOWED_HOSTS`
+fn allowed_webhook_hosts() -> Vec<String> {
+    std::env::var("WEBHOOK_ALLOWED_HOSTS")
+        .unwrap_or_default()
+        .split(',')
+        .map(|host| host.trim().to_lowercase())
+        .filter(|host| !host.is_empty())
+        .collect()
+}
+
+/// Check whether an address is loopback, link-local, private or unspecified
+fn is_private_ip(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => is_private_ipv4(ip),
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => is_private_ipv4(ip),
+            None => is_private_ipv6(ip),
+        },
+    }
+}
+
+fn is_private_ipv4(ip: Ipv4Addr) -> bool {
+    ip.is_loopback() || ip.is_private() || ip.is_link_local() || ip.is_unspecified()
+}
+
+fn is_private_ipv6(ip: Ipv6Addr) -> bool {
+    let first = ip.segments()[0];
+    ip.is_loopback()
+        || ip.is_unspecified()
+        // Unique local addresses, fc00::/7
+        || first & 0xfe00 == 0xfc00
+        // Link-local addresses, fe80::/10
+        || first & 0xffc0 == 0xfe80
+}
+
+/// Check whether the host of a url is this machine or on the private network, so that webhooks
+/// cannot be used to make the server send requests to internal services
+fn is_private_host(host: &str) -> bool {
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    match host.parse::<IpAddr>() {
+        Ok(ip) => is_private_ip(ip),
+        Err(_) => {
+            let domain = host.trim_end_matches('.');
+            domain == "localhost" || domain.ends_with(".localhost")
+        }
+    }
+}
+
+/// DNS resolver for the webhook client that drops private addresses, so that a public name
+/// which resolves to this machine or the private network cannot be used to get around the check
+/// in `NewWebhook::validate`
+/// Hosts in `WEBHOOK_ALLOWED_HOSTS` resolve as usual
+struct PublicResolver {
+    allowed: Vec<String>,
+}
+
+impl reqwest::dns::Resolve for PublicResolver {
+    fn resolve(&self, name: reqwest::dns::Name) -> reqwest::dns::Resolving {
+        let host = name.as_str().to_lowercase();
+        let allowed = self.allowed.contains(&host);
+        Box::pin(async move {
+            let addrs: Vec<SocketAddr> = tokio::net::lookup_host((host.as_str(), 0))
+                .await?
+                .filter(|addr| allowed || !is_private_ip(addr.ip()))
+                .collect();
+            if addrs.is_empty() {
+                return Err(format!("{} does not resolve to a public address", host).into());
+            }
+            let addrs: reqwest::dns::Addrs = Box::new(addrs.into_iter());
+            Ok(addrs)
+        })
+    }
+}
+
+/// Create the HTTP client used to send deliveries
+/// Redirects are not followed and names that resolve to private addresses are refused, unless
+/// their host is in `WEBHOOK_ALLOWED_HOSTS`
+fn webhook_client(timeout: Duration) -> reqwest::Client {
+    reqwest::Client::builder()
+        .timeout(timeout)
+        .redirect(reqwest::redirect::Policy::none())
+        .dns_resolver(Arc::new(PublicResolver {
+            allowed: allowed_webhook_hosts(),
+        }))
+        .build()
+        .expect("the HTTP client has a valid configuration")
+}
+
+impl NewWebhook {
+    /// Check that the url is an HTTP url that does not point at a private address, unless its host
+    /// is allowed in `WEBHOOK_ALLOWED_HOSTS`, and that every event type exists
+    pub fn validate(&self) -> Result<(), Err> {
+        if !(self.url.starts_with("http://") || self.url.starts_with("https://")) {
+            return Err(Err::InvalidUrl(self.url.clone()));
+        }
+        let url = Url::parse(&self.url).map_err(|_| Err::InvalidUrl(self.url.clone()))?;
+        let Some(host) = url.host_str().map(str::to_lowercase) else {
+            return Err(Err::InvalidUrl(self.url.clone()));
+        };
+        if is_private_host(&host) && !allowed_webhook_hosts().contains(&host) {
+            return Err(Err::PrivateWebhookUrl(self.url.clone()));
+        }
+        for event_type in self.event_types.iter().flatten() {
+            if ChangeKind::parse(event_type).is_none() {
+                return Err(Err::UnknownEventType(event_type.clone()));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Status of a webhook delivery
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum DeliveryStatus {
+    Pending,
+    Delivered,
+    Dead,
+}
+
+impl DeliveryStatus {
+    /// Name of the status, as stored in the `status` column
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DeliveryStatus::Pending => "pending",
+            DeliveryStatus::Delivered => "delivered",
+            DeliveryStatus::Dead => "dead",
+        }
+    }
+
+    fn parse(status: &str) -> Self {
+        match status {
+            "delivered" => DeliveryStatus::Delivered,
+            "dead" => DeliveryStatus::Dead,
+            _ => DeliveryStatus::Pending,
+        }
+    }
+}
+
+/// Webhook delivery struct used for the delivery log and the dead letter list
+#[derive(Debug, Serialize, Clone, ToSchema)]
+pub struct WebhookDelivery {
+    pub id: i64,
+    pub webhook_id: i32,
+    pub event_id: i64,
+    pub event_type: String,
+    #[schema(value_type = Object)]
+    pub payload: serde_json::Value,
+    pub status: DeliveryStatus,
+    pub attempts: i32,
+    pub next_attempt_on: NaiveDateTime,
+    pub last_status_code: Option<i32>,
+    pub last_error: Option<String>,
+    pub created_on: NaiveDateTime,
+    pub delivered_on: Option<NaiveDateTime>,
+}
+
+impl WebhookDelivery {
+    /// Read a delivery from a row of the `webhook_deliveries` table
+    pub fn from_row(row: &PgRow) -> Self {
+        WebhookDelivery {
+            id: row.get("id"),
+            webhook_id: row.get("webhook_id"),
+            event_id: row.get("event_id"),
+            event_type: row.get("event_type"),
+            payload: row
+                .get::<sqlx::types::Json<serde_json::Value>, _>("payload")
+                .0,
+            status: DeliveryStatus::parse(row.get("status")),
+            attempts: row.get("attempts"),
+            next_attempt_on: row.get("next_attempt_on"),
+            last_status_code: row.get("last_status_code"),
+            last_error: row.get("last_error"),
+            created_on: row.get("created_on"),
+            delivered_on: row.get("delivered_on"),
+        }
+    }
+}
+
+/// Delivery claimed by the worker, with what it needs to send it
+#[derive(Debug, Clone)]
+pub struct PendingDelivery {
+    pub id: i64,
+    pub event_type: String,
+    pub payload: serde_json::Value,
+    pub attempts: i32,
+    pub url: String,
+    pub secret: String,
+}
+
+/// Outcome of one delivery attempt
+#[derive(Debug, Clone, PartialEq)]
+pub struct DeliveryOutcome {
+    pub delivered: bool,
+    pub status_code: Option<i32>,
+    pub error: Option<String>,
+}
+
+/// Generate a random secret for a webhook
+fn generate_secret() -> String {
+    let mut bytes = [0u8; 32];
+    OsRng.fill_bytes(&mut bytes);
+    hex::encode(bytes)
+}
+
+/// Sign a payload for a webhook
+/// The signature is the hex HMAC-SHA256 of `{timestamp}.{body}` keyed with the webhook's secret,
+/// receivers should recompute it and reject old timestamps to stop replays
+pub fn sign_payload(secret: &str, timestamp: i64, body: &[u8]) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts any key length");
+    mac.update(timestamp.to_string().as_bytes());
+    mac.update(b".");
+    mac.update(body);
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+/// Send one delivery, any 2xx response counts as delivered
+pub async fn deliver(client: &reqwest::Client, delivery: &PendingDelivery) -> DeliveryOutcome {
+    let body = serde_json::to_vec(&delivery.payload).unwrap_or_default();
+    let timestamp = chrono::Utc::now().timestamp();
+    let response = client
+        .post(&delivery.url)
+        .header(header::CONTENT_TYPE, "application/json")
+        .header(
+            WEBHOOK_SIGNATURE_HEADER,
+            sign_payload(&delivery.secret, timestamp, &body),
+        )
+        .header(WEBHOOK_TIMESTAMP_HEADER, timestamp)
+        .header(WEBHOOK_EVENT_HEADER, &delivery.event_type)
+        .header(WEBHOOK_DELIVERY_HEADER, delivery.id)
+        .body(body)
+        .send()
+        .await;
+
+    match response {
+        Ok(response) if response.status().is_success() => DeliveryOutcome {
+            delivered: true,
+            status_code: Some(response.status().as_u16() as i32),
+            error: None,
+        },
+        Ok(response) => DeliveryOutcome {
+            delivered: false,
+            status_code: Some(response.status().as_u16() as i32),
+            error: Some(format!("Webhook responded with {}", response.status())),
+        },
+        Err(e) => DeliveryOutcome {
+            delivered: false,
+            status_code: None,
+            error: Some(e.to_string()),
+        },
+    }
+}
+
+/// Delay before the next attempt after `attempts` failed ones, doubling each time
+fn backoff(base: Duration, attempts: i32) -> Duration {
+    let exponent = attempts.saturating_sub(1).clamp(0, 20) as u32;
+    base.saturating_mul(2u32.pow(exponent))
+        .min(MAX_WEBHOOK_BACKOFF)
+}
+
+/// Webhook worker struct that sends the deliveries queued by the outbox relay
+#[derive(Clone)]
+pub struct WebhookWorker {
+    store: Store,
+    client: reqwest::Client,
+    max_attempts: i32,
+    backoff: Duration,
+    timeout: Duration,
+}
+
+impl WebhookWorker {
+    /// Constructor that reads `WEBHOOK_MAX_ATTEMPTS`, `WEBHOOK_BACKOFF` (seconds before the first
+    /// retry) and `WEBHOOK_TIMEOUT` (seconds)
+    pub fn new(store: Store) -> Self {
+        use std::env::var;
+
+        let max_attempts = var("WEBHOOK_MAX_ATTEMPTS")
+            .ok()
+            .and_then(|a| a.parse::<i32>().ok())
+            .filter(|a| *a > 0)
+            .unwrap_or(DEFAULT_WEBHOOK_MAX_ATTEMPTS);
+        let backoff = var("WEBHOOK_BACKOFF")
+            .ok()
+            .and_then(|b| b.parse::<u64>().ok())
+            .unwrap_or(DEFAULT_WEBHOOK_BACKOFF);
+        let timeout = var("WEBHOOK_TIMEOUT")
+            .ok()
+            .and_then(|t| t.parse::<u64>().ok())
+            .unwrap_or(DEFAULT_WEBHOOK_TIMEOUT);
+        let timeout = Duration::from_secs(timeout);
+
+        WebhookWorker {
+            store,
+            client: webhook_client(timeout),
+            max_attempts,
+            backoff: Duration::from_secs(backoff),
+            timeout,
+        }
+    }
+
+    /// Send the deliveries that are due until the server shuts down
+    pub async fn run_deliveries(mut self, shutdown: Shutdown) {
+        let mut interval = tokio::time::interval(WEBHOOK_POLL_INTERVAL);
+        loop {
+            tokio::select! {
+                _ = interval.tick() => {}
+                _ = shutdown.token.cancelled() => return,
+            }
+
+            // Keep going while there are full batches waiting
+            loop {
+                match self.send_due().await {
+                    Ok(sent) if sent as i64 == WEBHOOK_BATCH_SIZE => continue,
+                    Ok(_) => break,
+                    Err(e) => {
+                        tracing::error!("Could not send webhook deliveries: {:?}", e);
+                        break;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Claim a batch of due deliveries, send them at the same time and record the outcomes
+    async fn send_due(&mut self) -> Result<usize, sqlx::Error> {
+        // Lease the deliveries for longer than a send can take so a slow one is not sent twice
+        let lease = self.timeout * 2;
+        let deliveries = self
+            .store
+            .claim_webhook_deliveries(WEBHOOK_BATCH_SIZE, lease)
+            .await?;
+
+        let outcomes = futures_util::future::join_all(
+            deliveries
+                .iter()
+                .map(|delivery| deliver(&self.client, delivery)),
+        )
+        .await;
+
+        for (delivery, outcome) in deliveries.iter().zip(outcomes) {
+            let retry_in = if outcome.delivered || delivery.attempts >= self.max_attempts {
+                None
+            } else {
+                Some(backoff(self.backoff, delivery.attempts))
+            };
+            if !outcome.delivered {
+                tracing::warn!(
+                    "Webhook delivery {} to {} failed on attempt {}: {}",
+                    delivery.id,
+                    delivery.url,
+                    delivery.attempts,
+                    outcome.error.as_deref().unwrap_or_default()
+                );
+            }
+            self.store
+                .record_webhook_delivery(&delivery.id, &outcome, retry_in)
+                .await?;
+        }
+        Ok(deliveries.len())
+    }
+}
+
+/// Extract the `limit` and `offset` query params of the delivery routes
+fn extract_page(params: &HashMap<String, String>) -> Result<(i64, i64), Err> {
+    let limit = match params.get("limit") {
+        Some(limit) => limit.parse::<i64>().map_err(Err::ParseInt)?,
+        None => DEFAULT_DELIVERY_PAGE_SIZE,
+    };
+    let offset = match params.get("offset") {
+        Some(offset) => offset.parse::<i64>().map_err(Err::ParseInt)?,
+        None => 0,
+    };
+    Ok((limit, offset))
+}
+
+/// Fetch every webhook from the `webhooks` route
+/// # Example query
+/// GET requests to this route return every webhook without its secret
+/// `/webhooks`
+#[utoipa::path(
+    get,
+    path = "/webhooks",
+    tag = "webhooks",
+    responses(
+        (status = 200, description = "Webhooks found", body = [Webhook]),
+        (status = 400, description = "Database error", body = String),
+        (status = 401, description = "Missing or wrong credentials", body = String),
+        (status = 403, description = "User is not an admin", body = String),
+    ),
+    security(("basic" = []))
+)]
+pub async fn get_webhooks(State(store): State<Arc<RwLock<Store>>>, _admin: AdminUser) -> Response {
+    match store.read().await.get_webhooks().await {
+        Ok(webhooks) => (StatusCode::OK, Json(webhooks)).into_response(),
+        Err(e) => (StatusCode::BAD_REQUEST, e.to_string()).into_response(),
+    }
+}
+
+/// Add a webhook from the `webhooks` route
+/// # Example query
+/// POST requests to this route have a JSON body attached, the response includes the secret
+/// used to sign the deliveries, it is not shown again
+/// `/webhooks`
+/// `{"url": "https://example.com/hook", "event_types": ["question.created"]}`
+#[utoipa::path(
+    post,
+    path = "/webhooks",
+    tag = "webhooks",
+    request_body = NewWebhook,
+    responses(
+        (status = 201, description = "Webhook added", body = Webhook),
+        (status = 400, description = "Webhook is invalid or could not be added", body = String),
+        (status = 401, description = "Missing or wrong credentials", body = String),
+        (status = 403, description = "User is not an admin", body = String),
+    ),
+    security(("basic" = []))
+)]
+pub async fn add_webhook(
+    State(store): State<Arc<RwLock<Store>>>,
+    _admin: AdminUser,
+    Json(mut new_webhook): Json<NewWebhook>,
+) -> Response {
+    if let Err(e) = new_webhook.validate() {
+        return (StatusCode::BAD_REQUEST, e.to_string()).into_response();
+    }
+
+    let secret = new_webhook.secret.take().unwrap_or_else(generate_secret);
+    match store.write().await.add_webhook(new_webhook, secret).await {
+        Ok(webhook) => (StatusCode::CREATED, Json(webhook)).into_response(),
+        Err(e) => (StatusCode::BAD_REQUEST, e.to_string()).into_response(),
+    }
+}
+
+/// Fetch a specific webhook from the `webhook` route based on the id passed in the route
+/// # Example query
+/// GET requests to this route have an id attached so we just return the webhook we need
+/// `/webhook/1`
+#[utoipa::path(
+    get,
+    path = "/webhook/{id}",
+    tag = "webhooks",
+    params(("id" = i32, Path, description = "Webhook id")),
+    responses(
+        (status = 200, description = "Webhook found", body = Webhook),
+        (status = 400, description = "Database error", body = String),
+        (status = 404, description = "Webhook not found", body = String),
+        (status = 401, description = "Missing or wrong credentials", body = String),
+        (status = 403, description = "User is not an admin", body = String),
+    ),
+    security(("basic" = []))
+)]
+pub async fn get_webhook(
+    State(store): State<Arc<RwLock<Store>>>,
+    _admin: AdminUser,
+    Path(id): Path<i32>,
+) -> Response {
+    match store.read().await.get_webhook(&id).await {
+        Ok(webhook) => (StatusCode::OK, Json(webhook)).into_response(),
+        Err(sqlx::Error::RowNotFound) => {
+            (StatusCode::NOT_FOUND, Err::WebhookNotFound.to_string()).into_response()
+        }
+        Err(e) => (StatusCode::BAD_REQUEST, e.to_string()).into_response(),
+    }
+}
+
+/// Update a webhook from the `webhook` route based on the id passed in the route
+/// # Example query
+/// PUT requests to this route have an id and a JSON body attached, the secret cannot be changed
+/// `/webhook/1`
+/// `{"url": "https://example.com/hook", "active": false}`
+#[utoipa::path(
+    put,
+    path = "/webhook/{id}",
+    tag = "webhooks",
+    params(("id" = i32, Path, description = "Webhook id")),
+    request_body = NewWebhook,
+    responses(
+        (status = 200, description = "Webhook updated", body = Webhook),
+        (status = 400, description = "Webhook is invalid or could not be updated", body = String),
+        (status = 404, description = "Webhook not found", body = String),
+        (status = 401, description = "Missing or wrong credentials", body = String),
+        (status = 403, description = "User is not an admin", body = String),
+    ),
+    security(("basic" = []))
+)]
+pub async fn update_webhook(
+    State(store): State<Arc<RwLock<Store>>>,
+    _admin: AdminUser,
+    Path(id): Path<i32>,
+    Json(new_webhook): Json<NewWebhook>,
+) -> Response {
+    if let Err(e) = new_webhook.validate() {
+        return (StatusCode::BAD_REQUEST, e.to_string()).into_response();
+    }
+
+    match store.write().await.update_webhook(&id, new_webhook).await {
+        Ok(webhook) => (StatusCode::OK, Json(webhook)).into_response(),
+        Err(sqlx::Error::RowNotFound) => {
+            (StatusCode::NOT_FOUND, Err::WebhookNotFound.to_string()).into_response()
+        }
+        Err(e) => (StatusCode::BAD_REQUEST, e.to_string()).into_response(),
+    }
+}
+
+/// Delete a webhook and its delivery log from the `webhook` route based on the id passed in the
+/// route
+/// # Example query
+/// DELETE requests to this route have an id attached so we just delete the webhook we need
+/// `/webhook/1`
+#[utoipa::path(
+    delete,
+    path = "/webhook/{id}",
+    tag = "webhooks",
+    params(("id" = i32, Path, description = "Webhook id")),
+    responses(
+        (status = 200, description = "Webhook deleted", body = String),
+        (status = 400, description = "Database error", body = String),
+        (status = 404, description = "Webhook not found", body = String),
+        (status = 401, description = "Missing or wrong credentials", body = String),
+        (status = 403, description = "User is not an admin", body = String),
+    ),
+    security(("basic" = []))
+)]
+pub async fn delete_webhook(
+    State(store): State<Arc<RwLock<Store>>>,
+    _admin: AdminUser,
+    Path(id): Path<i32>,
+) -> Response {
+    match store.write().await.delete_webhook(&id).await {
+        Ok(0) => (StatusCode::NOT_FOUND, Err::WebhookNotFound.to_string()).into_response(),
+        Ok(_) => (StatusCode::OK, "Webhook deleted".to_string()).into_response(),
+        Err(e) => (StatusCode::BAD_REQUEST, e.to_string()).into_response(),
+    }
+}
+
+/// Fetch the delivery log of a webhook from the `webhook/deliveries` route
+/// # Example query
+/// GET requests to this route return the deliveries of the webhook, newest first
+/// `/webhook/1/deliveries?limit=50&offset=0`
+#[utoipa::path(
+    get,
+    path = "/webhook/{id}/deliveries",
+    tag = "webhooks",
+    params(
+        ("id" = i32, Path, description = "Webhook id"),
+        ("limit" = Option<i64>, Query, description = "Maximum number of deliveries to return, defaults to 50"),
+        ("offset" = Option<i64>, Query, description = "Number of deliveries to skip"),
+    ),
+    responses(
+        (status = 200, description = "Deliveries found", body = [WebhookDelivery]),
+        (status = 400, description = "Parameters are invalid or database error", body = String),
+        (status = 404, description = "Webhook not found", body = String),
+        (status = 401, description = "Missing or wrong credentials", body = String),
+        (status = 403, description = "User is not an admin", body = String),
+    ),
+    security(("basic" = []))
+)]
+pub async fn get_webhook_deliveries(
+    State(store): State<Arc<RwLock<Store>>>,
+    _admin: AdminUser,
+    Path(id): Path<i32>,
+    Query(params): Query<HashMap<String, String>>,
+) -> Response {
+    let (limit, offset) = match extract_page(&params) {
+        Ok(page) => page,
+        Err(e) => return (StatusCode::BAD_REQUEST, e.to_string()).into_response(),
+    };
+
+    let store = store.read().await;
+    match store.get_webhook(&id).await {
+        Ok(_) => {}
+        Err(sqlx::Error::RowNotFound) => {
+            return (StatusCode::NOT_FOUND, Err::WebhookNotFound.to_string()).into_response()
+        }
+        Err(e) => return (StatusCode::BAD_REQUEST, e.to_string()).into_response(),
+    }
+    match store
+        .get_webhook_deliveries(Some(id), None, limit, offset)
+        .await
+    {
+        Ok(deliveries) => (StatusCode::OK, Json(deliveries)).into_response(),
+        Err(e) => (StatusCode::BAD_REQUEST, e.to_string()).into_response(),
+    }
+}
+
+/// Fetch the dead letters from the `webhooks/dead-letters` route
+/// # Example query
+/// GET requests to this route return the deliveries of every webhook that ran out of attempts,
+/// newest first
+/// `/webhooks/dead-letters?limit=50&offset=0`
+#[utoipa::path(
+    get,
+    path = "/webhooks/dead-letters",
+    tag = "webhooks",
+    params(
+        ("limit" = Option<i64>, Query, description = "Maximum number of deliveries to return, defaults to 50"),
+        ("offset" = Option<i64>, Query, description = "Number of deliveries to skip"),
+    ),
+    responses(
+        (status = 200, description = "Dead letters found", body = [WebhookDelivery]),
+        (status = 400, description = "Parameters are invalid or database error", body = String),
+        (status = 401, description = "Missing or wrong credentials", body = String),
+        (status = 403, description = "User is not an admin", body = String),
+    ),
+    security(("basic" = []))
+)]
+pub async fn get_dead_letters(
+    State(store): State<Arc<RwLock<Store>>>,
+    _admin: AdminUser,
+    Query(params): Query<HashMap<String, String>>,
+) -> Response {
+    let (limit, offset) = match extract_page(&params) {
+        Ok(page) => page,
+        Err(e) => return (StatusCode::BAD_REQUEST, e.to_string()).into_response(),
+    };
+
+    match store
+        .read()
+        .await
+        .get_webhook_deliveries(None, Some(DeliveryStatus::Dead), limit, offset)
+        .await
+    {
+        Ok(deliveries) => (StatusCode::OK, Json(deliveries)).into_response(),
+        Err(e) => (StatusCode::BAD_REQUEST, e.to_string()).into_response(),
+    }
+}
+
+/// Retry a dead letter from the `webhooks/dead-letters` route
+/// # Example query
+/// POST requests to this route have the id of a dead letter attached, it is sent again straight
+/// away with a fresh set of attempts
+/// `/webhooks/dead-letters/7/retry`
+#[utoipa::path(
+    post,
+    path = "/webhooks/dead-letters/{id}/retry",
+    tag = "webhooks",
+    params(("id" = i64, Path, description = "Delivery id")),
+    responses(
+        (status = 200, description = "Delivery queued again", body = WebhookDelivery),
+        (status = 400, description = "Database error", body = String),
+        (status = 404, description = "Dead letter not found", body = String),
+        (status = 401, description = "Missing or wrong credentials", body = String),
+        (status = 403, description = "User is not an admin", body = String),
+    ),
+    security(("basic" = []))
+)]
+pub async fn retry_dead_letter(
+    State(store): State<Arc<RwLock<Store>>>,
+    _admin: AdminUser,
+    Path(id): Path<i64>,
+) -> Response {
+    match store.write().await.retry_webhook_delivery(&id).await {
+        Ok(delivery) => (StatusCode::OK, Json(delivery)).into_response(),
+        Err(sqlx::Error::RowNotFound) => {
+            (StatusCode::NOT_FOUND, Err::DeliveryNotFound.to_string()).into_response()
+        }
+        Err(e) => (StatusCode::BAD_REQUEST, e.to_string()).into_response(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Bytes;
+    use axum::http::HeaderMap;
+    use tokio::sync::mpsc;
+
+    /// Start a local stand-in for a webhook receiver that answers with `status` and passes every
+    /// request it gets back to the test
+    async fn stand_in(status: StatusCode) -> (String, mpsc::UnboundedReceiver<(HeaderMap, Bytes)>) {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let app = Router::new().route(
+            "/hook",
+            post(move |headers: HeaderMap, body: Bytes| {
+                let sender = sender.clone();
+                async move {
+                    sender.send((headers, body)).unwrap();
+                    status
+                }
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        (url, receiver)
+    }
+
+    fn pending(url: String) -> PendingDelivery {
+        PendingDelivery {
+            id: 7,
+            event_type: "question.created".to_owned(),
+            payload: serde_json::json!({ "type": "question.created", "question_id": 1 }),
+            attempts: 1,
+            url,
+            secret: "secret".to_owned(),
+        }
+    }
+
+    #[tokio::test]
+    async fn deliveries_are_signed() {
+        let (url, mut requests) = stand_in(StatusCode::NO_CONTENT).await;
+        let delivery = pending(url);
+
+        let outcome = deliver(&reqwest::Client::new(), &delivery).await;
+        assert!(outcome.delivered);
+        assert_eq!(outcome.status_code, Some(204));
+
+        // The receiver can recompute the signature from the timestamp and the raw body
+        let (headers, body) = requests.recv().await.unwrap();
+        let timestamp: i64 = headers[WEBHOOK_TIMESTAMP_HEADER]
+            .to_str()
+            .unwrap()
+            .parse()
+            .unwrap();
+        assert_eq!(
+            headers[WEBHOOK_SIGNATURE_HEADER],
+            sign_payload("secret", timestamp, &body)
+        );
+        assert_eq!(headers[WEBHOOK_EVENT_HEADER], "question.created");
+        assert_eq!(headers[WEBHOOK_DELIVERY_HEADER], "7");
+        assert_eq!(
+            serde_json::from_slice::<serde_json::Value>(&body).unwrap(),
+            delivery.payload
+        );
+    }
+
+    #[tokio::test]
+    async fn error_responses_are_not_delivered() {
+        let (url, _requests) = stand_in(StatusCode::INTERNAL_SERVER_ERROR).await;
+
+        let outcome = deliver(&reqwest::Client::new(), &pending(url)).await;
+        assert!(!outcome.delivered);
+        assert_eq!(outcome.status_code, Some(500));
+    }
+
+    #[tokio::test]
+    async fn unreachable_webhooks_are_not_delivered() {
+        let outcome = deliver(
+            &reqwest::Client::new(),
+            &pending("http://127.0.0.1:1/hook".to_owned()),
+        )
+        .await;
+        assert!(!outcome.delivered);
+        assert_eq!(outcome.status_code, None);
+        assert!(outcome.error.is_some());
+    }
+
+    #[tokio::test]
+    async fn redirects_are_not_followed() {
+        let (target, mut requests) = stand_in(StatusCode::NO_CONTENT).await;
+        let app = Router::new().route(
+            "/hook",
+            post(move || {
+                let target = target.clone();
+                async move { (StatusCode::TEMPORARY_REDIRECT, [(header::LOCATION, target)]) }
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let outcome = deliver(&webhook_client(Duration::from_secs(5)), &pending(url)).await;
+        assert!(!outcome.delivered);
+        assert_eq!(outcome.status_code, Some(307));
+        assert!(requests.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn names_resolving_to_private_addresses_are_refused() {
+        let (url, mut requests) = stand_in(StatusCode::NO_CONTENT).await;
+        let url = url.replace("127.0.0.1", "localhost");
+        let client = |allowed: Vec<String>| {
+            reqwest::Client::builder()
+                .dns_resolver(Arc::new(PublicResolver { allowed }))
+                .build()
+                .unwrap()
+        };
+
+        let outcome = deliver(&client(vec![]), &pending(url.clone())).await;
+        assert!(!outcome.delivered);
+        assert_eq!(outcome.status_code, None);
+        assert!(requests.try_recv().is_err());
+
+        // Allowed hosts resolve as usual
+        let outcome = deliver(&client(vec!["localhost".to_owned()]), &pending(url)).await;
+        assert!(outcome.delivered);
+    }
+
+    #[test]
+    fn signatures_depend_on_the_secret_and_timestamp() {
+        let signature = sign_payload("secret", 1, b"{}");
+        assert!(signature.starts_with("sha256="));
+        assert_eq!(signature, sign_payload("secret", 1, b"{}"));
+        assert_ne!(signature, sign_payload("other", 1, b"{}"));
+        assert_ne!(signature, sign_payload("secret", 2, b"{}"));
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_the_limit() {
+        let base = Duration::from_secs(5);
+        assert_eq!(backoff(base, 1), Duration::from_secs(5));
+        assert_eq!(backoff(base, 2), Duration::from_secs(10));
+        assert_eq!(backoff(base, 4), Duration::from_secs(40));
+        assert_eq!(backoff(base, 100), MAX_WEBHOOK_BACKOFF);
+    }
+
+    #[test]
+    fn private_urls_are_rejected() {
+        let webhook = |url: &str| NewWebhook {
+            url: url.to_owned(),
+            secret: None,
+            event_types: None,
+            active: None,
+        };
+
+        for url in [
+            "http://127.0.0.1:8080/hook",
+            "http://localhost/hook",
+            "http://10.1.2.3/hook",
+            "http://192.168.0.10/hook",
+            "http://169.254.169.254/latest/meta-data",
+            "http://[::1]/hook",
+            "http://[fd00::1]/hook",
+            "http://[::ffff:127.0.0.1]/hook",
+        ] {
+            assert!(
+                matches!(webhook(url).validate(), Err(Err::PrivateWebhookUrl(_))),
+                "{} was accepted",
+                url
+            );
+        }
+        assert!(webhook("https://example.com/hook").validate().is_ok());
+        assert!(webhook("https://93.184.216.34/hook").validate().is_ok());
+    }
+}